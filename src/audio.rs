@@ -0,0 +1,247 @@
+use amethyst::{
+    assets::{AssetStorage, Loader, ProgressCounter},
+    audio::{output::Output, AudioSink, OggFormat, Source, SourceHandle, WavFormat},
+    core::{timing::Time, SystemDesc},
+    derive::SystemDesc,
+    ecs::prelude::{Read, ReadExpect, System, SystemData, World, WorldExt, Write},
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+};
+
+use log::{error, info};
+
+use crate::events::GameEvent;
+use crate::fallback::{asset_exists, report_missing, silent_sound};
+use crate::pause::Paused;
+use crate::rhythm::BeatClock;
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::{Gameboard, Score};
+
+// stems of one loop, all the same length, the first always plays and the others join in
+pub const MUSIC_STEMS: [&str; 3] = ["audio/music/base.ogg", "audio/music/drive.ogg", "audio/music/danger.ogg"];
+// a stem joins in from this level (10 lines each) or once the stack is this many rows high
+const STEM_LEVELS: [u32; 3] = [0, 5, 10];
+const STEM_HEIGHTS: [usize; 3] = [0, 10, 15];
+const FADE_SECONDS: f32 = 2.; // from silent to full volume and back
+
+pub struct Sounds {
+    pub single: SourceHandle,
+    pub double: SourceHandle,
+    pub triple: SourceHandle,
+    pub tetris: SourceHandle,
+    pub t_spin: SourceHandle,
+    pub perfect_clear: SourceHandle,
+}
+
+impl Sounds {
+    fn clear_sound(&self, lines: usize, t_spin: bool, perfect_clear: bool) -> &SourceHandle {
+        if perfect_clear {
+            &self.perfect_clear
+        }
+        else if t_spin {
+            &self.t_spin
+        }
+        else {
+            match lines {
+                1 => &self.single,
+                2 => &self.double,
+                3 => &self.triple,
+                _ => &self.tetris,
+            }
+        }
+    }
+}
+
+fn load_sound(loader: &Loader, world: &World, file: &str, progress: &mut ProgressCounter) -> SourceHandle {
+    if !asset_exists(file) {
+        report_missing(world, file);
+        return silent_sound(world, progress);
+    }
+    loader.load(file, WavFormat, progress, &world.read_resource())
+}
+
+pub fn init_sounds(world: &mut World, progress: &mut ProgressCounter) {
+    let sounds = {
+        let loader = world.read_resource::<Loader>();
+        Sounds {
+            single: load_sound(&loader, world, "audio/clear_single.wav", progress),
+            double: load_sound(&loader, world, "audio/clear_double.wav", progress),
+            triple: load_sound(&loader, world, "audio/clear_triple.wav", progress),
+            tetris: load_sound(&loader, world, "audio/clear_tetris.wav", progress),
+            t_spin: load_sound(&loader, world, "audio/clear_t_spin.wav", progress),
+            perfect_clear: load_sound(&loader, world, "audio/perfect_clear.wav", progress),
+        }
+    };
+    world.insert(sounds);
+}
+
+// music is optional, without every stem in place the game plays without it
+pub struct MusicStems(pub Vec<SourceHandle>);
+
+pub fn init_music(world: &mut World, progress: &mut ProgressCounter) {
+    if !MUSIC_STEMS.iter().all(|file| asset_exists(file)) {
+        info!("No music, the stems go in {:?}", MUSIC_STEMS);
+        world.insert(MusicStems(vec![]));
+        return;
+    }
+    let stems = {
+        let loader = world.read_resource::<Loader>();
+        MUSIC_STEMS.iter().map(|file| loader.load(*file, OggFormat, &mut *progress, &world.read_resource())).collect()
+    };
+    world.insert(MusicStems(stems));
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(SfxSystemDesc))]
+pub struct SfxSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl SfxSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for SfxSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, AssetStorage<Source>>,
+        ReadExpect<'s, Sounds>,
+        Option<Read<'s, Output>>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (events, storage, sounds, output, settings): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            if let GameEvent::LinesCleared { lines, t_spin, perfect_clear, .. } = event {
+                let handle = sounds.clear_sound(*lines, *t_spin, *perfect_clear);
+                if let (Some(output), Some(sound)) = (&output, storage.get(handle)) {
+                    output.play_once(sound, settings.sfx_volume());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default, SystemDesc)]
+pub struct AudioControlSystem {
+    mute_held: bool,
+}
+
+impl<'s> System<'s> for AudioControlSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Write<'s, GameSettings>,
+        Option<Write<'s, AudioSink>>,
+    );
+
+    fn run(&mut self, (input, mut settings, mut sink): Self::SystemData) {
+        let mute_down = input.action_is_down("mute").unwrap_or(false);
+        if mute_down && !self.mute_held {
+            settings.muted = !settings.muted;
+            settings.save();
+        }
+        self.mute_held = mute_down;
+
+        if let Some(sink) = sink.as_mut() {
+            sink.set_volume(settings.music_volume());
+        }
+    }
+}
+
+// plays the stems in sync, each on a sink of its own, and fades stems in as the level rises or the
+// stack gets high, the stack is only looked at when a piece locks or lines clear
+#[derive(SystemDesc)]
+#[system_desc(name(MusicSystemDesc))]
+pub struct MusicSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    sinks: Vec<AudioSink>,
+    #[system_desc(skip)]
+    volumes: Vec<f32>, // 0 to 1 per stem, before the music volume
+    #[system_desc(skip)]
+    targets: Vec<f32>,
+}
+
+impl MusicSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self {
+            reader_id,
+            sinks: vec![],
+            volumes: vec![],
+            targets: vec![],
+        }
+    }
+
+    fn update_targets(&mut self, level: u32, stack_height: usize) {
+        self.targets = STEM_LEVELS
+            .iter()
+            .zip(STEM_HEIGHTS.iter())
+            .map(|(&from_level, &from_height)| if level >= from_level || stack_height >= from_height { 1. } else { 0. })
+            .collect();
+    }
+}
+
+impl<'s> System<'s> for MusicSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, AssetStorage<Source>>,
+        Option<Read<'s, MusicStems>>,
+        Option<Read<'s, Output>>,
+        Read<'s, GameSettings>,
+        Read<'s, Score>,
+        Read<'s, GameRules>,
+        Read<'s, Gameboard>,
+        Read<'s, Paused>,
+        Read<'s, Time>,
+        Write<'s, BeatClock>,
+    );
+
+    fn run(&mut self, (events, storage, stems, output, settings, score, rules, gameboard, paused, time, mut clock): Self::SystemData) {
+        let changed = events
+            .read(&mut self.reader_id)
+            .any(|event| matches!(event, GameEvent::PieceLocked { .. } | GameEvent::LinesCleared { .. } | GameEvent::GameOver { .. }));
+        let (stems, output) = match (stems, output) {
+            (Some(stems), Some(output)) if !stems.0.is_empty() => (stems, output),
+            _ => return,
+        };
+        if self.sinks.is_empty() {
+            self.sinks = stems.0.iter().map(|_| AudioSink::new(&output)).collect();
+            self.volumes = vec![0.; stems.0.len()];
+            self.volumes[0] = 1.;
+            self.update_targets(0, 0);
+        }
+        if changed {
+            self.update_targets(rules.level(score.lines), gameboard.stack_height());
+        }
+
+        if paused.0 {
+            self.sinks.iter().for_each(AudioSink::pause);
+            return;
+        }
+        self.sinks.iter().for_each(AudioSink::play);
+
+        // every stem is queued again together once the loop ran out, so they stay in step, and the
+        // beat clock starts over with them
+        if self.sinks.iter().all(AudioSink::empty) {
+            clock.restart();
+            for (sink, handle) in self.sinks.iter().zip(stems.0.iter()) {
+                if let Some(source) = storage.get(handle) {
+                    if let Err(e) = sink.append(source) {
+                        error!("Failed to queue music: {}", e);
+                    }
+                }
+            }
+        }
+
+        let step = time.delta_real_seconds() / FADE_SECONDS;
+        for ((sink, volume), &target) in self.sinks.iter_mut().zip(self.volumes.iter_mut()).zip(self.targets.iter()) {
+            *volume = if *volume < target { (*volume + step).min(target) } else { (*volume - step).max(target) };
+            sink.set_volume(*volume * settings.music_volume());
+        }
+    }
+}
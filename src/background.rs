@@ -0,0 +1,132 @@
+use amethyst::{
+    assets::{AssetStorage, Loader, ProgressCounter},
+    core::math::Vector3,
+    core::timing::Time,
+    core::transform::Transform,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Join, Read, ReadStorage, System, SystemData, World, WorldExt, WriteStorage},
+    renderer::{camera::Projection, sprite::Sprite, Camera, ImageFormat, SpriteRender, SpriteSheet, Texture},
+};
+
+use crate::fallback::{asset_exists, report_missing};
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::Score;
+use crate::theme::Theme;
+
+const BACKGROUND_DIR: &str = "backgrounds";
+const BACKGROUND_Z: f32 = -10.; // behind everything on the board
+const LAYER_Z: f32 = 0.01; // between one layer and the next
+const DRIFT_SPEED: f32 = 0.3; // radians per second
+
+// the theme's backgrounds by level, each layer with how far it drifts
+#[derive(Default)]
+pub struct Backgrounds(pub Vec<Vec<(SpriteRender, f32)>>);
+
+// called by the LoadingState, layers whose image is missing are left out and a level without any
+// shows the plain background
+pub fn load_backgrounds(world: &mut World, progress: &mut ProgressCounter) {
+    let backgrounds = world.read_resource::<Theme>().backgrounds.clone();
+    let backgrounds = backgrounds
+        .iter()
+        .map(|layers| {
+            layers
+                .iter()
+                .filter_map(|layer| {
+                    let file = format!("{}/{}", BACKGROUND_DIR, layer.image);
+                    if !asset_exists(&file) {
+                        report_missing(world, &file);
+                        return None;
+                    }
+                    let loader = world.read_resource::<Loader>();
+                    let texture = loader.load(file, ImageFormat::default(), &mut *progress, &world.read_resource::<AssetStorage<Texture>>());
+                    // one world unit square over the whole image, stretched to the window by the system
+                    let sprite = Sprite::from_pixel_values(1, 1, 1, 1, 0, 0, [0., 0.], false, false);
+                    let sheet = loader.load_from_data(
+                        SpriteSheet { texture, sprites: vec![sprite] },
+                        &mut *progress,
+                        &world.read_resource::<AssetStorage<SpriteSheet>>(),
+                    );
+                    Some((SpriteRender { sprite_sheet: sheet, sprite_number: 0 }, layer.drift))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    world.insert(Backgrounds(backgrounds));
+}
+
+// shows the background of the level behind the board, switching as the level goes up and swaying
+// the layers that drift, there is none with a chroma key or the 3D board
+#[derive(Default, SystemDesc)]
+pub struct BackgroundSystem {
+    shown: Option<usize>, // index of the background the layers belong to
+    layers: Vec<(Entity, f32)>,
+}
+
+impl<'s> System<'s> for BackgroundSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, Score>,
+        Read<'s, GameRules>,
+        Option<Read<'s, Backgrounds>>,
+        Read<'s, GameSettings>,
+        Read<'s, Time>,
+        ReadStorage<'s, Camera>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+    );
+
+    fn run(&mut self, (entities, score, rules, backgrounds, settings, time, cameras, mut transforms, mut sprites): Self::SystemData) {
+        // a game played again starts over with everything deleted
+        if self.layers.iter().any(|&(layer, _)| !entities.is_alive(layer)) {
+            self.layers.clear();
+            self.shown = None;
+        }
+        let backgrounds = match backgrounds {
+            Some(backgrounds) if !backgrounds.0.is_empty() && settings.chroma_key.is_none() => backgrounds,
+            _ => return,
+        };
+        // the part of the world the flat camera shows
+        let view = (&cameras, &transforms).join().find_map(|(camera, transform)| match camera.projection() {
+            Projection::Orthographic(ortho) => {
+                let (x, y) = (transform.translation().x, transform.translation().y);
+                Some((x + (ortho.left() + ortho.right()) / 2., y + (ortho.bottom() + ortho.top()) / 2., ortho.right() - ortho.left(), ortho.top() - ortho.bottom()))
+            },
+            _ => None,
+        });
+        let (x, y, width, height) = match view {
+            Some(view) => view,
+            None => return,
+        };
+
+        let index = rules.level(score.lines) as usize % backgrounds.0.len();
+        if self.shown != Some(index) {
+            for (layer, _) in self.layers.drain(..) {
+                entities.delete(layer).ok();
+            }
+            for (sprite, drift) in backgrounds.0[index].iter() {
+                let layer = entities.build_entity()
+                    .with(Transform::default(), &mut transforms)
+                    .with(sprite.clone(), &mut sprites)
+                    .build();
+                self.layers.push((layer, drift.max(0.)));
+            }
+            self.shown = Some(index);
+        }
+
+        let t = time.absolute_time_seconds() as f32 * DRIFT_SPEED;
+        for (i, &(layer, drift)) in self.layers.iter().enumerate() {
+            let drift = if settings.reduced_motion { 0. } else { drift };
+            // a drifting layer is that much larger, so its edges never come into view
+            let phase = t + i as f32;
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(
+                x + drift * width * phase.sin(),
+                y + drift * height * (phase * 0.7).cos(),
+                BACKGROUND_Z + i as f32 * LAYER_Z,
+            );
+            transform.set_scale(Vector3::new(width * (1. + 2. * drift), height * (1. + 2. * drift), 1.));
+            transforms.insert(layer, transform).ok();
+        }
+    }
+}
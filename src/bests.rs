@@ -0,0 +1,116 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use log::error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::profile;
+
+pub const PERSONAL_BESTS_PATH: &str = "personal_bests.ron";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PersonalBest {
+    pub mode: String, // e.g. "sprint" or "marathon nes", see GameMode::best_name
+    pub points: u32,
+    pub lines: u32,
+    pub time: Option<f32>, // seconds to the mode's goal, None for endless modes and goals never reached
+    pub games: u32,
+}
+
+impl PersonalBest {
+    // a reached goal beats none and a faster one a slower one, without goals more points win
+    pub fn beats(&self, other: &PersonalBest) -> bool {
+        match (self.time, other.time) {
+            (Some(ours), Some(theirs)) => ours < theirs,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => self.points > other.points,
+        }
+    }
+
+    pub fn time_text(&self) -> Option<String> {
+        self.time.map(|time| format!("{}:{:04.1}", (time / 60.) as u32, time % 60.))
+    }
+}
+
+// best game of every mode played on this machine, one for every variant of modes that have them
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PersonalBests {
+    pub bests: Vec<PersonalBest>,
+}
+
+impl PersonalBests {
+    pub fn load() -> Self {
+        let path = match application_root_dir() {
+            Ok(root) => profile::path(&root, PERSONAL_BESTS_PATH),
+            Err(_) => return Self::default(),
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load_no_fallback(&path).unwrap_or_else(|e| {
+            error!("Failed to load personal bests: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn find(&self, mode: &str) -> Option<&PersonalBest> {
+        self.bests.iter().find(|best| best.mode == mode)
+    }
+
+    // bests of the mode and its variants
+    pub fn of_mode<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a PersonalBest> {
+        self.bests
+            .iter()
+            .filter(move |best| best.mode == name || best.mode.starts_with(&format!("{} ", name)))
+    }
+
+    // takes in bests kept elsewhere like ChallengeResults::merge
+    pub fn merge(&mut self, other: PersonalBests) {
+        for theirs in other.bests {
+            match self.bests.iter_mut().find(|best| best.mode == theirs.mode) {
+                Some(ours) => {
+                    let games = ours.games.max(theirs.games);
+                    if theirs.beats(ours) {
+                        *ours = theirs;
+                    }
+                    ours.games = games;
+                },
+                None => self.bests.push(theirs),
+            }
+        }
+    }
+
+    // keeps the game if it beats the best of its mode and counts it, true for a new best
+    pub fn record(game: PersonalBest) -> bool {
+        let mut bests = Self::load();
+        let new = match bests.bests.iter_mut().find(|best| best.mode == game.mode) {
+            Some(best) => {
+                let games = best.games + 1;
+                let new = game.beats(best);
+                if new {
+                    *best = game;
+                }
+                best.games = games;
+                new
+            },
+            None => {
+                bests.bests.push(PersonalBest { games: 1, ..game });
+                true
+            },
+        };
+
+        let result = application_root_dir()
+            .map_err(Into::into)
+            .and_then(|root| bests.write(profile::path(&root, PERSONAL_BESTS_PATH)));
+        if let Err(e) = result {
+            error!("Failed to write personal bests: {}", e);
+        }
+        new
+    }
+}
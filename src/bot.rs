@@ -0,0 +1,267 @@
+use amethyst::{
+    config::Config,
+    core::timing::Time,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Join, Read, System, SystemData, World, Write, WriteStorage},
+    shrev::EventChannel,
+    utils::application_root_dir,
+};
+
+use log::{info, warn};
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::events::GameEvent;
+use crate::rules::{GameRules, DEFAULT_BOARD_WIDTH};
+use crate::settings::GameSettings;
+use crate::solver::{board_lines, drop_piece, QueuedPiece};
+use crate::state::{hard_drop, Gameboard, Piece, BOARD_HEIGHT};
+
+pub const BOT_DIR: &str = "resources/bots";
+
+// the preview as the player sees it, kept up to date by the game state
+#[derive(Default)]
+pub struct UpcomingPieces(pub Vec<QueuedPiece>);
+
+// how the bot plays, the difficulties are files in resources/bots, custom.ron is free to edit
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub search_depth: usize, // pieces looked ahead, the one in play included, limited by the preview
+    pub misdrop_rate: f32, // chance of placing a piece anywhere, 0 to 1
+    pub pieces_per_second: f32, // speed cap
+    pub aggression: f32, // 0 clears whatever it can and keeps the stack low, 1 builds for tetrises
+}
+
+impl BotConfig {
+    // falls back to the default bot if the file can't be read
+    pub fn load_named(name: &str) -> Self {
+        match application_root_dir() {
+            Ok(root) => Self::load(root.join(BOT_DIR).join(format!("{}.ron", name))),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn aggression(&self) -> f32 {
+        self.aggression.max(0.).min(1.)
+    }
+
+    fn clear_reward(&self, lines: usize) -> f32 {
+        let aggression = self.aggression();
+        let tetris_bonus = if lines == 4 { 4. * aggression } else { 0. };
+        lines as f32 * (0.76 - 0.9 * aggression) + tetris_bonus
+    }
+
+    // higher is better, weights after the usual hand tuned line clearing heuristics
+    fn evaluate(&self, lines: &[u16]) -> f32 {
+        let mut heights = [0usize; 10];
+        let mut holes = 0;
+        for x in 0..10 {
+            let column = lines.iter().map(|line| line & 1 << x != 0).collect::<Vec<_>>();
+            heights[x] = column.iter().rposition(|&filled| filled).map_or(0, |y| y + 1);
+            holes += column[..heights[x]].iter().filter(|&&filled| !filled).count();
+        }
+        let aggregate = heights.iter().sum::<usize>() as f32;
+        let bumpiness = heights.windows(2).map(|pair| (pair[0] as i32 - pair[1] as i32).abs()).sum::<i32>() as f32;
+
+        -(0.51 - 0.25 * self.aggression()) * aggregate - 0.36 * holes as f32 - 0.18 * bumpiness
+    }
+
+    // value of the best sequence of placements for the queue, cleared lines included
+    fn best(&self, lines: &[u16], queue: &[QueuedPiece], cancelled: &AtomicBool) -> f32 {
+        match queue.first() {
+            _ if cancelled.load(Ordering::Relaxed) => std::f32::MIN,
+            None => self.evaluate(lines),
+            Some(piece) => placements(lines, piece)
+                .into_iter()
+                .map(|(_, _, next, cleared)| self.clear_reward(cleared) + self.best(&next, &queue[1..], cancelled))
+                .fold(std::f32::MIN, f32::max),
+        }
+    }
+
+    // rotation and left edge to drop the piece at, None if it fits nowhere
+    fn choose(&self, lines: &[u16], current: &QueuedPiece, lookahead: &[QueuedPiece], cancelled: &AtomicBool) -> Option<(usize, usize)> {
+        let candidates = placements(lines, current);
+        let mut rng = rand::thread_rng();
+        let chosen = if !candidates.is_empty() && rng.gen::<f32>() < self.misdrop_rate {
+            candidates.get(rng.gen_range(0, candidates.len()))
+        }
+        else {
+            candidates
+                .iter()
+                .map(|candidate| (candidate, self.clear_reward(candidate.3) + self.best(&candidate.2, lookahead, cancelled)))
+                .fold(None, |best: Option<(&_, f32)>, (candidate, value)| match best {
+                    Some((_, best_value)) if best_value >= value => best,
+                    _ => Some((candidate, value)),
+                })
+                .map(|(candidate, _)| candidate)
+        };
+        chosen.map(|&(rotation, x, _, _)| (rotation, x))
+    }
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            search_depth: 2,
+            misdrop_rate: 0.05,
+            pieces_per_second: 1.,
+            aggression: 0.5,
+        }
+    }
+}
+
+// every straight drop of the piece: rotation, column, the lines afterwards and how many were cleared
+fn placements(lines: &[u16], piece: &QueuedPiece) -> Vec<(usize, usize, Vec<u16>, usize)> {
+    let mut padded = lines.to_vec();
+    padded.resize(BOARD_HEIGHT, 0);
+
+    let mut placements = vec![];
+    for (rotation, cells) in piece.rotations.iter().enumerate() {
+        let width = cells.iter().map(|&(x, _)| x).max().unwrap_or(0) + 1;
+        for x in 0..=(10 - width) {
+            if let Some(next) = drop_piece(&padded, cells, x) {
+                let cleared = BOARD_HEIGHT - next.len();
+                placements.push((rotation, x, next, cleared));
+            }
+        }
+    }
+    placements
+}
+
+struct Plan {
+    piece: Entity,
+    rotation: usize,
+    x: usize, // left edge of the rotation's cells
+    dropped: bool,
+}
+
+// a search for a piece still running on its worker thread
+struct Search {
+    piece: Entity,
+    answer: Receiver<Option<(usize, usize)>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+// plays the game when `bot` in the settings names a bot config, the deeper bots look ahead too far
+// to search on the main thread, so the search of every piece runs on a worker like PcSolver's
+#[derive(Default, SystemDesc)]
+pub struct BotSystem {
+    loaded: String,
+    config: Option<BotConfig>,
+    search: Option<Search>,
+    plan: Option<Plan>,
+    waited: f32,
+}
+
+impl BotSystem {
+    fn start_search(&mut self, config: &BotConfig, entity: Entity, piece: &Piece, gameboard: &Gameboard, upcoming: &[QueuedPiece]) {
+        self.cancel();
+
+        let config = config.clone();
+        let lines = board_lines(gameboard);
+        let current = QueuedPiece::new(piece.block_idx, piece.relative_coords);
+        let lookahead = upcoming[..config.search_depth.saturating_sub(1).min(upcoming.len())].to_vec();
+        let (sender, answer) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            sender.send(config.choose(&lines, &current, &lookahead, &worker_cancelled)).ok();
+        });
+        self.search = Some(Search { piece: entity, answer, cancelled });
+    }
+
+    fn cancel(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // the plan once the search for the piece is done, the piece drops where it is if there is no answer
+    fn poll(&mut self, piece: &Piece) -> Option<Plan> {
+        let search = self.search.as_ref()?;
+        let chosen = match search.answer.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(chosen) => chosen,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        let entity = search.piece;
+        self.search = None;
+        let (rotation, x) = chosen.unwrap_or((piece.idx, piece.coord.0));
+        Some(Plan { piece: entity, rotation, x, dropped: false })
+    }
+}
+
+impl<'s> System<'s> for BotSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Piece>,
+        Read<'s, Gameboard>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        Read<'s, UpcomingPieces>,
+        Read<'s, Time>,
+        Write<'s, EventChannel<GameEvent>>,
+    );
+
+    fn run(&mut self, (entities, mut pieces, gameboard, rules, settings, upcoming, time, mut events): Self::SystemData) {
+        if settings.bot != self.loaded {
+            self.loaded = settings.bot.clone();
+            self.config = if self.loaded.is_empty() { None } else { Some(BotConfig::load_named(&self.loaded)) };
+            self.plan = None;
+            self.cancel();
+            info!("Bot {}", if self.loaded.is_empty() { "off" } else { &self.loaded });
+        }
+        let config = match &self.config {
+            Some(config) => config.clone(),
+            None => return,
+        };
+        // the search works on bitmasks of the standard board
+        if gameboard.width() != DEFAULT_BOARD_WIDTH {
+            warn!("The bot only plays on a board {} columns wide", DEFAULT_BOARD_WIDTH);
+            self.config = None;
+            self.cancel();
+            return;
+        }
+
+        for (entity, piece) in (&entities, &mut pieces).join() {
+            let planned = self.plan.as_ref().map(|plan| plan.piece) == Some(entity);
+            if !planned && self.search.as_ref().map(|search| search.piece) != Some(entity) {
+                self.start_search(&config, entity, piece, &gameboard, &upcoming.0);
+                self.waited = 0.;
+            }
+            if !planned {
+                if let Some(plan) = self.poll(piece) {
+                    self.plan = Some(plan);
+                }
+            }
+
+            // the time spent searching counts towards the wait
+            self.waited += time.delta_seconds();
+            let plan = match self.plan.as_mut() {
+                Some(plan) if plan.piece == entity && !plan.dropped && self.waited >= 1. / config.pieces_per_second.max(0.1) => plan,
+                _ => continue,
+            };
+            plan.dropped = true;
+
+            // the plan counts columns from the left edge of the cells, the piece from its own corner
+            let (prev_idx, prev_x) = (piece.idx, piece.coord.0);
+            let left_edge = piece.relative_coords[plan.rotation].iter().map(|&(x, _)| x).min().unwrap_or(0);
+            piece.idx = plan.rotation;
+            piece.coord.0 = plan.x.saturating_sub(left_edge);
+            if !gameboard.can_place_blocks(&piece.get_abs()) {
+                piece.idx = prev_idx;
+                piece.coord.0 = prev_x;
+            }
+            hard_drop(piece, &gameboard, rules.lock_delay, &mut events);
+        }
+    }
+}
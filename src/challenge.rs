@@ -0,0 +1,285 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use log::{error, info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::profile;
+use crate::rules::Randomizer;
+use crate::stats::PIECE_NAMES;
+
+pub const CHALLENGE_RESULTS_PATH: &str = "challenge_results.ron";
+pub const WEEKLY_CHALLENGES_PATH: &str = "resources/challenges/weekly.ron";
+pub const PRACTICE_DIR: &str = "resources/practice";
+pub const OPENERS_PATH: &str = "resources/practice/openers.ron";
+
+// block_idx of board cells drawn with 'X', filled without belonging to a piece
+pub const GARBAGE_BLOCK: usize = usize::MAX;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// days since the unix epoch in UTC, so players in every time zone share a day's seed
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
+
+pub fn this_week() -> u64 {
+    today() / 7
+}
+
+// a curated game or practice setup, the same pieces on the same board for everyone
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Challenge {
+    pub name: String,
+    pub sequence: String, // piece letters in the order they are dealt, the randomizer takes over after
+    pub board: Vec<String>, // starting stack, top line first, '.' for empty cells, piece letters or 'X' for blocks
+    pub weights: BTreeMap<String, u32>, // odds of the pieces dealt after the sequence by letter, missing ones at 1, a bag if empty
+    pub bags: usize, // sets shuffled together into a bag when there are no weights, 0 and 1 for one
+}
+
+impl Challenge {
+    // a practice setup from resources/practice, by file name without the extension
+    pub fn load_practice(name: &str) -> Option<Self> {
+        let path = application_root_dir().ok()?.join(PRACTICE_DIR).join(format!("{}.ron", name));
+        match Self::load_no_fallback(&path) {
+            Ok(challenge) => Some(challenge),
+            Err(e) => {
+                error!("Failed to load practice setup {:?}: {}", path, e);
+                None
+            },
+        }
+    }
+
+    // written by the board editor, cells are block_idx bottom line first, empty lines on top are left out
+    pub fn from_board(name: &str, sequence: &str, board: &[Vec<Option<usize>>]) -> Self {
+        let height = board.iter().rposition(|line| line.iter().any(Option::is_some)).map_or(0, |y| y + 1);
+        let board = board[..height]
+            .iter()
+            .rev()
+            .map(|line| line.iter().map(|&cell| cell_char(cell)).collect())
+            .collect();
+        Self { name: name.to_string(), sequence: sequence.to_string(), board, ..Self::default() }
+    }
+
+    pub fn save_practice(&self) {
+        let result = application_root_dir()
+            .map_err(Into::into)
+            .and_then(|root| self.write(root.join(PRACTICE_DIR).join(format!("{}.ron", self.name))));
+        match result {
+            Ok(()) => info!("Saved practice board {}", self.name),
+            Err(e) => error!("Failed to save practice board {}: {}", self.name, e),
+        }
+    }
+
+    pub fn pieces(&self) -> Vec<usize> {
+        pieces(&self.sequence)
+    }
+
+    // block_idx of every cell, bottom line first like a saved game
+    pub fn board(&self) -> Vec<Vec<Option<usize>>> {
+        self.board
+            .iter()
+            .rev()
+            .map(|line| line.chars().take(10).map(|cell| if cell == 'X' { Some(GARBAGE_BLOCK) } else { piece_idx(cell) }).collect())
+            .collect()
+    }
+
+    // weighted odds, a bag of several sets or the plain bag
+    pub fn randomizer(&self) -> Randomizer {
+        if !self.weights.is_empty() {
+            Randomizer::Weighted(PIECE_NAMES.iter().map(|&name| self.weights.get(name).copied().unwrap_or(1)).collect())
+        }
+        else if self.bags > 1 {
+            Randomizer::Bags(self.bags)
+        }
+        else {
+            Randomizer::Bag
+        }
+    }
+}
+
+pub fn piece_idx(letter: char) -> Option<usize> {
+    PIECE_NAMES.iter().position(|name| name.starts_with(letter.to_ascii_uppercase()))
+}
+
+fn cell_char(cell: Option<usize>) -> char {
+    match cell {
+        Some(GARBAGE_BLOCK) => 'X',
+        Some(block_idx) => PIECE_NAMES.get(block_idx).and_then(|name| name.chars().next()).unwrap_or('X'),
+        None => '.',
+    }
+}
+
+fn pieces(sequence: &str) -> Vec<usize> {
+    sequence
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .filter_map(piece_idx)
+        .collect()
+}
+
+// a known opener, its bag order and where every piece of it belongs
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Opener {
+    pub name: String,
+    pub sequence: String, // piece letters in the order they are dealt
+    pub targets: Vec<String>, // top line first, every cell marked with the position of its piece in the sequence, 0-9 then a-z
+}
+
+impl Opener {
+    pub fn pieces(&self) -> Vec<usize> {
+        pieces(&self.sequence)
+    }
+
+    // the cells each piece of the sequence should lock in, bottom line is y 0
+    pub fn targets(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut targets = vec![vec![]; self.pieces().len()];
+        for (y, line) in self.targets.iter().rev().enumerate() {
+            for (x, mark) in line.chars().take(10).enumerate() {
+                let target = mark.to_digit(36).and_then(|piece| targets.get_mut(piece as usize));
+                if let Some(target) = target {
+                    target.push((x, y));
+                }
+            }
+        }
+        targets
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Openers {
+    pub openers: Vec<Opener>,
+}
+
+impl Openers {
+    pub fn find(name: &str) -> Option<Opener> {
+        let path = application_root_dir().ok()?.join(OPENERS_PATH);
+        let openers = match Self::load_no_fallback(&path) {
+            Ok(openers) => openers,
+            Err(e) => {
+                error!("Failed to load openers: {}", e);
+                return None;
+            },
+        };
+        let opener = openers.openers.into_iter().find(|opener| opener.name == name);
+        if opener.is_none() {
+            warn!("No opener named {} in {:?}", name, path);
+        }
+        opener
+    }
+}
+
+// the bundled weekly challenges, one is picked per week and they repeat once all were played
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WeeklyChallenges {
+    pub challenges: Vec<Challenge>,
+}
+
+impl WeeklyChallenges {
+    // the week number and this week's challenge
+    pub fn current() -> Option<(u64, Challenge)> {
+        let path = application_root_dir().ok()?.join(WEEKLY_CHALLENGES_PATH);
+        let weekly = match Self::load_no_fallback(&path) {
+            Ok(weekly) => weekly,
+            Err(e) => {
+                error!("Failed to load weekly challenges: {}", e);
+                return None;
+            },
+        };
+        if weekly.challenges.is_empty() {
+            warn!("No weekly challenges in {:?}", path);
+            return None;
+        }
+
+        let week = this_week();
+        let challenge = weekly.challenges[(week % weekly.challenges.len() as u64) as usize].clone();
+        Some((week, challenge))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChallengeResult {
+    pub challenge: String, // e.g. "daily 20376" or "weekly 2910 tetris practice"
+    pub points: u32,
+    pub lines: u32,
+    pub attempts: u32,
+}
+
+// best result of every daily and weekly challenge played on this machine
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChallengeResults {
+    pub results: Vec<ChallengeResult>,
+}
+
+impl ChallengeResults {
+    pub fn load() -> Self {
+        let path = match application_root_dir() {
+            Ok(root) => profile::path(&root, CHALLENGE_RESULTS_PATH),
+            Err(_) => return Self::default(),
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load_no_fallback(&path).unwrap_or_else(|e| {
+            error!("Failed to load challenge results: {}", e);
+            Self::default()
+        })
+    }
+
+    // takes in results kept elsewhere, the higher score of a challenge wins and the attempts are
+    // those of the side that played it more often, as both may count the same games
+    pub fn merge(&mut self, other: ChallengeResults) {
+        for theirs in other.results {
+            match self.results.iter_mut().find(|result| result.challenge == theirs.challenge) {
+                Some(ours) => {
+                    ours.attempts = ours.attempts.max(theirs.attempts);
+                    if theirs.points > ours.points {
+                        ours.points = theirs.points;
+                        ours.lines = theirs.lines;
+                    }
+                },
+                None => self.results.push(theirs),
+            }
+        }
+    }
+
+    // keeps the higher score of the challenge and counts the attempt
+    pub fn record(challenge: &str, points: u32, lines: u32) {
+        let mut results = Self::load();
+        match results.results.iter_mut().find(|result| result.challenge == challenge) {
+            Some(result) => {
+                result.attempts += 1;
+                if points > result.points {
+                    result.points = points;
+                    result.lines = lines;
+                }
+            },
+            None => results.results.push(ChallengeResult { challenge: challenge.to_string(), points, lines, attempts: 1 }),
+        }
+        if let Some(best) = results.results.iter().find(|result| result.challenge == challenge) {
+            info!("Challenge {}: best {} points, {} lines over {} attempts", challenge, best.points, best.lines, best.attempts);
+        }
+
+        let result = application_root_dir()
+            .map_err(Into::into)
+            .and_then(|root| results.write(profile::path(&root, CHALLENGE_RESULTS_PATH)));
+        if let Err(e) = result {
+            error!("Failed to write challenge results: {}", e);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use amethyst::{
+    ecs::prelude::{World, WorldExt},
+    input::{Button, InputHandler, StringBindings, VirtualKeyCode},
+};
+
+use log::warn;
+
+use crate::hud::show_popup;
+use crate::locale::Locale;
+use crate::settings::{Device, GameSettings};
+
+const WARNING_SECONDS: f32 = 8.;
+
+// actions held down at the same time while playing, a direction charging while rotating and dropping
+const HELD_TOGETHER: [&[&str]; 4] = [
+    &["left", "rotate", "hard_drop"],
+    &["right", "rotate", "hard_drop"],
+    &["left", "down", "rotate"],
+    &["right", "down", "rotate"],
+];
+
+// keys that many keyboards without full rollover can't report together, the third one is lost,
+// from what players run into most rather than a complete list
+const KNOWN_CONFLICTS: [&[VirtualKeyCode]; 8] = [
+    &[VirtualKeyCode::Up, VirtualKeyCode::Left, VirtualKeyCode::Space],
+    &[VirtualKeyCode::Up, VirtualKeyCode::Right, VirtualKeyCode::Space],
+    &[VirtualKeyCode::Down, VirtualKeyCode::Left, VirtualKeyCode::Space],
+    &[VirtualKeyCode::Down, VirtualKeyCode::Right, VirtualKeyCode::Space],
+    &[VirtualKeyCode::LShift, VirtualKeyCode::Up, VirtualKeyCode::Left],
+    &[VirtualKeyCode::RShift, VirtualKeyCode::Up, VirtualKeyCode::Left],
+    &[VirtualKeyCode::RShift, VirtualKeyCode::Up, VirtualKeyCode::Right],
+    &[VirtualKeyCode::W, VirtualKeyCode::A, VirtualKeyCode::Space],
+];
+
+// bindings to suggest instead, keep them clear of KNOWN_CONFLICTS
+const PRESETS: [(&str, [(&str, VirtualKeyCode); 5]); 2] = [
+    ("arrows and x/c", [
+        ("left", VirtualKeyCode::Left),
+        ("right", VirtualKeyCode::Right),
+        ("down", VirtualKeyCode::Down),
+        ("rotate", VirtualKeyCode::X),
+        ("hard_drop", VirtualKeyCode::C),
+    ]),
+    ("j/l and f/space", [
+        ("left", VirtualKeyCode::J),
+        ("right", VirtualKeyCode::L),
+        ("down", VirtualKeyCode::K),
+        ("rotate", VirtualKeyCode::F),
+        ("hard_drop", VirtualKeyCode::Space),
+    ]),
+];
+
+// the single keys bound to the action, combinations aren't held while playing
+fn keys(input: &InputHandler<StringBindings>, action: &str) -> Vec<VirtualKeyCode> {
+    input.bindings
+        .action_bindings(action)
+        .filter_map(|combo| match combo {
+            [Button::Key(key)] => Some(*key),
+            _ => None,
+        })
+        .collect()
+}
+
+// the known conflicts among keys held together, with one key of every action in play
+fn conflicts(bound: &dyn Fn(&str) -> Vec<VirtualKeyCode>) -> Vec<&'static [VirtualKeyCode]> {
+    let mut found = vec![];
+    for actions in HELD_TOGETHER.iter() {
+        let held = actions.iter().map(|action| bound(action)).collect::<Vec<_>>();
+        for conflict in KNOWN_CONFLICTS.iter() {
+            let hit = conflict.iter().all(|key| held.iter().any(|keys| keys.contains(key)))
+                && held.iter().all(|keys| keys.iter().any(|key| conflict.contains(key)));
+            if hit && !found.contains(conflict) {
+                found.push(*conflict);
+            }
+        }
+    }
+    found
+}
+
+// called as a game starts, warns about every keyboard player whose bindings run into a known
+// conflict and suggests a preset that doesn't
+pub fn check_bindings(world: &World, players: usize) {
+    let settings = world.read_resource::<GameSettings>();
+    let input = world.read_resource::<InputHandler<StringBindings>>();
+    let locale = world.read_resource::<Locale>();
+    let suggestion = PRESETS
+        .iter()
+        .map(|(name, keys)| {
+            let keys = keys.iter().map(|(action, key)| format!("{} {:?}", action, key)).collect::<Vec<_>>().join(", ");
+            format!("{} ({})", name, keys)
+        })
+        .collect::<Vec<_>>()
+        .join(" or ");
+
+    let mut warnings = vec![];
+    for player in 0..players {
+        if settings.device(player) != Device::Keyboard {
+            continue;
+        }
+        let prefix = if player == 0 { String::new() } else { format!("p{}_", player + 1) };
+        for conflict in conflicts(&|action| keys(&input, &format!("{}{}", prefix, action))) {
+            let keys = conflict.iter().map(|key| format!("{:?}", key)).collect::<Vec<_>>().join(" + ");
+            warnings.push(locale.text("key_conflict", &[&(player + 1), &keys, &suggestion]));
+        }
+    }
+    if warnings.is_empty() {
+        return;
+    }
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    show_popup(world, warnings.join("\n"), Some(WARNING_SECONDS));
+}
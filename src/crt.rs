@@ -0,0 +1,118 @@
+use amethyst::{
+    assets::{AssetStorage, Handle, Loader},
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Read, ReadExpect, System, SystemData, WriteStorage},
+    renderer::rendy::hal::image::{Kind, ViewKind},
+    renderer::rendy::texture::{pixel::Rgba8Srgb, TextureBuilder},
+    renderer::Texture,
+    ui::{Anchor, Stretch, UiImage, UiTransform},
+    window::ScreenDimensions,
+};
+
+use crate::theme::Theme;
+
+const SCANLINE_ALPHA: u8 = 70; // of every other pixel row
+const VIGNETTE_SIZE: u32 = 128; // pixels of the texture stretched over the window
+const VIGNETTE_ALPHA: f32 = 0.7; // at the very edge
+const VIGNETTE_START: f32 = 0.6; // distance from the middle where the edges start to darken, 1 is the edge
+const CORNER_RADIUS: f32 = 0.08; // of the window, corners outside it are black like a curved tube's
+const Z: f32 = 100.; // over the HUD
+
+fn texture(width: u32, height: u32, pixels: Vec<Rgba8Srgb>) -> TextureBuilder<'static> {
+    TextureBuilder::new()
+        .with_kind(Kind::D2(width, height, 1, 1))
+        .with_view_kind(ViewKind::D2)
+        .with_data_width(width)
+        .with_data_height(height)
+        .with_data(pixels)
+}
+
+// one pixel wide, a dark row under every other row of the window
+fn scanlines(height: u32) -> TextureBuilder<'static> {
+    let pixels = (0..height)
+        .map(|y| Rgba8Srgb { repr: [0, 0, 0, if y % 2 == 0 { SCANLINE_ALPHA } else { 0 }] })
+        .collect();
+    texture(1, height, pixels)
+}
+
+// darker towards the edges, the corners rounded off
+fn vignette() -> TextureBuilder<'static> {
+    let half = VIGNETTE_SIZE as f32 / 2.;
+    let pixels = (0..VIGNETTE_SIZE)
+        .flat_map(|y| (0..VIGNETTE_SIZE).map(move |x| {
+            let (dx, dy) = (((x as f32 + 0.5) - half).abs() / half, ((y as f32 + 0.5) - half).abs() / half);
+            // past the rounded corner
+            let inner = 1. - 2. * CORNER_RADIUS;
+            let (cx, cy) = ((dx - inner).max(0.), (dy - inner).max(0.));
+            if (cx * cx + cy * cy).sqrt() > 2. * CORNER_RADIUS {
+                return Rgba8Srgb { repr: [0, 0, 0, 255] };
+            }
+            let distance = (dx * dx + dy * dy).sqrt() / 2f32.sqrt();
+            let darkness = ((distance - VIGNETTE_START) / (1. - VIGNETTE_START)).max(0.).min(1.);
+            Rgba8Srgb { repr: [0, 0, 0, (darkness * darkness * VIGNETTE_ALPHA * 255.) as u8] }
+        }))
+        .collect();
+    texture(VIGNETTE_SIZE, VIGNETTE_SIZE, pixels)
+}
+
+fn overlay(entities: &Entities, transforms: &mut WriteStorage<UiTransform>, id: &str, z: f32) -> Entity {
+    let transform = UiTransform::new(id.to_string(), Anchor::Middle, Anchor::Middle, 0., 0., z, 1., 1.)
+        .with_stretch(Stretch::XY { x_margin: 0., y_margin: 0., keep_aspect_ratio: false });
+    entities.build_entity().with(transform, transforms).build()
+}
+
+// scanlines and a vignette with rounded corners over the whole window for themes with `crt: true`,
+// drawn as UI images on top of the frame rather than by a shader that bends it
+#[derive(Default, SystemDesc)]
+pub struct CrtSystem {
+    overlays: Option<(Entity, Entity)>, // scanlines, vignette
+    scanlines: Option<(u32, Handle<Texture>)>, // for the window height it was made for
+    vignette: Option<Handle<Texture>>,
+}
+
+impl<'s> System<'s> for CrtSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiImage>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Texture>>,
+        ReadExpect<'s, ScreenDimensions>,
+        Option<Read<'s, Theme>>,
+    );
+
+    fn run(&mut self, (entities, mut transforms, mut images, loader, texture_storage, screen, theme): Self::SystemData) {
+        let crt = theme.map_or(false, |theme| theme.crt);
+        // overlays go with everything else when a game is played again
+        let (scanline_overlay, vignette_overlay) = match self.overlays {
+            Some((scanlines, vignette)) if entities.is_alive(scanlines) && entities.is_alive(vignette) => (scanlines, vignette),
+            _ if !crt => return,
+            _ => {
+                let overlays = (overlay(&entities, &mut transforms, "crt_scanlines", Z), overlay(&entities, &mut transforms, "crt_vignette", Z + 1.));
+                self.overlays = Some(overlays);
+                overlays
+            },
+        };
+        if !crt {
+            images.remove(scanline_overlay);
+            images.remove(vignette_overlay);
+            return;
+        }
+
+        let height = screen.height().max(1.) as u32;
+        if self.scanlines.as_ref().map_or(true, |&(made_for, _)| made_for != height) {
+            self.scanlines = Some((height, loader.load_from_data(scanlines(height).into(), (), &texture_storage)));
+        }
+        if self.vignette.is_none() {
+            self.vignette = Some(loader.load_from_data(vignette().into(), (), &texture_storage));
+        }
+        if let (Some((_, scanlines)), Some(vignette)) = (&self.scanlines, &self.vignette) {
+            for &(overlay, texture) in &[(scanline_overlay, scanlines), (vignette_overlay, vignette)] {
+                let image = UiImage::Texture(texture.clone());
+                if images.get(overlay) != Some(&image) {
+                    images.insert(overlay, image).ok();
+                }
+            }
+        }
+    }
+}
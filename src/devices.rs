@@ -0,0 +1,78 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::WorldExt,
+    input::{is_close_requested, InputEvent, VirtualKeyCode},
+    prelude::*,
+};
+
+use log::info;
+
+use crate::hud::{clear_popup, show_popup};
+use crate::locale::Locale;
+use crate::pause::Paused;
+use crate::settings::{Device, GameSettings};
+
+// pushed on top of a game for more than one player before the first piece, each player in turn
+// presses a key to play on the keyboard or a button on the gamepad they want, escape keeps the
+// devices from last time. The choice is saved to `devices` in the settings
+pub struct DeviceSelectState {
+    players: usize,
+    devices: Vec<Device>,
+}
+
+impl DeviceSelectState {
+    pub fn new(players: usize) -> Self {
+        Self { players, devices: vec![] }
+    }
+
+    fn prompt(&self, world: &World) {
+        let text = world.read_resource::<Locale>().text("device_prompt", &[&(self.devices.len() + 1)]);
+        info!("{}", text);
+        show_popup(world, text, None);
+    }
+
+    fn choose(&mut self, world: &World, device: Device) -> SimpleTrans {
+        // a gamepad steers one player only, the keyboard has bindings for everyone
+        if device != Device::Keyboard && self.devices.contains(&device) {
+            return Trans::None;
+        }
+        self.devices.push(device);
+        if self.devices.len() < self.players {
+            self.prompt(world);
+            return Trans::None;
+        }
+        let mut settings = world.write_resource::<GameSettings>();
+        settings.devices = self.devices.clone();
+        info!("{}", world.read_resource::<Locale>().text("devices_chosen", &[&format!("{:?}", settings.devices)]));
+        settings.save();
+        Trans::Pop
+    }
+}
+
+impl SimpleState for DeviceSelectState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(0.);
+        data.world.insert(Paused(true));
+        self.prompt(data.world);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(1.);
+        data.world.insert(Paused(false));
+        clear_popup(data.world);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match &event {
+            StateEvent::Window(event) if is_close_requested(&event) => Trans::Quit,
+            StateEvent::Input(InputEvent::KeyPressed { key_code: VirtualKeyCode::Escape, .. }) => Trans::Pop,
+            StateEvent::Input(InputEvent::KeyPressed { .. }) => self.choose(data.world, Device::Keyboard),
+            StateEvent::Input(InputEvent::ControllerButtonPressed { which, .. }) => self.choose(data.world, Device::Gamepad(*which)),
+            _ => Trans::None,
+        }
+    }
+}
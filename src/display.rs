@@ -0,0 +1,384 @@
+use amethyst::{
+    config::Config,
+    core::frame_limiter::FrameLimiter,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Join, Read, ReadExpect, System, SystemData, World, Write, WriteStorage},
+    input::{InputHandler, StringBindings},
+    renderer::{camera::Projection, Camera},
+    shrev::{EventChannel, ReaderId},
+    utils::application_root_dir,
+    window::{DisplayConfig, MonitorIdent, ScreenDimensions, Window},
+    winit::{dpi::PhysicalSize, Event, WindowEvent},
+};
+
+use log::{error, info};
+
+use std::path::PathBuf;
+
+use crate::pause::Paused;
+use crate::render3d;
+use crate::rules::GameRules;
+use crate::settings::{Framing, GameSettings, DISPLAY_CONFIG_PATH, MAX_ZOOM, MIN_ZOOM};
+use crate::state::BOARD_HEIGHT;
+
+// blocks that always have to be visible next to the board, the preview column
+const PREVIEW_COLUMN_BLOCKS: f32 = 6.;
+// smallest window, the playfield at 16 pixels per block
+const BASE_WINDOW: (u32, u32) = (256, 384);
+
+// playfield in world units
+pub fn playfield_size(block_size: f32, board_width: usize) -> (f32, f32) {
+    ((board_width as f32 + PREVIEW_COLUMN_BLOCKS) * block_size, BOARD_HEIGHT as f32 * block_size)
+}
+
+// projection that fits the whole playfield into a window of the given size times the zoom,
+// keeping blocks square and centering the playfield vertically, horizontally as well unless it is
+// framed against the left edge
+pub fn playfield_projection(width: f32, height: f32, block_size: f32, board_width: usize, zoom: f32, framing: Framing) -> Projection {
+    let (playfield_width, playfield_height) = playfield_size(block_size, board_width);
+    let scale = (width / playfield_width).min(height / playfield_height) * zoom;
+    let half_width = width / scale / 2.;
+    let half_height = height / scale / 2.;
+    let left = match framing {
+        Framing::Centered => -half_width,
+        Framing::Left => -playfield_width / 2., // the camera looks at the playfield's center
+    };
+    Projection::orthographic(left, left + 2. * half_width, -half_height, half_height, 0.1, 2000.)
+}
+
+// the playfield at a fixed size in the bottom left corner, one world unit per pixel times the ui scale,
+// so capture layouts don't have to follow the window
+fn anchored_projection(width: f32, height: f32, block_size: f32, board_width: usize, scale: f32) -> Projection {
+    let (playfield_width, playfield_height) = playfield_size(block_size, board_width);
+    let (left, bottom) = (-playfield_width / 2., -playfield_height / 2.); // the camera looks at the playfield's center
+    Projection::orthographic(left, left + width / scale, bottom, bottom + height / scale, 0.1, 2000.)
+}
+
+// the projection for the window, in perspective for the 3D board, anchored in streamer mode and
+// fitted otherwise
+pub fn camera_projection(screen: &ScreenDimensions, settings: &GameSettings, board_width: usize, perspective: bool) -> Projection {
+    let (width, height) = (screen.width(), screen.height());
+    if perspective {
+        render3d::projection(width, height)
+    }
+    else if settings.streamer_mode {
+        let scale = settings.ui_scale(screen.hidpi_factor()) as f32;
+        anchored_projection(width, height, settings.block_size, board_width, scale)
+    }
+    else {
+        playfield_projection(width, height, settings.block_size, board_width, settings.zoom(), settings.framing)
+    }
+}
+
+// title, size, borders and fullscreen from the display config, the size is only applied windowed
+// and is multiplied by the ui scale
+pub fn apply_display_config(window: &Window, config: &DisplayConfig, ui_scale: f64) {
+    window.set_title(&config.title);
+    window.set_decorations(config.decorations);
+    match &config.fullscreen {
+        Some(monitor) => window.set_fullscreen(Some(monitor.monitor_id(window))),
+        None => {
+            window.set_fullscreen(None);
+            if let Some((width, height)) = config.dimensions {
+                let size = PhysicalSize::new(f64::from(width) * ui_scale, f64::from(height) * ui_scale);
+                window.set_inner_size(size.to_logical(window.get_hidpi_factor()));
+            }
+        },
+    }
+}
+
+// keeps the camera projection in line with the window size, zoom, framing and the streamer mode setting
+#[derive(Default, SystemDesc)]
+pub struct CameraFitSystem {
+    fitted: Option<(f32, f32, f32, usize, bool, f32, Framing)>, // window size, block size, board width, streamer mode, zoom and framing of the last fit
+}
+
+impl<'s> System<'s> for CameraFitSystem {
+    type SystemData = (
+        WriteStorage<'s, Camera>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, GameSettings>,
+        Read<'s, GameRules>,
+    );
+
+    fn run(&mut self, (mut cameras, screen, settings, rules): Self::SystemData) {
+        let fit = (screen.width(), screen.height(), settings.block_size, rules.board_width, settings.streamer_mode, settings.zoom(), settings.framing);
+        if Some(fit) == self.fitted || fit.0 <= 0. || fit.1 <= 0. {
+            return;
+        }
+        self.fitted = Some(fit);
+
+        for camera in (&mut cameras).join() {
+            let perspective = matches!(camera.projection(), Projection::Perspective(_));
+            camera.set_projection(camera_projection(&screen, &settings, rules.board_width, perspective));
+        }
+    }
+}
+
+// flips fullscreen on the "fullscreen" action (F11 or Alt+Enter) and writes it back to the display config
+#[derive(Default, SystemDesc)]
+pub struct FullscreenSystem {
+    toggle_held: bool,
+}
+
+impl<'s> System<'s> for FullscreenSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Window>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (input, window, settings): Self::SystemData) {
+        let pressed = input.action_is_down("fullscreen").unwrap_or(false);
+        let toggled = pressed && !self.toggle_held;
+        self.toggle_held = pressed;
+        if !toggled {
+            return;
+        }
+
+        update_display_config(&window, settings.ui_scale(window.get_hidpi_factor()), |config| {
+            config.fullscreen = match config.fullscreen {
+                Some(_) => None,
+                None => MonitorIdent::from_monitor_id(&*window, window.get_current_monitor()),
+            };
+            info!("Fullscreen {}", if config.fullscreen.is_some() { "on" } else { "off" });
+        });
+    }
+}
+
+const ZOOM_STEP: f32 = 1.25;
+
+// zooms on "zoom_in" and "zoom_out" (+ and -) and switches the framing on "framing" (F9), both are
+// saved to the settings, CameraFitSystem picks them up
+#[derive(Default, SystemDesc)]
+pub struct CameraControlSystem {
+    held: [bool; 3],
+}
+
+impl<'s> System<'s> for CameraControlSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Write<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (input, mut settings): Self::SystemData) {
+        let mut pressed = [false; 3];
+        for (i, action) in ["zoom_in", "zoom_out", "framing"].iter().enumerate() {
+            let down = input.action_is_down(action).unwrap_or(false);
+            pressed[i] = down && !self.held[i];
+            self.held[i] = down;
+        }
+        if !pressed.iter().any(|&pressed| pressed) {
+            return;
+        }
+
+        if pressed[0] {
+            settings.zoom = (settings.zoom() * ZOOM_STEP).min(MAX_ZOOM);
+        }
+        if pressed[1] {
+            settings.zoom = (settings.zoom() / ZOOM_STEP).max(MIN_ZOOM);
+        }
+        if pressed[2] {
+            settings.framing = match settings.framing {
+                Framing::Centered => Framing::Left,
+                Framing::Left => Framing::Centered,
+            };
+        }
+        info!("Zoom {:.2}, {:?} framing", settings.zoom(), settings.framing);
+        settings.save();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolution {
+    Windowed(u32, u32),
+    Fullscreen(MonitorIdent), // at the monitor's own resolution
+}
+
+impl Resolution {
+    fn of(config: &DisplayConfig) -> Option<Self> {
+        match (&config.fullscreen, config.dimensions) {
+            (Some(monitor), _) => Some(Resolution::Fullscreen(monitor.clone())),
+            (None, Some((width, height))) => Some(Resolution::Windowed(width, height)),
+            (None, None) => None,
+        }
+    }
+
+    fn apply_to(&self, config: &mut DisplayConfig) {
+        match self {
+            Resolution::Windowed(width, height) => {
+                config.fullscreen = None;
+                config.dimensions = Some((*width, *height));
+            },
+            Resolution::Fullscreen(monitor) => config.fullscreen = Some(monitor.clone()),
+        }
+    }
+}
+
+// whole multiples of the playfield that fit on the current monitor, then fullscreen on every monitor
+pub fn resolutions(window: &Window, ui_scale: f64) -> Vec<Resolution> {
+    let available = window.get_current_monitor().get_dimensions();
+
+    let mut resolutions: Vec<Resolution> = (1..)
+        .map(|scale| (BASE_WINDOW.0 * scale, BASE_WINDOW.1 * scale))
+        .take_while(|&(width, height)| {
+            f64::from(width) * ui_scale <= available.width && f64::from(height) * ui_scale <= available.height
+        })
+        .map(|(width, height)| Resolution::Windowed(width, height))
+        .collect();
+    if resolutions.is_empty() {
+        resolutions.push(Resolution::Windowed(BASE_WINDOW.0, BASE_WINDOW.1));
+    }
+
+    resolutions.extend(
+        window.get_available_monitors()
+            .filter_map(|monitor| MonitorIdent::from_monitor_id(window, monitor))
+            .map(Resolution::Fullscreen)
+    );
+    resolutions
+}
+
+// steps through the resolutions on the "resolution" action (F10) and writes the choice to the display config
+#[derive(Default, SystemDesc)]
+pub struct ResolutionSystem {
+    cycle_held: bool,
+}
+
+impl<'s> System<'s> for ResolutionSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Window>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (input, window, settings): Self::SystemData) {
+        let pressed = input.action_is_down("resolution").unwrap_or(false);
+        let cycled = pressed && !self.cycle_held;
+        self.cycle_held = pressed;
+        if !cycled {
+            return;
+        }
+
+        let ui_scale = settings.ui_scale(window.get_hidpi_factor());
+        let resolutions = resolutions(&window, ui_scale);
+        update_display_config(&window, ui_scale, |config| {
+            let current = Resolution::of(config);
+            let next = resolutions.iter()
+                .position(|resolution| Some(resolution) == current.as_ref())
+                .map_or(0, |idx| (idx + 1) % resolutions.len());
+            resolutions[next].apply_to(config);
+            info!("Resolution {:?} ({} of {})", resolutions[next], next + 1, resolutions.len());
+        });
+    }
+}
+
+// resizes the window when the ui scale setting or the display's hidpi factor changes
+#[derive(Default, SystemDesc)]
+pub struct UiScaleSystem {
+    applied: Option<f64>,
+}
+
+impl<'s> System<'s> for UiScaleSystem {
+    type SystemData = (
+        ReadExpect<'s, Window>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (window, settings): Self::SystemData) {
+        let ui_scale = settings.ui_scale(window.get_hidpi_factor());
+        if self.applied == Some(ui_scale) {
+            return;
+        }
+        self.applied = Some(ui_scale);
+
+        if let Some(path) = display_config_path() {
+            apply_display_config(&window, &DisplayConfig::load(&path), ui_scale);
+            info!("UI scale {}", ui_scale);
+        }
+    }
+}
+
+fn display_config_path() -> Option<PathBuf> {
+    match application_root_dir() {
+        Ok(root) => Some(root.join(DISPLAY_CONFIG_PATH)),
+        Err(e) => {
+            error!("Failed to locate the display config: {}", e);
+            None
+        },
+    }
+}
+
+// loads the display config, changes it, applies it to the window and saves it again
+fn update_display_config(window: &Window, ui_scale: f64, change: impl FnOnce(&mut DisplayConfig)) {
+    let path = match display_config_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let mut config = DisplayConfig::load(&path);
+    change(&mut config);
+    apply_display_config(window, &config, ui_scale);
+
+    if let Err(e) = config.write(&path) {
+        error!("Failed to save the display config: {}", e);
+    }
+}
+
+// whether the window has the keyboard
+pub struct Focused(pub bool);
+
+impl Default for Focused {
+    fn default() -> Self {
+        Focused(true)
+    }
+}
+
+// the window is in the background or the game paused, effects nobody needs then are skipped
+#[derive(Default)]
+pub struct Idle(pub bool);
+
+// follows the window focus and drops to `idle_fps` while idle, back to `fps_cap` once the window
+// has the focus again and the game goes on
+#[derive(SystemDesc)]
+#[system_desc(name(IdleSystemDesc))]
+pub struct IdleSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<Event>,
+    #[system_desc(skip)]
+    applied: Option<(bool, u32, u32)>, // idle, fps_cap and idle_fps the frame limiter was last set for
+}
+
+impl IdleSystem {
+    pub fn new(reader_id: ReaderId<Event>) -> Self {
+        Self { reader_id, applied: None }
+    }
+}
+
+impl<'s> System<'s> for IdleSystem {
+    type SystemData = (
+        Read<'s, EventChannel<Event>>,
+        Write<'s, Focused>,
+        Write<'s, Idle>,
+        Read<'s, Paused>,
+        Read<'s, GameSettings>,
+        Write<'s, FrameLimiter>,
+    );
+
+    fn run(&mut self, (events, mut focused, mut idle, paused, settings, mut frame_limiter): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            if let Event::WindowEvent { event: WindowEvent::Focused(has_focus), .. } = event {
+                focused.0 = *has_focus;
+            }
+        }
+        idle.0 = !focused.0 || paused.0;
+
+        let wanted = (idle.0, settings.fps_cap, settings.idle_fps);
+        if self.applied == Some(wanted) {
+            return;
+        }
+        self.applied = Some(wanted);
+        let (strategy, max_fps) = if idle.0 { settings.idle_frame_rate_limit() } else { settings.frame_rate_limit() };
+        frame_limiter.set_rate(strategy, max_fps);
+    }
+}
@@ -0,0 +1,209 @@
+use amethyst::{
+    core::math::{Point3, Vector2},
+    core::transform::Transform,
+    ecs::prelude::{Entity, World, WorldExt},
+    input::{get_key, is_close_requested, InputHandler, StringBindings, VirtualKeyCode},
+    prelude::*,
+    renderer::{palette::Srgba, resources::Tint, transparent::Transparent, Camera},
+    window::ScreenDimensions,
+    winit::{ElementState, MouseButton},
+};
+
+use log::info;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::challenge::{Challenge, GARBAGE_BLOCK};
+use crate::effects::{BlockSprites, OUTLINE_SPRITE};
+use crate::mode::PracticeBoardMode;
+use crate::rules::{GameRules, DEFAULT_BOARD_WIDTH};
+use crate::settings::GameSettings;
+use crate::state::{coord_to_transform, init_camera, TetrisGameState, SPAWN_COORD};
+use crate::stats::PIECE_NAMES;
+use crate::theme::Theme;
+
+pub const DEFAULT_BOARD: &str = "custom";
+
+const EDITOR_HEIGHT: usize = SPAWN_COORD.1; // the spawn rows stay empty
+const GRID_ALPHA: f32 = 0.15;
+
+// paints a practice board and its queue, plays it with enter and saves it to resources/practice with F2
+pub struct EditorState {
+    name: String, // file name in resources/practice
+    board: Vec<Vec<Option<usize>>>, // block_idx of every cell, bottom line first
+    queue: String, // piece letters
+    randomizer: (BTreeMap<String, u32>, usize), // weights and bags of the loaded setup, saved as they were
+    cell_entities: Vec<Entity>,
+    camera: Option<Entity>,
+    dirty: bool,
+}
+
+impl EditorState {
+    // continues the practice board of that name if there is one
+    pub fn new(name: &str) -> Self {
+        let setup = Challenge::load_practice(name);
+        let mut board = setup.as_ref().map_or(vec![], |setup| setup.board());
+        board.resize(EDITOR_HEIGHT, vec![]);
+        for line in board.iter_mut() {
+            line.resize(DEFAULT_BOARD_WIDTH, None);
+        }
+        Self {
+            name: name.to_string(),
+            board,
+            randomizer: setup.as_ref().map_or((BTreeMap::new(), 0), |setup| (setup.weights.clone(), setup.bags)),
+            queue: setup.map_or(String::new(), |setup| setup.sequence),
+            cell_entities: vec![],
+            camera: None,
+            dirty: true,
+        }
+    }
+
+    fn setup(&self) -> Challenge {
+        let (weights, bags) = self.randomizer.clone();
+        Challenge { weights, bags, ..Challenge::from_board(&self.name, &self.queue, &self.board) }
+    }
+
+    // the cell under the mouse
+    fn hovered_cell(&self, world: &World) -> Option<(usize, usize)> {
+        let camera = self.camera?;
+        let (mouse_x, mouse_y) = world.read_resource::<InputHandler<StringBindings>>().mouse_position()?;
+        let screen = world.read_resource::<ScreenDimensions>();
+        let cameras = world.read_storage::<Camera>();
+        let transforms = world.read_storage::<Transform>();
+        let point = cameras.get(camera)?.projection().screen_to_world_point(
+            Point3::new(mouse_x, mouse_y, 0.),
+            Vector2::new(screen.width(), screen.height()),
+            transforms.get(camera)?,
+        );
+
+        let block_size = world.read_resource::<GameSettings>().block_size;
+        let (x, y) = (point.x / block_size, point.y / block_size);
+        if x < 0. || y < 0. || x >= DEFAULT_BOARD_WIDTH as f32 || y >= EDITOR_HEIGHT as f32 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    // filled cells in the garbage color, empty ones as a faint grid
+    fn redraw(&mut self, world: &mut World) {
+        for e in self.cell_entities.drain(..) {
+            world.delete_entity(e).ok();
+        }
+
+        let block_size = world.read_resource::<GameSettings>().block_size;
+        for (y, line) in self.board.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                let (sprite, tint) = {
+                    let sprites = &world.read_resource::<BlockSprites>().sprites;
+                    let theme = world.read_resource::<Theme>();
+                    match *cell {
+                        Some(block_idx) => (sprites[theme.block_sprite(block_idx)].clone(), theme.block_tint(block_idx, 1.)),
+                        None => (sprites[OUTLINE_SPRITE].clone(), Tint(Srgba::new(1., 1., 1., GRID_ALPHA))),
+                    }
+                };
+                let entity = world.create_entity()
+                    .with(coord_to_transform((x, y), block_size))
+                    .with(sprite)
+                    .with(tint)
+                    .with(Transparent)
+                    .build();
+                self.cell_entities.push(entity);
+            }
+        }
+        self.dirty = false;
+    }
+}
+
+impl SimpleState for EditorState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        world.insert(GameRules::tetrus());
+        let dimensions = (*world.read_resource::<ScreenDimensions>()).clone();
+        self.camera = Some(init_camera(world, &dimensions, false));
+        info!("Board editor for {}: left click fills a cell, right click empties it", self.name);
+        info!("Type piece letters to queue them, backspace removes the last one, delete clears the board");
+        info!("Enter plays the board, F2 saves it to resources/practice/{}.ron", self.name);
+        info!("Queue: {}", self.queue);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        for e in self.cell_entities.drain(..).chain(self.camera.take()) {
+            data.world.delete_entity(e).ok();
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        let event = match &event {
+            StateEvent::Window(event) => event,
+            _ => return Trans::None,
+        };
+        if is_close_requested(event) {
+            return Trans::Quit;
+        }
+
+        let key = match get_key(event) {
+            Some((key, ElementState::Pressed)) => key,
+            _ => return Trans::None,
+        };
+        match key {
+            VirtualKeyCode::Escape => return Trans::Quit,
+            VirtualKeyCode::Return => {
+                info!("Playing {}", self.name);
+                let mode = PracticeBoardMode::from_setup(self.setup());
+                return Trans::Switch(Box::new(TetrisGameState::new(Arc::new(mode))));
+            },
+            VirtualKeyCode::F2 => self.setup().save_practice(),
+            VirtualKeyCode::Delete => {
+                for line in self.board.iter_mut() {
+                    for cell in line.iter_mut() {
+                        *cell = None;
+                    }
+                }
+                self.dirty = true;
+            },
+            VirtualKeyCode::Back => {
+                self.queue.pop();
+                info!("Queue: {}", self.queue);
+            },
+            _ => {
+                let letter = format!("{:?}", key);
+                if PIECE_NAMES.contains(&letter.as_str()) {
+                    self.queue.push_str(&letter);
+                    info!("Queue: {}", self.queue);
+                }
+            },
+        }
+        Trans::None
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let paint = {
+            let input = data.world.read_resource::<InputHandler<StringBindings>>();
+            if input.mouse_button_is_down(MouseButton::Left) {
+                Some(Some(GARBAGE_BLOCK))
+            }
+            else if input.mouse_button_is_down(MouseButton::Right) {
+                Some(None)
+            }
+            else {
+                None
+            }
+        };
+        if let (Some(cell), Some((x, y))) = (paint, self.hovered_cell(data.world)) {
+            if self.board[y][x] != cell {
+                self.board[y][x] = cell;
+                self.dirty = true;
+            }
+        }
+
+        if self.dirty {
+            self.redraw(data.world);
+        }
+        Trans::None
+    }
+}
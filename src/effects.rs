@@ -0,0 +1,563 @@
+use amethyst::{
+    core::math::Vector3,
+    core::timing::Time,
+    core::transform::Transform,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::{Component, DenseVecStorage},
+    ecs::prelude::{Builder, Entities, Entity, Join, Read, ReadExpect, ReadStorage, System, SystemData, World, WorldExt, WriteStorage},
+    renderer::{palette::Srgba, resources::Tint, transparent::Transparent, SpriteRender},
+    shrev::{EventChannel, ReaderId},
+};
+
+use rand::Rng;
+
+use crate::challenge::GARBAGE_BLOCK;
+use crate::events::GameEvent;
+use crate::display::{playfield_size, Idle};
+use crate::settings::{Ambience, GameSettings};
+use crate::rules::GameRules;
+use crate::state::{coord_to_transform, preview_transform, Block, Gameboard, Piece, PieceBlock, BOARD_HEIGHT};
+use crate::theme::Theme;
+
+pub const OUTLINE_SPRITE: usize = 7;
+pub const WHITE_SPRITE: usize = 8;
+pub const BLOCK_SPRITE: usize = 9; // light grey frame around white, tinted per piece
+pub const PATTERN_SPRITES: usize = 10; // one patterned block per piece in block_idx order
+pub const GARBAGE_SPRITE: usize = PATTERN_SPRITES + 7; // hatched, for cells that belong to no piece
+pub const LAST_SPRITE: usize = GARBAGE_SPRITE;
+
+const TRAIL_LIFETIME: f32 = 0.25;
+const TRAIL_ALPHA: f32 = 0.5;
+const TRAIL_WIDTH: f32 = 0.6; // relative to a block
+const HOLE_ALPHA: f32 = 0.6;
+const HALO_SCALE: f32 = 1.5; // of a block
+const HALO_ALPHA: f32 = 0.35; // at full glow, pulsing around it
+const HALO_PULSE: f32 = 4.; // radians per second
+const HALO_Z: f32 = -0.05;
+const CLEAR_GLOW_LIFETIME: f32 = 0.4;
+const CLEAR_GLOW_HEIGHT: f32 = 1.4; // of a block, the glow spills over the neighbouring lines
+const HOLE_Z: f32 = -0.1; // behind the blocks
+const AMBIENT_Z: f32 = -5.; // in front of the backgrounds
+const AMBIENT_RATE: f32 = 6.; // particles per second
+const AMBIENT_LIFETIME: f32 = 8.;
+const AMBIENT_ALPHA: f32 = 0.5;
+const AMBIENT_SIZE: (f32, f32) = (0.1, 0.25); // of a block
+const SNOW_SPEED: f32 = 1.5; // blocks per second
+const STAR_SPEED: f32 = 0.3;
+const SPAWN_SLIDE_SECONDS: f32 = 0.1;
+const SKY_FADE: f32 = 0.4; // alpha lost per row above the visible rows
+const TAPE_LINES: usize = 3; // per step back
+const TAPE_LIFETIME: f32 = 0.15;
+const TAPE_ALPHA: f32 = 0.5;
+const TAPE_HEIGHT: (f32, f32) = (0.1, 0.6); // of a block
+const TAPE_Z: f32 = 0.6; // over everything on the board
+
+pub struct BlockSprites {
+    pub sprites: Vec<SpriteRender>, // the first frame if the sheet is animated
+    pub frames: usize, // 1 unless the theme's animated sheet was loaded
+}
+
+// fades the Tint of an entity to nothing and deletes it afterwards
+pub struct Fade {
+    pub lifetime: f32,
+    pub remaining: f32,
+    pub start_alpha: f32,
+}
+
+impl Fade {
+    pub fn new(lifetime: f32, start_alpha: f32) -> Self {
+        Self {
+            lifetime,
+            remaining: lifetime,
+            start_alpha,
+        }
+    }
+}
+
+impl Component for Fade {
+    type Storage = DenseVecStorage<Self>;
+}
+
+// bright lines across the board that are gone at once, like a tape being rewound, called on every
+// step back while rewinding
+pub fn tape_lines(world: &mut World, board_width: usize) {
+    let sprite = world.read_resource::<BlockSprites>().sprites[WHITE_SPRITE].clone();
+    let block_size = world.read_resource::<GameSettings>().block_size;
+    let mut rng = rand::thread_rng();
+    for _ in 0..TAPE_LINES {
+        let height = rng.gen_range(TAPE_HEIGHT.0, TAPE_HEIGHT.1);
+        let mut transform = Transform::default();
+        transform.set_scale(Vector3::new(board_width as f32, height, 1.));
+        transform.set_translation_xyz(
+            board_width as f32 * block_size / 2.,
+            rng.gen_range(0., BOARD_HEIGHT as f32) * block_size,
+            TAPE_Z,
+        );
+        world.create_entity()
+            .with(transform)
+            .with(sprite.clone())
+            .with(Tint(Srgba::new(1., 1., 1., TAPE_ALPHA)))
+            .with(Transparent)
+            .with(Fade::new(TAPE_LIFETIME, TAPE_ALPHA))
+            .build();
+    }
+}
+
+// moves with its velocity until its Fade runs out
+pub struct Particle {
+    pub velocity: Vector3<f32>, // world units per second
+}
+
+impl Component for Particle {
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[derive(SystemDesc)]
+pub struct ParticleSystem;
+
+impl<'s> System<'s> for ParticleSystem {
+    type SystemData = (
+        ReadStorage<'s, Particle>,
+        WriteStorage<'s, Transform>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (particles, mut transforms, time): Self::SystemData) {
+        for (particle, transform) in (&particles, &mut transforms).join() {
+            transform.prepend_translation(particle.velocity * time.delta_seconds());
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(HardDropTrailSystemDesc))]
+pub struct HardDropTrailSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl HardDropTrailSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for HardDropTrailSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<GameEvent>>,
+        ReadExpect<'s, BlockSprites>,
+        ReadExpect<'s, Theme>,
+        Read<'s, GameSettings>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Fade>,
+    );
+
+    fn run(&mut self, (entities, events, block_sprites, theme, settings, mut transforms, mut sprites, mut tints, mut transparents, mut fades): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            if settings.reduced_motion {
+                continue;
+            }
+            if let GameEvent::HardDrop { blocks, distance, block_idx } = event {
+                if *distance == 0 {
+                    continue;
+                }
+
+                let mut columns: Vec<(usize, usize)> = vec![]; // (x, highest landed y)
+                for &(x, y) in blocks {
+                    match columns.iter_mut().find(|(cx, _)| *cx == x) {
+                        Some(column) => column.1 = column.1.max(y),
+                        None => columns.push((x, y)),
+                    }
+                }
+
+                for (x, top) in columns {
+                    entities.build_entity()
+                        .with(trail_transform(x, top + 1, *distance, settings.block_size), &mut transforms)
+                        .with(block_sprites.sprites[BLOCK_SPRITE].clone(), &mut sprites) // patterns would smear when stretched
+                        .with(theme.block_tint(*block_idx, TRAIL_ALPHA), &mut tints)
+                        .with(Transparent, &mut transparents)
+                        .with(Fade::new(TRAIL_LIFETIME, TRAIL_ALPHA), &mut fades)
+                        .build();
+                }
+            }
+        }
+    }
+}
+
+// a single sprite stretched over `rows` rows starting at `bottom`
+fn trail_transform(x: usize, bottom: usize, rows: usize, block_size: f32) -> Transform {
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new(TRAIL_WIDTH, rows as f32, 1.));
+    transform.set_translation_xyz(
+        (x as f32 + 0.5) * block_size,
+        (2 * bottom + rows) as f32 * block_size / 2.,
+        -0.2
+    );
+    transform
+}
+
+// fades blocks above the rows the rules show, the first row above is still half there so pieces can
+// be seen peeking in before they come down
+#[derive(SystemDesc)]
+pub struct SkySystem;
+
+impl<'s> System<'s> for SkySystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Block>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+        Read<'s, GameRules>,
+    );
+
+    fn run(&mut self, (entities, blocks, mut tints, mut transparents, rules): Self::SystemData) {
+        if rules.visible_rows >= BOARD_HEIGHT {
+            return;
+        }
+        for (entity, block, tint) in (&entities, &blocks, &mut tints).join() {
+            let above = (block.coord.1 + 1).saturating_sub(rules.visible_rows);
+            let alpha = (1. - above as f32 * SKY_FADE).max(0.);
+            if (tint.0.alpha - alpha).abs() > f32::EPSILON {
+                tint.0.alpha = alpha;
+                transparents.insert(entity, Transparent).ok();
+            }
+        }
+    }
+}
+
+// on the blocks of a piece that just spawned while they slide out of the first preview slot
+#[derive(Default)]
+pub struct SpawnSlide {
+    pub elapsed: f32,
+}
+
+impl Component for SpawnSlide {
+    type Storage = DenseVecStorage<Self>;
+}
+
+// moves and grows the blocks of a new piece from where it was previewed to where it is, the piece
+// itself is in play from the start, blocks that are no longer part of one snap into place
+#[derive(SystemDesc)]
+pub struct SpawnSlideSystem;
+
+impl<'s> System<'s> for SpawnSlideSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Block>,
+        ReadStorage<'s, PieceBlock>,
+        ReadStorage<'s, Piece>,
+        WriteStorage<'s, SpawnSlide>,
+        WriteStorage<'s, Transform>,
+        Read<'s, Time>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (entities, blocks, piece_blocks, pieces, mut slides, mut transforms, time, rules, settings): Self::SystemData) {
+        let mut done = vec![];
+        for (entity, block, slide, transform) in (&entities, &blocks, &mut slides, &mut transforms).join() {
+            slide.elapsed += time.delta_seconds();
+            let target = coord_to_transform(block.coord, settings.block_size);
+            let piece = piece_blocks.get(entity).and_then(|piece_block| pieces.get(piece_block.piece));
+            let piece = match piece {
+                Some(piece) if slide.elapsed < SPAWN_SLIDE_SECONDS => piece,
+                _ => {
+                    transform.set_translation(*target.translation());
+                    transform.set_scale(Vector3::new(1., 1., 1.));
+                    done.push(entity);
+                    continue;
+                },
+            };
+            let cell = (block.coord.0.saturating_sub(piece.coord.0), block.coord.1.saturating_sub(piece.coord.1));
+            let start = preview_transform(0, cell, rules.board_width, settings.block_size);
+            // eases out, quick at first and settling into place
+            let t = 1. - (1. - slide.elapsed / SPAWN_SLIDE_SECONDS).powi(2);
+            let (from, to) = (start.translation(), target.translation());
+            transform.set_translation(from + (to - from) * t);
+            let scale = 0.5 + 0.5 * t;
+            transform.set_scale(Vector3::new(scale, scale, 1.));
+        }
+        for entity in done {
+            slides.remove(entity);
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+pub struct FadeSystem;
+
+impl<'s> System<'s> for FadeSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Fade>,
+        WriteStorage<'s, Tint>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (entities, mut fades, mut tints, time): Self::SystemData) {
+        for (entity, fade, tint) in (&entities, &mut fades, &mut tints).join() {
+            fade.remaining -= time.delta_seconds();
+            if fade.remaining <= 0. {
+                entities.delete(entity).ok();
+            } else {
+                tint.0.alpha = fade.start_alpha * fade.remaining / fade.lifetime;
+            }
+        }
+    }
+}
+
+// outlines the empty cells of rows with garbage in them, so the way through stands out from the
+// black behind the board
+#[derive(Default, SystemDesc)]
+pub struct GarbageHoleSystem {
+    markers: Vec<(Entity, (usize, usize))>,
+}
+
+impl<'s> System<'s> for GarbageHoleSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, Gameboard>,
+        ReadStorage<'s, Block>,
+        Option<Read<'s, BlockSprites>>,
+        Option<Read<'s, Theme>>,
+        Read<'s, GameSettings>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+    );
+
+    fn run(&mut self, (entities, gameboard, blocks, block_sprites, theme, settings, mut transforms, mut sprites, mut tints, mut transparents): Self::SystemData) {
+        let (block_sprites, theme) = match (block_sprites, theme) {
+            (Some(block_sprites), Some(theme)) => (block_sprites, theme),
+            _ => return,
+        };
+        let is_garbage = |cell: &Option<Entity>| cell.and_then(|entity| blocks.get(entity)).map_or(false, |block| block.block_idx == GARBAGE_BLOCK);
+        let holes = gameboard.board
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.iter().any(&is_garbage))
+            .flat_map(|(y, line)| line.iter().enumerate().filter(|(_, cell)| cell.is_none()).map(move |(x, _)| (x, y)))
+            .collect::<Vec<(usize, usize)>>();
+
+        // markers go with everything else when a game is played again
+        self.markers.retain(|&(entity, _)| entities.is_alive(entity));
+        if self.markers.iter().map(|&(_, coord)| coord).eq(holes.iter().cloned()) {
+            return;
+        }
+        for &(entity, _) in &self.markers {
+            entities.delete(entity).ok();
+        }
+        self.markers = holes
+            .into_iter()
+            .map(|coord| {
+                let mut transform = coord_to_transform(coord, settings.block_size);
+                transform.set_translation_z(HOLE_Z);
+                let entity = entities.build_entity()
+                    .with(transform, &mut transforms)
+                    .with(block_sprites.sprites[OUTLINE_SPRITE].clone(), &mut sprites)
+                    .with(theme.block_tint(GARBAGE_BLOCK, HOLE_ALPHA), &mut tints)
+                    .with(Transparent, &mut transparents)
+                    .build();
+                (entity, coord)
+            })
+            .collect();
+    }
+}
+
+// a soft halo behind every block of the falling pieces and a flash that fades over cleared lines,
+// both as bright as `glow`, translucent sprites stand in for a bloom pass
+#[derive(SystemDesc)]
+#[system_desc(name(GlowSystemDesc))]
+pub struct GlowSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    halos: Vec<Entity>,
+}
+
+impl GlowSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id, halos: vec![] }
+    }
+}
+
+impl<'s> System<'s> for GlowSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<GameEvent>>,
+        ReadStorage<'s, Piece>,
+        Option<Read<'s, BlockSprites>>,
+        Option<Read<'s, Theme>>,
+        Read<'s, GameSettings>,
+        Read<'s, GameRules>,
+        Read<'s, Time>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Fade>,
+    );
+
+    fn run(&mut self, (entities, events, pieces, block_sprites, theme, settings, rules, time, mut transforms, mut sprites, mut tints, mut transparents, mut fades): Self::SystemData) {
+        let cleared = events
+            .read(&mut self.reader_id)
+            .flat_map(|event| match event {
+                GameEvent::LinesCleared { rows, .. } => rows.clone(),
+                _ => vec![],
+            })
+            .collect::<Vec<usize>>();
+        let (block_sprites, theme) = match (block_sprites, theme) {
+            (Some(block_sprites), Some(theme)) => (block_sprites, theme),
+            _ => return,
+        };
+        let glow = settings.glow.max(0.).min(1.);
+        let white = &block_sprites.sprites[WHITE_SPRITE];
+
+        if glow > 0. && settings.flashes() {
+            for y in cleared {
+                let mut transform = coord_to_transform((0, y), settings.block_size);
+                transform.set_scale(Vector3::new(rules.board_width as f32, CLEAR_GLOW_HEIGHT, 1.));
+                transform.set_translation_x(rules.board_width as f32 * settings.block_size / 2.);
+                transform.set_translation_z(HALO_Z);
+                entities.build_entity()
+                    .with(transform, &mut transforms)
+                    .with(white.clone(), &mut sprites)
+                    .with(Tint(Srgba::new(1., 1., 1., glow)), &mut tints)
+                    .with(Transparent, &mut transparents)
+                    .with(Fade::new(CLEAR_GLOW_LIFETIME, glow), &mut fades)
+                    .build();
+            }
+        }
+
+        // halos go with everything else when a game is played again
+        self.halos.retain(|&halo| entities.is_alive(halo));
+        let cells = if glow > 0. {
+            pieces.join().flat_map(|piece| piece.get_abs().into_iter().map(move |coord| (coord, piece.block_idx))).collect::<Vec<_>>()
+        }
+        else {
+            vec![]
+        };
+        while self.halos.len() > cells.len() {
+            if let Some(halo) = self.halos.pop() {
+                entities.delete(halo).ok();
+            }
+        }
+        while self.halos.len() < cells.len() {
+            let halo = entities.build_entity()
+                .with(Transform::default(), &mut transforms)
+                .with(white.clone(), &mut sprites)
+                .with(Tint(Srgba::new(1., 1., 1., 0.)), &mut tints)
+                .with(Transparent, &mut transparents)
+                .build();
+            self.halos.push(halo);
+        }
+
+        let pulse = if settings.reduced_motion { 0. } else { (time.absolute_time_seconds() as f32 * HALO_PULSE).sin() };
+        let alpha = glow * HALO_ALPHA * (0.75 + 0.25 * pulse);
+        for (&halo, &(coord, block_idx)) in self.halos.iter().zip(cells.iter()) {
+            let mut transform = coord_to_transform(coord, settings.block_size);
+            transform.set_scale(Vector3::new(HALO_SCALE, HALO_SCALE, 1.));
+            transform.set_translation_z(HALO_Z);
+            transforms.insert(halo, transform).ok();
+            tints.insert(halo, theme.block_tint(block_idx, alpha)).ok();
+        }
+    }
+}
+
+// steps every sprite from an animated block sheet to the frame for the time, all in step
+#[derive(Default, SystemDesc)]
+pub struct BlockAnimationSystem;
+
+impl<'s> System<'s> for BlockAnimationSystem {
+    type SystemData = (
+        Option<Read<'s, BlockSprites>>,
+        Option<Read<'s, Theme>>,
+        Read<'s, GameSettings>,
+        Read<'s, Time>,
+        Read<'s, Idle>,
+        WriteStorage<'s, SpriteRender>,
+    );
+
+    fn run(&mut self, (block_sprites, theme, settings, time, idle, mut sprites): Self::SystemData) {
+        let (block_sprites, fps) = match (block_sprites, theme.as_ref().and_then(|theme| theme.animation.as_ref())) {
+            (Some(block_sprites), Some(animation)) if block_sprites.frames > 1 && !idle.0 => (block_sprites, animation.fps),
+            _ => return,
+        };
+        let sheet = match block_sprites.sprites.first() {
+            Some(sprite) => &sprite.sprite_sheet,
+            None => return,
+        };
+        let per_frame = LAST_SPRITE + 1;
+        // held on the first frame for reduced motion
+        let frame = if settings.reduced_motion { 0 } else { (time.absolute_time_seconds() as f32 * fps.max(0.)) as usize % block_sprites.frames };
+        for sprite in (&mut sprites).join() {
+            if sprite.sprite_sheet != *sheet {
+                continue;
+            }
+            let sprite_number = sprite.sprite_number % per_frame + frame * per_frame;
+            if sprite.sprite_number != sprite_number {
+                sprite.sprite_number = sprite_number;
+            }
+        }
+    }
+}
+
+// stars or snow drifting behind the playfield, as `ambience` picks
+#[derive(Default, SystemDesc)]
+pub struct AmbientParticleSystem {
+    due: f32, // particles owed since the last one spawned
+}
+
+impl<'s> System<'s> for AmbientParticleSystem {
+    type SystemData = (
+        Entities<'s>,
+        Option<Read<'s, BlockSprites>>,
+        Read<'s, GameSettings>,
+        Read<'s, GameRules>,
+        Read<'s, Time>,
+        Read<'s, Idle>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Fade>,
+        WriteStorage<'s, Particle>,
+    );
+
+    fn run(&mut self, (entities, block_sprites, settings, rules, time, idle, mut transforms, mut sprites, mut tints, mut transparents, mut fades, mut particles): Self::SystemData) {
+        let block_sprites = match block_sprites {
+            Some(block_sprites) if settings.ambience != Ambience::Off && !settings.reduced_motion && !idle.0 => block_sprites,
+            _ => return,
+        };
+        self.due += time.delta_seconds() * AMBIENT_RATE;
+        let (width, height) = playfield_size(settings.block_size, rules.board_width);
+        let mut rng = rand::thread_rng();
+        while self.due >= 1. {
+            self.due -= 1.;
+            let size = rng.gen_range(AMBIENT_SIZE.0, AMBIENT_SIZE.1);
+            let x = rng.gen_range(0., width);
+            // snow comes in over the top, stars appear anywhere
+            let (y, velocity) = match settings.ambience {
+                Ambience::Snow => (height, Vector3::new(rng.gen_range(-0.3, 0.3), -SNOW_SPEED, 0.)),
+                _ => (rng.gen_range(0., height), Vector3::new(rng.gen_range(-0.1, 0.1), STAR_SPEED, 0.)),
+            };
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(x, y, AMBIENT_Z);
+            transform.set_scale(Vector3::new(size, size, 1.));
+            entities.build_entity()
+                .with(transform, &mut transforms)
+                .with(block_sprites.sprites[WHITE_SPRITE].clone(), &mut sprites)
+                .with(Tint(Srgba::new(1., 1., 1., AMBIENT_ALPHA)), &mut tints)
+                .with(Transparent, &mut transparents)
+                .with(Fade::new(AMBIENT_LIFETIME, AMBIENT_ALPHA), &mut fades)
+                .with(Particle { velocity: velocity * settings.block_size }, &mut particles)
+                .build();
+        }
+    }
+}
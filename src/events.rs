@@ -0,0 +1,40 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    PieceSpawned {
+        block_idx: usize,
+    },
+    HardDrop {
+        blocks: Vec<(usize, usize)>, // where the piece landed
+        distance: usize, // rows travelled
+        block_idx: usize,
+    },
+    SoftDrop {
+        distance: usize, // rows travelled with down held
+        block_idx: usize,
+    },
+    PieceLocked {
+        blocks: Vec<(usize, usize)>,
+        holes: Vec<(usize, usize)>, // empty cells the piece covered up
+        block_idx: usize,
+        dropped: [u32; 2], // rows soft and hard dropped, at most the rows from the spawn down to here
+    },
+    LinesCleared {
+        lines: usize,
+        rows: Vec<usize>, // where the cleared lines were, bottom first
+        combo: u32, // consecutive placements that cleared lines, this one included
+        t_spin: bool,
+        perfect_clear: bool,
+    },
+    LevelUp {
+        level: u32, // lines cleared / LINES_PER_LEVEL
+    },
+    GameOver {
+        rules: String, // name of the preset
+        seed: Option<u64>,
+        points: u32,
+        lines: u32,
+    },
+}
@@ -0,0 +1,106 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use image::{gif::GifEncoder, Delay, Frame, RgbaImage};
+
+use log::info;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::gamelog::{clear_full_lines, place_lock, read_log, starting_board};
+use crate::rules::{GameRules, DEFAULT_BOARD_WIDTH};
+use crate::profile;
+use crate::screenshot::render_board;
+use crate::settings::{GameSettings, SETTINGS_PATH};
+use crate::theme::Theme;
+
+pub const REPLAY_DIR: &str = "replays";
+
+const FRAME_MS: u32 = 250; // every placement and clear is shown this long
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Gif,
+    Png, // one numbered image per frame
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gif" => Some(ExportFormat::Gif),
+            "png" => Some(ExportFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+// replays the locks of a gameplay log without a window, one frame with every new piece and one after
+// every clear, starting from the board of the preset the log names, practice boards start out empty
+fn render_frames(locks: &[(Vec<(usize, usize)>, usize)], preset: Option<GameRules>, theme: &Theme) -> Vec<RgbaImage> {
+    let widest = locks.iter().flat_map(|(blocks, _)| blocks.iter().map(|&(x, _)| x + 1)).max().unwrap_or(0);
+    let rules = preset.unwrap_or_else(|| GameRules { board_width: widest.max(DEFAULT_BOARD_WIDTH), ..GameRules::tetrus() });
+    let board_width = rules.board_width;
+    let mut board = starting_board(&rules);
+    let draw = |board: &Vec<Vec<Option<usize>>>| {
+        let cells = board
+            .iter()
+            .enumerate()
+            .flat_map(|(y, line)| line.iter().enumerate().filter_map(move |(x, cell)| cell.map(|block_idx| ((x, y), block_idx))))
+            .map(|(coord, block_idx)| (coord, theme.block_tint(block_idx, 1.).0))
+            .collect::<Vec<_>>();
+        render_board(&cells, board_width)
+    };
+
+    let mut frames = vec![draw(&board)];
+    for (blocks, block_idx) in locks {
+        place_lock(&mut board, blocks, *block_idx);
+        frames.push(draw(&board));
+        if clear_full_lines(&mut board) > 0 {
+            frames.push(draw(&board));
+        }
+    }
+    frames
+}
+
+// writes replays/<log name>.gif or replays/<log name>/<frame>.png, finished games only if their seal holds
+pub fn export_replay(log: &Path, format: ExportFormat) -> io::Result<PathBuf> {
+    let root = application_root_dir()?;
+    let settings = GameSettings::load(profile::path(&root, SETTINGS_PATH));
+    let theme = Theme::load_named(&settings.theme);
+    let game = read_log(log)?;
+    if game.result.is_some() && !game.sealed {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the log was changed after the game"));
+    }
+    let preset = game.result.and_then(|result| {
+        GameRules::presets().iter().find(|(name, _)| *name == result.rules).map(|(_, preset)| preset())
+    });
+    let frames = render_frames(&game.locks, preset, &theme);
+
+    let name = log.file_stem().map_or("replay".into(), |stem| stem.to_string_lossy().to_string());
+    let dir = root.join(REPLAY_DIR);
+    fs::create_dir_all(&dir)?;
+    let to_io = |e: image::ImageError| io::Error::new(io::ErrorKind::Other, e);
+    let path = match format {
+        ExportFormat::Gif => {
+            let path = dir.join(format!("{}.gif", name));
+            let mut encoder = GifEncoder::new(File::create(&path)?);
+            let delay = Delay::from_numer_denom_ms(FRAME_MS, 1);
+            encoder.encode_frames(frames.into_iter().map(|image| Frame::from_parts(image, 0, 0, delay))).map_err(to_io)?;
+            path
+        },
+        ExportFormat::Png => {
+            let path = dir.join(&name);
+            fs::create_dir_all(&path)?;
+            for (i, image) in frames.iter().enumerate() {
+                image.save(path.join(format!("{:04}.png", i))).map_err(to_io)?;
+            }
+            path
+        },
+    };
+    info!("Exported {:?} to {:?}", log, path);
+    Ok(path)
+}
@@ -0,0 +1,155 @@
+use amethyst::{
+    assets::{AssetStorage, Format, Handle, Loader, ProgressCounter},
+    audio::{SourceHandle, WavFormat},
+    ecs::prelude::{World, WorldExt},
+    renderer::rendy::hal::image::{Kind, ViewKind},
+    renderer::rendy::texture::{pixel::Rgba8Srgb, TextureBuilder},
+    renderer::{sprite::Sprite, SpriteSheet, Texture},
+    utils::application_root_dir,
+};
+
+use log::warn;
+
+use crate::effects::{BLOCK_SPRITE, GARBAGE_SPRITE, LAST_SPRITE, OUTLINE_SPRITE, PATTERN_SPRITES};
+
+const BLOCK_SIZE: u32 = 16;
+const OUTLINE_WIDTH: u32 = 2;
+
+// flat stand-ins for the block art in block_idx order: I, L, rev_L, square, T, S, Z
+const BLOCK_COLORS: [[u8; 4]; 7] = [
+    [0, 240, 240, 255],
+    [240, 160, 0, 255],
+    [0, 0, 240, 255],
+    [240, 240, 0, 255],
+    [160, 0, 240, 255],
+    [0, 240, 0, 255],
+    [240, 0, 0, 255],
+];
+
+// asset paths that were missing and replaced, shown to the player once loading is done
+#[derive(Default)]
+pub struct MissingAssets {
+    pub files: Vec<String>,
+}
+
+pub fn asset_exists(file: &str) -> bool {
+    application_root_dir()
+        .map(|root| root.join("resources").join(file).is_file())
+        .unwrap_or(false)
+}
+
+pub fn report_missing(world: &World, file: &str) {
+    warn!("Missing asset {}, using a generated fallback", file);
+    world.write_resource::<MissingAssets>().files.push(file.to_string());
+}
+
+// the same patterns as the pattern sprites in sprites/blocks.png
+fn patterned(block_idx: usize, x: u32, y: u32) -> bool {
+    match block_idx {
+        0 => y % 4 == 1,
+        1 => x % 4 == 1 && y % 4 == 1,
+        2 => (x / 2 + y / 2) % 2 == 0,
+        3 => ((x == 5 || x == 10) && (5..=10).contains(&y)) || ((y == 5 || y == 10) && (5..=10).contains(&x)),
+        4 => x == 7 || x == 8 || y == 7 || y == 8,
+        5 => (x + y) % 4 == 0,
+        _ => (x + 16 - y) % 4 == 0,
+    }
+}
+
+fn pixel(x: u32, y: u32, sprite: usize) -> Rgba8Srgb {
+    let edge = x < OUTLINE_WIDTH || y < OUTLINE_WIDTH
+        || x >= BLOCK_SIZE - OUTLINE_WIDTH || y >= BLOCK_SIZE - OUTLINE_WIDTH;
+    let repr = if sprite < BLOCK_COLORS.len() {
+        BLOCK_COLORS[sprite]
+    }
+    else if sprite == OUTLINE_SPRITE {
+        if edge { [255, 255, 255, 255] } else { [0, 0, 0, 0] }
+    }
+    else if sprite == BLOCK_SPRITE {
+        if edge { [153, 153, 153, 255] } else { [255, 255, 255, 255] }
+    }
+    else if sprite == GARBAGE_SPRITE {
+        if edge {
+            [89, 89, 89, 255]
+        }
+        else if (x + y) % 5 == 0 {
+            [166, 166, 166, 255]
+        }
+        else {
+            [230, 230, 230, 255]
+        }
+    }
+    else if sprite >= PATTERN_SPRITES {
+        if edge {
+            [89, 89, 89, 255]
+        }
+        else if patterned(sprite - PATTERN_SPRITES, x, y) {
+            [153, 153, 153, 255]
+        }
+        else {
+            [255, 255, 255, 255]
+        }
+    }
+    else {
+        [255, 255, 255, 255]
+    };
+    Rgba8Srgb { repr }
+}
+
+// one row of flat colored blocks laid out like sprites/blocks.png
+pub fn generated_block_sheet(world: &World, progress: &mut ProgressCounter) -> Handle<SpriteSheet> {
+    let sprite_count = LAST_SPRITE as u32 + 1;
+    let width = BLOCK_SIZE * sprite_count;
+    let pixels = (0..BLOCK_SIZE)
+        .flat_map(|y| (0..width).map(move |x| pixel(x % BLOCK_SIZE, y, (x / BLOCK_SIZE) as usize)))
+        .collect::<Vec<Rgba8Srgb>>();
+
+    let loader = world.read_resource::<Loader>();
+    let texture = TextureBuilder::new()
+        .with_kind(Kind::D2(width, BLOCK_SIZE, 1, 1))
+        .with_view_kind(ViewKind::D2)
+        .with_data_width(width)
+        .with_data_height(BLOCK_SIZE)
+        .with_data(pixels);
+    let texture_handle = loader.load_from_data(
+        texture.into(),
+        &mut *progress,
+        &world.read_resource::<AssetStorage<Texture>>(),
+    );
+
+    let sprites = (0..sprite_count)
+        .map(|i| {
+            Sprite::from_pixel_values(width, BLOCK_SIZE, BLOCK_SIZE, BLOCK_SIZE, i * BLOCK_SIZE, 0, [0., 0.], false, false)
+        })
+        .collect();
+    loader.load_from_data(
+        SpriteSheet { texture: texture_handle, sprites },
+        progress,
+        &world.read_resource::<AssetStorage<SpriteSheet>>(),
+    )
+}
+
+// a tenth of a second of 8 bit mono silence at 8kHz
+fn silent_wav() -> Vec<u8> {
+    let samples = 800u32;
+    let mut wav = vec![];
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // pcm
+    wav.extend_from_slice(&1u16.to_le_bytes()); // channels
+    wav.extend_from_slice(&8000u32.to_le_bytes()); // sample rate
+    wav.extend_from_slice(&8000u32.to_le_bytes()); // byte rate
+    wav.extend_from_slice(&1u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&samples.to_le_bytes());
+    wav.extend(std::iter::repeat(128u8).take(samples as usize)); // unsigned 8 bit, 128 is zero
+    wav
+}
+
+pub fn silent_sound(world: &World, progress: &mut ProgressCounter) -> SourceHandle {
+    let data = WavFormat.import_simple(silent_wav()).expect("WavFormat accepts any bytes");
+    world.read_resource::<Loader>().load_from_data(data, progress, &world.read_resource())
+}
@@ -0,0 +1,141 @@
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Join, Read, ReadStorage, System, SystemData, World},
+    input::{InputHandler, StringBindings},
+};
+
+use log::info;
+
+use serde::Serialize;
+
+use std::collections::VecDeque;
+
+use crate::rules::{GameRules, DEFAULT_BOARD_WIDTH};
+use crate::state::{spawn_coord, Gameboard, Piece, SPAWN_COORD};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Input {
+    Left,
+    Right,
+    DasLeft, // held until the piece stops at the wall
+    DasRight,
+    Rotate,
+}
+
+impl Input {
+    fn symbol(self) -> &'static str {
+        match self {
+            Input::Left => "<",
+            Input::Right => ">",
+            Input::DasLeft => "DAS<",
+            Input::DasRight => "DAS>",
+            Input::Rotate => "^",
+        }
+    }
+}
+
+// placements with more inputs than needed and all placements so far
+#[derive(Clone, Default, Serialize)]
+pub struct FinesseScore {
+    pub pieces: u32,
+    pub faults: u32,
+}
+
+// columns the piece can take in a rotation on an empty board
+fn columns(rotation: &[(usize, usize)]) -> std::ops::RangeInclusive<usize> {
+    let right_edge = rotation.iter().map(|&(x, _)| x).max().unwrap_or(0);
+    0..=(9 - right_edge)
+}
+
+// the shortest inputs from a spawn in column spawn_x to every rotation and column on an empty board,
+// indexed [rotation][x]
+fn shortest_inputs(rotations: &[&[(usize, usize)]], spawn_x: usize) -> Vec<Vec<Option<Vec<Input>>>> {
+    let mut paths = vec![vec![None; 10]; rotations.len()];
+    let spawn = (0, spawn_x);
+    paths[spawn.0][spawn.1] = Some(vec![]);
+
+    let mut open = VecDeque::new();
+    open.push_back(spawn);
+    while let Some((rotation, x)) = open.pop_front() {
+        let path = paths[rotation][x].clone().unwrap_or_default();
+        let fits = columns(&rotations[rotation]);
+        let mut moves = vec![(Input::DasLeft, rotation, *fits.start()), (Input::DasRight, rotation, *fits.end())];
+        if x > *fits.start() {
+            moves.push((Input::Left, rotation, x - 1));
+        }
+        if x < *fits.end() {
+            moves.push((Input::Right, rotation, x + 1));
+        }
+        // rotating goes backwards through the table like Piece::next
+        let rotated = (rotation + rotations.len() - 1) % rotations.len();
+        if columns(&rotations[rotated]).contains(&x) {
+            moves.push((Input::Rotate, rotated, x));
+        }
+
+        for (input, next_rotation, next_x) in moves {
+            if paths[next_rotation][next_x].is_none() {
+                let mut next_path = path.clone();
+                next_path.push(input);
+                paths[next_rotation][next_x] = Some(next_path);
+                open.push_back((next_rotation, next_x));
+            }
+        }
+    }
+    paths
+}
+
+// fewest left, right and rotate presses that place the piece where it is, None for tucks, spins
+// and anything else a drop from above the stack can't reach, those aren't judged
+pub fn optimal_inputs(piece: &Piece, spawn_x: usize, gameboard: &Gameboard) -> Option<u32> {
+    let dropped = (piece.coord.1..=SPAWN_COORD.1).all(|y| gameboard.can_place_blocks(&Piece { coord: (piece.coord.0, y), ..*piece }.get_abs()));
+    if !dropped {
+        return None;
+    }
+    shortest_inputs(piece.relative_coords, spawn_x)
+        .get(piece.idx)
+        .and_then(|row| row.get(piece.coord.0))
+        .and_then(|path| path.as_ref())
+        .map(|path| path.len() as u32)
+}
+
+// logs the finesse of every placement of the piece in play on the "finesse" action
+#[derive(Default, SystemDesc)]
+pub struct FinesseHelpSystem {
+    help_held: bool,
+}
+
+impl<'s> System<'s> for FinesseHelpSystem {
+    type SystemData = (
+        ReadStorage<'s, Piece>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, GameRules>,
+    );
+
+    fn run(&mut self, (pieces, input, rules): Self::SystemData) {
+        let pressed = input.action_is_down("finesse").unwrap_or(false);
+        let shown = pressed && !self.help_held;
+        self.help_held = pressed;
+        // like the trainer, only the tetrominoes on the standard board
+        if !shown || rules.board_width != DEFAULT_BOARD_WIDTH || rules.piece_set.is_some() {
+            return;
+        }
+
+        for piece in pieces.join() {
+            info!("Finesse for {}, by rotation and column:", rules.piece_name(piece.block_idx));
+            let spawn = spawn_coord(rules.board_width, piece.player, rules.players);
+            for (rotation, row) in shortest_inputs(piece.relative_coords, spawn.0).iter().enumerate() {
+                let placements = row
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(x, path)| path.as_ref().map(|path| (x, path)))
+                    .map(|(x, path)| {
+                        let inputs = path.iter().map(|input| input.symbol()).collect::<Vec<_>>();
+                        format!("{}: {}", x + 1, if inputs.is_empty() { "-".to_string() } else { inputs.join(" ") })
+                    })
+                    .collect::<Vec<_>>();
+                info!("  {}  {}", rotation, placements.join(" | "));
+            }
+        }
+    }
+}
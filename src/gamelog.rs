@@ -0,0 +1,498 @@
+use amethyst::{
+    core::timing::Time,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+    utils::application_root_dir,
+};
+
+use log::error;
+
+use serde::Serialize;
+
+use serde_json::Value;
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::events::GameEvent;
+use crate::profile;
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::{dealt_pieces, BOARD_HEIGHT, SPAWN_COORD};
+use crate::stats::TRACKED_ACTIONS;
+
+pub const LOG_DIR: &str = "logs";
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    frame: u64,
+    #[serde(flatten)]
+    entry: LogEntry<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum LogEntry<'a> {
+    Event { event: &'a GameEvent },
+    Input { action: &'a str, pressed: bool },
+    Seal { hash: String }, // of every line before it, written after the game is over
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// FNV-1a, the same on every platform and compiler version unlike the std hasher
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// the log of the game in play, reset by every new game so it gets a file of its own
+#[derive(Default)]
+pub struct GameLogFile(pub Option<PathBuf>);
+
+fn open_log() -> io::Result<(BufWriter<File>, PathBuf)> {
+    let dir = profile::path(&application_root_dir()?, LOG_DIR);
+    fs::create_dir_all(&dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let path = dir.join(format!("game-{}.jsonl", timestamp));
+    Ok((BufWriter::new(File::create(&path)?), path))
+}
+
+// writes one JSON line per game event and input change while `gameplay_log` is enabled
+#[derive(SystemDesc)]
+#[system_desc(name(GameLogSystemDesc))]
+pub struct GameLogSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    held: [bool; 5],
+    #[system_desc(skip)]
+    writer: Option<BufWriter<File>>,
+    #[system_desc(skip)]
+    hash: u64,
+    #[system_desc(skip)]
+    failed: bool,
+}
+
+impl GameLogSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self {
+            reader_id,
+            held: [false; 5],
+            writer: None,
+            hash: FNV_OFFSET,
+            failed: false,
+        }
+    }
+
+    fn write_line(&mut self, line: &LogLine<'_>) {
+        let result = match self.writer.as_mut() {
+            Some(writer) => serde_json::to_string(line)
+                .map_err(io::Error::from)
+                .map(|line| line + "\n")
+                .and_then(|line| {
+                    self.hash = fnv1a(self.hash, line.as_bytes());
+                    writer.write_all(line.as_bytes())
+                }),
+            None => return,
+        };
+        if let Err(e) = result {
+            error!("Failed to write gameplay log, disabling it: {}", e);
+            self.writer = None;
+            self.failed = true;
+        }
+    }
+}
+
+impl<'s> System<'s> for GameLogSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, GameSettings>,
+        Read<'s, Time>,
+        Write<'s, GameLogFile>,
+    );
+
+    fn run(&mut self, (events, input, settings, time, mut log_file): Self::SystemData) {
+        if !settings.gameplay_log || self.failed {
+            // keep the reader caught up so enabling the log doesn't replay old events
+            events.read(&mut self.reader_id).for_each(drop);
+            return;
+        }
+
+        // a sealed log stays closed until the next game resets the file
+        if log_file.0.is_none() {
+            match open_log() {
+                Ok((writer, path)) => {
+                    self.writer = Some(writer);
+                    self.hash = FNV_OFFSET;
+                    log_file.0 = Some(path);
+                },
+                Err(e) => {
+                    error!("Failed to open gameplay log: {}", e);
+                    self.failed = true;
+                    return;
+                },
+            }
+        }
+        if self.writer.is_none() {
+            events.read(&mut self.reader_id).for_each(drop);
+            return;
+        }
+
+        let frame = time.frame_number();
+        // collected first, writing a line needs the whole system
+        let mut changed = vec![];
+        for (held, &action) in self.held.iter_mut().zip(TRACKED_ACTIONS.iter()) {
+            let down = settings.player_action_is_down(&input, 0, action);
+            if down != *held {
+                *held = down;
+                changed.push((action, down));
+            }
+        }
+        for (action, pressed) in changed {
+            self.write_line(&LogLine { frame, entry: LogEntry::Input { action, pressed } });
+        }
+
+        let new_events = events.read(&mut self.reader_id).cloned().collect::<Vec<GameEvent>>();
+        for event in &new_events {
+            self.write_line(&LogLine { frame, entry: LogEntry::Event { event } });
+            if let GameEvent::GameOver { .. } = event {
+                let hash = format!("{:016x}", self.hash);
+                self.write_line(&LogLine { frame, entry: LogEntry::Seal { hash } });
+                if let Some(mut writer) = self.writer.take() {
+                    writer.flush().ok();
+                }
+                return;
+            }
+        }
+
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().ok();
+        }
+    }
+}
+
+// what a game claims in its GameOver line
+#[derive(Clone, Debug)]
+pub struct LoggedResult {
+    pub rules: String,
+    pub seed: Option<u64>,
+    pub points: u32,
+    pub lines: u32,
+}
+
+// a gameplay log read back: the cells and block_idx of every locked piece in order, the rows each
+// of them scored as drops, the hard drops, the result if the game was finished and whether the seal
+// matches the lines before it
+pub struct LoggedGame {
+    pub locks: Vec<(Vec<(usize, usize)>, usize)>,
+    pub dropped: Vec<[u64; 2]>, // soft, hard, by lock
+    pub hard_drops: Vec<(usize, Vec<(usize, usize)>, u64)>, // locks before it, where it landed, rows
+    pub result: Option<LoggedResult>,
+    pub sealed: bool,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn cells(event: &Value) -> Option<Vec<(usize, usize)>> {
+    event["blocks"]
+        .as_array()
+        .map(|blocks| blocks.iter().filter_map(|block| Some((block[0].as_u64()? as usize, block[1].as_u64()? as usize))).collect())
+}
+
+pub fn read_log(path: &Path) -> io::Result<LoggedGame> {
+    let mut game = LoggedGame { locks: vec![], dropped: vec![], hard_drops: vec![], result: None, sealed: false };
+    let mut hash = FNV_OFFSET;
+    for (number, text) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let text = text?;
+        let line: Value = serde_json::from_str(&text).map_err(|e| invalid(format!("line {}: {}", number + 1, e)))?;
+        if line["kind"] == "seal" {
+            game.sealed = line["hash"].as_str() == Some(&format!("{:016x}", hash));
+            break;
+        }
+        hash = fnv1a(hash, text.as_bytes());
+        hash = fnv1a(hash, b"\n");
+
+        let event = &line["event"];
+        if event["type"] == "PieceLocked" {
+            let blocks = cells(event).ok_or_else(|| invalid(format!("line {}: a lock without blocks", number + 1)))?;
+            game.locks.push((blocks, event["block_idx"].as_u64().unwrap_or(0) as usize));
+            let dropped = &event["dropped"];
+            game.dropped.push([dropped[0].as_u64().unwrap_or(0), dropped[1].as_u64().unwrap_or(0)]);
+        }
+        else if event["type"] == "HardDrop" {
+            let blocks = cells(event).ok_or_else(|| invalid(format!("line {}: a hard drop without blocks", number + 1)))?;
+            game.hard_drops.push((game.locks.len(), blocks, event["distance"].as_u64().unwrap_or(0)));
+        }
+        else if event["type"] == "GameOver" {
+            game.result = Some(LoggedResult {
+                rules: event["rules"].as_str().unwrap_or_default().to_string(),
+                seed: event["seed"].as_u64(),
+                points: event["points"].as_u64().unwrap_or(0) as u32,
+                lines: event["lines"].as_u64().unwrap_or(0) as u32,
+            });
+        }
+    }
+    Ok(game)
+}
+
+// puts a locked piece on a board of block_idx per cell, bottom line first
+pub fn place_lock(board: &mut Vec<Vec<Option<usize>>>, blocks: &[(usize, usize)], block_idx: usize) {
+    for &(x, y) in blocks {
+        if let Some(cell) = board.get_mut(y).and_then(|line| line.get_mut(x)) {
+            *cell = Some(block_idx);
+        }
+    }
+}
+
+// the cells moved to the bottom left corner and sorted, to compare shapes wherever they are
+fn shape(cells: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let bottom = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    let mut shape = cells.iter().map(|&(x, y)| (x - left, y - bottom)).collect::<Vec<_>>();
+    shape.sort_unstable();
+    shape
+}
+
+// why a logged lock couldn't have happened on the board: not the cells of the piece in one of its
+// rotations, outside the board, on filled cells or with nothing under it
+fn check_lock(board: &[Vec<Option<usize>>], blocks: &[(usize, usize)], block_idx: usize, rules: &GameRules) -> Result<(), String> {
+    let rotations = rules.rotation_system.rotations(block_idx).ok_or_else(|| format!("there is no piece {}", block_idx))?;
+    if !rotations.iter().any(|cells| shape(cells) == shape(blocks)) {
+        return Err(format!("the cells aren't a {}", rules.piece_name(block_idx)));
+    }
+    let cell = |x: usize, y: usize| board.get(y).and_then(|line| line.get(x)).copied();
+    if blocks.iter().any(|&(x, y)| cell(x, y).is_none()) {
+        return Err("it lies outside the board".to_string());
+    }
+    if blocks.iter().any(|&(x, y)| cell(x, y) != Some(None)) {
+        return Err("it covers filled cells".to_string());
+    }
+    let rests = blocks.iter().any(|&(x, y)| y == 0 || (!blocks.contains(&(x, y - 1)) && cell(x, y - 1) != Some(None)));
+    if !rests {
+        return Err("it locked in the air".to_string());
+    }
+    Ok(())
+}
+
+// removes full lines like the board clearer and returns how many there were
+pub fn clear_full_lines(board: &mut Vec<Vec<Option<usize>>>) -> usize {
+    let width = board.first().map_or(0, Vec::len);
+    let before = board.len();
+    board.retain(|line| line.iter().any(Option::is_none));
+    let cleared = before - board.len();
+    board.resize(before, vec![None; width]);
+    cleared
+}
+
+// the starting board of a preset as block_idx per cell, every line BOARD_HEIGHT long
+pub fn starting_board(rules: &GameRules) -> Vec<Vec<Option<usize>>> {
+    let mut board = rules.starting_board.clone();
+    board.resize(BOARD_HEIGHT, vec![]);
+    for line in board.iter_mut() {
+        line.resize(rules.board_width, None);
+    }
+    board
+}
+
+// the rows from the spawn down to where a lock's piece is, the most it can score as drops, with the
+// piece's origin as low as the cells allow where rotations of the same shape sit differently
+fn rows_fallen(blocks: &[(usize, usize)], block_idx: usize, rules: &GameRules) -> u64 {
+    let bottom = blocks.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    let origin = rules
+        .rotation_system
+        .rotations(block_idx)
+        .into_iter()
+        .flat_map(|rotations| rotations.iter())
+        .filter(|cells| shape(cells) == shape(blocks))
+        .map(|cells| bottom.saturating_sub(cells.iter().map(|&(_, y)| y).min().unwrap_or(0)))
+        .min()
+        .unwrap_or(bottom);
+    SPAWN_COORD.1.saturating_sub(origin) as u64
+}
+
+// checks the seal of a finished game, that a seeded game got the pieces of its seed and plays
+// the pieces again from the starting board of its preset, every lock has to fit where it is, drop
+// no more rows than it fell from the spawn and land where its hard drop did, and the lines and
+// points have to come out as claimed, returns the claim if they do. The seal isn't keyed, so the
+// replay is what catches a log edited and sealed again. Without a seed the pieces can't be checked,
+// a log could deal itself any, so an unseeded game plays back but isn't verified
+pub fn verify_log(path: &Path) -> Result<LoggedResult, String> {
+    let game = read_log(path).map_err(|e| e.to_string())?;
+    let result = game.result.ok_or("the game wasn't finished")?;
+    if !game.sealed {
+        return Err("the log was changed after the game".to_string());
+    }
+
+    let preset = GameRules::presets().iter().find(|(name, _)| *name == result.rules).map(|(_, preset)| preset());
+    let mut rules = match preset {
+        Some(rules) if !rules.combo_drill && rules.piece_set.is_none() => rules,
+        _ => return Err(format!("{} games can't be played back", result.rules)),
+    };
+    rules.seed = result.seed;
+    if rules.seed.is_some() {
+        let locked = game.locks.iter().map(|&(_, block_idx)| block_idx).collect::<Vec<usize>>();
+        if locked != dealt_pieces(&rules, locked.len()) {
+            return Err("the pieces aren't the ones the seed deals".to_string());
+        }
+    }
+
+    let mut board = starting_board(&rules);
+    let (mut points, mut lines) = (0u64, 0u32);
+    let overflow = || "the score overflows".to_string();
+    for (i, ((blocks, block_idx), &[soft, hard])) in game.locks.iter().zip(&game.dropped).enumerate() {
+        check_lock(&board, blocks, *block_idx, &rules).map_err(|reason| format!("piece {} can't lock there, {}", i + 1, reason))?;
+        let fallen = rows_fallen(blocks, *block_idx, &rules);
+        if soft.checked_add(hard).map_or(true, |dropped| dropped > fallen) {
+            return Err(format!("piece {} claims {} rows dropped, it fell {}", i + 1, soft.saturating_add(hard), fallen));
+        }
+        // the other players' pieces can lock between a hard drop and its own lock
+        let landed = game.hard_drops
+            .iter()
+            .filter(|(before, _, _)| *before + rules.players.max(1) > i && *before <= i)
+            .any(|(_, landing, distance)| landing == blocks && *distance >= hard);
+        if hard > 0 && !landed {
+            return Err(format!("piece {} claims {} rows hard dropped, no hard drop that far landed there", i + 1, hard));
+        }
+        let drop_points = soft
+            .checked_mul(u64::from(rules.drop_scores[0]))
+            .and_then(|soft| hard.checked_mul(u64::from(rules.drop_scores[1]))?.checked_add(soft))
+            .ok_or_else(overflow)?;
+
+        place_lock(&mut board, blocks, *block_idx);
+        let cleared = clear_full_lines(&mut board);
+        points = points
+            .checked_add(drop_points)
+            .and_then(|points| points.checked_add(u64::from(rules.line_score(cleared, lines))))
+            .ok_or_else(overflow)?;
+        lines = lines.checked_add(cleared as u32).ok_or_else(overflow)?;
+    }
+    if (points, lines) != (u64::from(result.points), result.lines) {
+        return Err(format!(
+            "claims {} points and {} lines, the pieces make {} points and {} lines",
+            result.points, result.lines, points, lines,
+        ));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    const I_FLAT: [(usize, usize); 4] = [(0, 0), (1, 0), (2, 0), (3, 0)];
+
+    fn moved(cells: &[(usize, usize)], x: usize, y: usize) -> Vec<(usize, usize)> {
+        cells.iter().map(|&(dx, dy)| (x + dx, y + dy)).collect()
+    }
+
+    fn locked(blocks: &[(usize, usize)], block_idx: usize, dropped: [u64; 2]) -> Value {
+        json!({ "type": "PieceLocked", "blocks": blocks, "holes": [], "block_idx": block_idx, "dropped": dropped })
+    }
+
+    // a finished tetrus game of the events, sealed the way GameLogSystem seals it
+    fn sealed_log(name: &str, mut entries: Vec<Value>, points: u32, lines: u32) -> PathBuf {
+        entries.push(json!({ "type": "GameOver", "rules": "tetrus", "seed": null, "points": points, "lines": lines }));
+        let seal_frame = entries.len();
+
+        let mut text = String::new();
+        let mut hash = FNV_OFFSET;
+        for (frame, event) in entries.into_iter().enumerate() {
+            let line = json!({ "frame": frame, "kind": "event", "event": event }).to_string() + "\n";
+            hash = fnv1a(hash, line.as_bytes());
+            text.push_str(&line);
+        }
+        text.push_str(&json!({ "frame": seal_frame, "kind": "seal", "hash": format!("{:016x}", hash) }).to_string());
+        text.push('\n');
+
+        let path = std::env::temp_dir().join(format!("tetrus_{}_{}.jsonl", name, std::process::id()));
+        fs::write(&path, text).expect("the log can be written");
+        path
+    }
+
+    // two I pieces and an O fill the bottom line
+    fn single_locks(second_i_row: usize) -> Vec<Value> {
+        vec![
+            locked(&moved(&I_FLAT, 0, 0), 0, [0, 0]),
+            locked(&moved(&I_FLAT, 4, second_i_row), 0, [0, 0]),
+            locked(&moved(&[(0, 0), (1, 0), (0, 1), (1, 1)], 8, 0), 3, [0, 0]),
+        ]
+    }
+
+    // the first I hard dropped from the spawn after a soft drop of `soft` rows
+    fn dropped_locks(soft: u64, hard: u64) -> Vec<Value> {
+        let mut entries = single_locks(0);
+        entries[0] = locked(&moved(&I_FLAT, 0, 0), 0, [soft, hard]);
+        entries.insert(0, json!({ "type": "HardDrop", "blocks": moved(&I_FLAT, 0, 0), "distance": hard, "block_idx": 0 }));
+        entries
+    }
+
+    #[test]
+    fn verifies_an_honest_log() {
+        let path = sealed_log("honest", single_locks(0), 100, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        let result = result.expect("the game plays back as logged");
+        assert_eq!((result.points, result.lines), (100, 1));
+    }
+
+    #[test]
+    fn rejects_a_resealed_log_with_a_floating_piece() {
+        let path = sealed_log("tampered", single_locks(5), 100, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        let reason = result.err().expect("the floating I is caught");
+        assert!(reason.contains("piece 2"), "{}", reason);
+        assert!(reason.contains("in the air"), "{}", reason);
+    }
+
+    #[test]
+    fn verifies_drops_from_the_spawn() {
+        let path = sealed_log("drops", dropped_locks(5, 15), 100 + 5 + 2 * 15, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        result.expect("the I fell 20 rows, 5 soft and 15 hard");
+    }
+
+    #[test]
+    fn rejects_drops_further_than_the_spawn() {
+        let path = sealed_log("far_drops", dropped_locks(10, 15), 100 + 10 + 2 * 15, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        let reason = result.err().expect("25 rows from 20 above the floor are caught");
+        assert!(reason.contains("piece 1 claims 25 rows dropped"), "{}", reason);
+    }
+
+    #[test]
+    fn rejects_a_hard_drop_that_landed_elsewhere() {
+        let mut entries = dropped_locks(0, 10);
+        entries[0]["blocks"] = json!(moved(&I_FLAT, 0, 3));
+        let path = sealed_log("elsewhere", entries, 100 + 2 * 10, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        let reason = result.err().expect("the hard drop doesn't match the lock");
+        assert!(reason.contains("no hard drop"), "{}", reason);
+    }
+
+    #[test]
+    fn rejects_overflowing_drops_without_panicking() {
+        let path = sealed_log("overflow", dropped_locks(u64::MAX, 1), 0, 1);
+        let result = verify_log(&path);
+        fs::remove_file(&path).ok();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    input::{ControllerAxis, ControllerButton, ControllerEvent, InputEvent, InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+};
+
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks},
+    Axis, Button, EventType, GamepadId, Gilrs,
+};
+
+use log::{info, warn};
+
+use crate::events::GameEvent;
+use crate::settings::GameSettings;
+
+// strength relative to rumble_strength and milliseconds
+const HARD_DROP_PULSE: (f32, u32) = (0.4, 60);
+const TETRIS_PULSE: (f32, u32) = (1., 250);
+
+// the actions of a player given a gamepad, whatever the bindings say
+const LAYOUT: [(&str, &[ControllerButton]); 5] = [
+    ("left", &[ControllerButton::DPadLeft]),
+    ("right", &[ControllerButton::DPadRight]),
+    ("down", &[ControllerButton::DPadDown]),
+    ("hard_drop", &[ControllerButton::DPadUp]),
+    ("rotate", &[ControllerButton::A, ControllerButton::B]),
+];
+
+pub fn action_is_down(input: &InputHandler<StringBindings>, which: u32, action: &str) -> bool {
+    LAYOUT
+        .iter()
+        .filter(|(name, _)| *name == action)
+        .flat_map(|(_, buttons)| buttons.iter())
+        .any(|&button| input.controller_button_is_down(which, button))
+}
+
+// the name of the gamepad in use if it was unplugged, the game waits until it is back
+#[derive(Default)]
+pub struct GamepadLost(pub Option<String>);
+
+fn controller_button(button: Button) -> Option<ControllerButton> {
+    Some(match button {
+        Button::South => ControllerButton::A,
+        Button::East => ControllerButton::B,
+        Button::West => ControllerButton::X,
+        Button::North => ControllerButton::Y,
+        Button::LeftTrigger => ControllerButton::LeftShoulder,
+        Button::RightTrigger => ControllerButton::RightShoulder,
+        Button::LeftTrigger2 => ControllerButton::LeftTrigger,
+        Button::RightTrigger2 => ControllerButton::RightTrigger,
+        Button::Select => ControllerButton::Back,
+        Button::Start => ControllerButton::Start,
+        Button::Mode => ControllerButton::Guide,
+        Button::LeftThumb => ControllerButton::LeftStick,
+        Button::RightThumb => ControllerButton::RightStick,
+        Button::DPadUp => ControllerButton::DPadUp,
+        Button::DPadDown => ControllerButton::DPadDown,
+        Button::DPadLeft => ControllerButton::DPadLeft,
+        Button::DPadRight => ControllerButton::DPadRight,
+        _ => return None,
+    })
+}
+
+fn controller_axis(axis: Axis) -> Option<ControllerAxis> {
+    Some(match axis {
+        Axis::LeftStickX => ControllerAxis::LeftX,
+        Axis::LeftStickY => ControllerAxis::LeftY,
+        Axis::RightStickX => ControllerAxis::RightX,
+        Axis::RightStickY => ControllerAxis::RightY,
+        Axis::LeftZ => ControllerAxis::LeftTrigger,
+        Axis::RightZ => ControllerAxis::RightTrigger,
+        _ => return None,
+    })
+}
+
+// reads the gamepads through gilrs, the input bundle has no gamepad support without sdl, and passes
+// their buttons and sticks on to the input handler, so `Controller(0, A)` and the like in the bindings
+// work. Gamepads get the lowest free number when they are plugged in, a pad plugged back in takes
+// the place of the one that was unplugged without a restart. Also pulses the gamepad that was used
+// last on hard drops and tetrises through gilrs' force feedback. Runs thread local as gilrs can't
+// be shared
+#[derive(SystemDesc)]
+#[system_desc(name(GamepadSystemDesc))]
+pub struct GamepadSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    gilrs: Option<Gilrs>,
+    #[system_desc(skip)]
+    slots: Vec<Option<GamepadId>>, // by controller number in the bindings
+    #[system_desc(skip)]
+    gamepad: Option<GamepadId>, // the one that sent the latest input
+    #[system_desc(skip)]
+    effect: Option<Effect>, // stops once dropped, kept until the next pulse
+}
+
+impl GamepadSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                warn!("No gamepad support: {}", e);
+                None
+            },
+        };
+        // pads plugged in before the start don't send a connected event
+        let slots = gilrs.as_ref().map_or(vec![], |gilrs| gilrs.gamepads().map(|(id, _)| Some(id)).collect());
+        Self {
+            reader_id,
+            gilrs,
+            slots,
+            gamepad: None,
+            effect: None,
+        }
+    }
+
+    fn slot(&self, id: GamepadId) -> Option<u32> {
+        self.slots.iter().position(|&slot| slot == Some(id)).map(|slot| slot as u32)
+    }
+
+    fn connect(&mut self, id: GamepadId) -> u32 {
+        if let Some(slot) = self.slot(id) {
+            return slot;
+        }
+        match self.slots.iter().position(Option::is_none) {
+            Some(free) => {
+                self.slots[free] = Some(id);
+                free as u32
+            },
+            None => {
+                self.slots.push(Some(id));
+                self.slots.len() as u32 - 1
+            },
+        }
+    }
+
+    fn pulse(&mut self, (strength, millis): (f32, u32), settings: &GameSettings) {
+        let (gilrs, gamepad) = match (self.gilrs.as_mut(), self.gamepad) {
+            (Some(gilrs), Some(gamepad)) => (gilrs, gamepad),
+            _ => return,
+        };
+        if !gilrs.gamepad(gamepad).is_ff_supported() {
+            return;
+        }
+
+        let magnitude = (strength * settings.rumble_strength()) * f32::from(u16::max_value());
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: magnitude as u16 },
+                scheduling: Replay { play_for: Ticks::from_ms(millis), ..Default::default() },
+                ..Default::default()
+            })
+            .gamepads(&[gamepad])
+            .finish(gilrs);
+        match effect.and_then(|effect| effect.play().map(|_| effect)) {
+            Ok(effect) => self.effect = Some(effect),
+            Err(e) => warn!("Failed to rumble: {}", e),
+        }
+    }
+}
+
+impl<'s> System<'s> for GamepadSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, GameSettings>,
+        Write<'s, InputHandler<StringBindings>>,
+        Write<'s, EventChannel<InputEvent<StringBindings>>>,
+        Write<'s, GamepadLost>,
+    );
+
+    fn run(&mut self, (events, settings, mut input, mut input_events, mut lost): Self::SystemData) {
+        let mut gamepad_events = vec![];
+        if let Some(gilrs) = self.gilrs.as_mut() {
+            while let Some(event) = gilrs.next_event() {
+                gamepad_events.push((event.id, event.event, gilrs.gamepad(event.id).name().to_string()));
+            }
+        }
+        for (id, event, name) in gamepad_events {
+            let controller_event = match event {
+                EventType::Connected => {
+                    let which = self.connect(id);
+                    info!("Gamepad {} connected as controller {}", name, which);
+                    if self.gamepad.is_none() && lost.0.is_some() {
+                        self.gamepad = Some(id);
+                        lost.0 = None;
+                    }
+                    Some(ControllerEvent::ControllerConnected { which })
+                },
+                EventType::Disconnected => {
+                    let which = self.slot(id);
+                    if let Some(which) = which {
+                        self.slots[which as usize] = None;
+                    }
+                    info!("Gamepad {} disconnected", name);
+                    if self.gamepad == Some(id) {
+                        self.gamepad = None;
+                        lost.0 = Some(name);
+                    }
+                    which.map(|which| ControllerEvent::ControllerDisconnected { which })
+                },
+                EventType::ButtonPressed(button, _) => {
+                    if self.gamepad != Some(id) {
+                        info!("Using gamepad {}", name);
+                    }
+                    self.gamepad = Some(id);
+                    let which = self.connect(id);
+                    controller_button(button).map(|button| ControllerEvent::ControllerButtonPressed { which, button })
+                },
+                EventType::ButtonReleased(button, _) => {
+                    let which = self.connect(id);
+                    controller_button(button).map(|button| ControllerEvent::ControllerButtonReleased { which, button })
+                },
+                EventType::AxisChanged(axis, value, _) => {
+                    let which = self.connect(id);
+                    controller_axis(axis).map(|axis| ControllerEvent::ControllerAxisMoved { which, axis, value })
+                },
+                _ => None,
+            };
+            if let Some(controller_event) = controller_event {
+                input.send_controller_event(&controller_event, &mut input_events);
+            }
+        }
+
+        for event in events.read(&mut self.reader_id).cloned().collect::<Vec<GameEvent>>() {
+            if !settings.rumble {
+                continue;
+            }
+            match event {
+                GameEvent::HardDrop { .. } => self.pulse(HARD_DROP_PULSE, &settings),
+                GameEvent::LinesCleared { lines, .. } if lines >= 4 => self.pulse(TETRIS_PULSE, &settings),
+                _ => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use log::error;
+
+use serde::{Deserialize, Serialize};
+
+pub const GRAVITY_DIR: &str = "resources/gravity";
+
+const FALLBACK_SPEED: f32 = 1.; // rows per second of a curve that couldn't be loaded
+
+// how fast pieces fall as the levels go up, from resources/gravity
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GravityCurve {
+    pub levels: Vec<(u32, f32)>, // rows per second from the given level on, ascending by level
+}
+
+impl GravityCurve {
+    // by file name in resources/gravity without the extension, a curve that can't be loaded
+    // falls at one row per second
+    pub fn load(name: &str) -> Self {
+        let path = match application_root_dir() {
+            Ok(root) => root.join(GRAVITY_DIR).join(format!("{}.ron", name)),
+            Err(e) => {
+                error!("Failed to find gravity curve {}: {}", name, e);
+                return Self::default();
+            },
+        };
+        match Self::load_no_fallback(&path) {
+            Ok(curve) if !curve.levels.is_empty() => curve,
+            Ok(_) => {
+                error!("Gravity curve {:?} has no levels", path);
+                Self::default()
+            },
+            Err(e) => {
+                error!("Failed to load gravity curve {:?}: {}", path, e);
+                Self::default()
+            },
+        }
+    }
+
+    // rows per second at the level, the first entry's before it starts
+    pub fn speed(&self, level: u32) -> f32 {
+        self.levels
+            .iter()
+            .take_while(|&&(from_level, _)| from_level <= level)
+            .last()
+            .or_else(|| self.levels.first())
+            .map_or(FALLBACK_SPEED, |&(_, speed)| speed)
+    }
+}
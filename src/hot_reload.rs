@@ -0,0 +1,116 @@
+use amethyst::{
+    config::Config,
+    core::timing::Time,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, ReadExpect, System, SystemData, World, Write},
+    input::{Bindings, InputHandler, StringBindings},
+    utils::application_root_dir,
+    window::{DisplayConfig, Window},
+};
+
+use log::{error, info};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::display::apply_display_config;
+use crate::profile;
+use crate::settings::{GameSettings, BINDINGS_PATH, DISPLAY_CONFIG_PATH, SETTINGS_PATH};
+
+const CHECK_INTERVAL: f32 = 1.; // seconds between looking at the config files
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        let changed = now.is_some() && now != self.modified;
+        self.modified = now;
+        changed
+    }
+}
+
+// reloads settings, bindings and the display config when their files change on disk,
+// sprites and other assets are reloaded by the HotReloadBundle
+#[derive(SystemDesc)]
+pub struct ConfigReloadSystem {
+    since_check: f32,
+    settings: WatchedFile,
+    bindings: WatchedFile,
+    display_config: WatchedFile,
+}
+
+impl ConfigReloadSystem {
+    pub fn new() -> amethyst::Result<Self> {
+        let root = application_root_dir()?;
+        Ok(Self {
+            since_check: 0.,
+            settings: WatchedFile::new(profile::path(&root, SETTINGS_PATH)),
+            bindings: WatchedFile::new(profile::path(&root, BINDINGS_PATH)),
+            display_config: WatchedFile::new(root.join(DISPLAY_CONFIG_PATH)),
+        })
+    }
+}
+
+impl<'s> System<'s> for ConfigReloadSystem {
+    type SystemData = (
+        Write<'s, GameSettings>,
+        Write<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Window>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut settings, mut input, window, time): Self::SystemData) {
+        self.since_check += time.delta_real_seconds();
+        if self.since_check < CHECK_INTERVAL {
+            return;
+        }
+        self.since_check = 0.;
+
+        if self.settings.changed() {
+            match GameSettings::load_no_fallback(&self.settings.path) {
+                Ok(mut reloaded) => {
+                    reloaded.block_size = settings.block_size; // not part of the file
+                    // a changed fps_cap is applied by the IdleSystem
+                    *settings = reloaded;
+                    info!("Reloaded {:?}", self.settings.path);
+                },
+                Err(e) => error!("Failed to reload settings: {}", e),
+            }
+        }
+
+        if self.bindings.changed() {
+            match Bindings::<StringBindings>::load_no_fallback(&self.bindings.path) {
+                Ok(reloaded) => {
+                    input.bindings = reloaded;
+                    info!("Reloaded {:?}", self.bindings.path);
+                },
+                Err(e) => error!("Failed to reload bindings: {}", e),
+            }
+        }
+
+        if self.display_config.changed() {
+            match DisplayConfig::load_no_fallback(&self.display_config.path) {
+                Ok(config) => {
+                    apply_display_config(&window, &config, settings.ui_scale(window.get_hidpi_factor()));
+                    info!("Reloaded {:?}", self.display_config.path);
+                },
+                Err(e) => error!("Failed to reload display config: {}", e),
+            }
+        }
+    }
+}
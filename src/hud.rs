@@ -0,0 +1,137 @@
+use amethyst::{
+    assets::{AssetStorage, Loader, ProgressCounter},
+    core::timing::Time,
+    derive::SystemDesc,
+    ecs::prelude::{Entity, Read, System, SystemData, World, WorldExt, Write, WriteStorage},
+    prelude::*,
+    ui::{get_default_font, Anchor, FontAsset, FontHandle, LineMode, TtfFormat, UiText, UiTransform},
+};
+
+use crate::fallback::asset_exists;
+use crate::locale::Locale;
+use crate::state::Score;
+use crate::stats::SessionStats;
+
+// a font of your own in place of the one built into amethyst_ui
+const FONT_PATH: &str = "fonts/hud.ttf";
+const FONT_SIZE: f32 = 20.;
+const POPUP_FONT_SIZE: f32 = 24.;
+const MARGIN: f32 = 12.; // pixels from the window edge
+const LINE_HEIGHT: f32 = 26.;
+const TEXT_COLOR: [f32; 4] = [1., 1., 1., 1.];
+
+pub struct HudFont(pub FontHandle);
+
+// called by the LoadingState so the font is ready with the sprites
+pub fn load_font(world: &mut World, progress: &mut ProgressCounter) {
+    let font = {
+        let loader = world.read_resource::<Loader>();
+        let storage = world.read_resource::<AssetStorage<FontAsset>>();
+        if asset_exists(FONT_PATH) {
+            loader.load(FONT_PATH, TtfFormat, progress, &storage)
+        }
+        else {
+            get_default_font(&loader, &storage)
+        }
+    };
+    world.insert(HudFont(font));
+}
+
+// lines from the mode, e.g. how many opener pieces were right, updated whenever lines are cleared
+#[derive(Default)]
+pub struct HudLines(pub Vec<String>);
+
+// text over the middle of the window until it is cleared, or for `remaining` seconds
+#[derive(Default)]
+pub struct Popup {
+    pub text: String,
+    pub remaining: Option<f32>,
+}
+
+pub fn show_popup(world: &World, text: String, seconds: Option<f32>) {
+    *world.write_resource::<Popup>() = Popup { text, remaining: seconds };
+}
+
+pub fn clear_popup(world: &World) {
+    *world.write_resource::<Popup>() = Popup::default();
+}
+
+pub struct Hud {
+    points: Entity,
+    lines: Entity,
+    time: Entity,
+    mode: Entity,
+    popup: Entity,
+}
+
+fn text(world: &mut World, font: &FontHandle, id: &str, anchor: Anchor, y: f32, height: f32, font_size: f32) -> Entity {
+    let x = match anchor {
+        Anchor::TopLeft => MARGIN,
+        Anchor::TopRight => -MARGIN,
+        _ => 0.,
+    };
+    let transform = UiTransform::new(id.to_string(), anchor, anchor, x, y, 1., 400., height);
+    let mut text = UiText::new(font.clone(), String::new(), TEXT_COLOR, font_size);
+    text.align = anchor;
+    text.line_mode = LineMode::Wrap;
+    world.create_entity().with(transform).with(text).build()
+}
+
+// score, lines and time in the top right corner, the mode's lines below them and popups in the middle
+pub fn create_hud(world: &mut World) {
+    let font = world.read_resource::<HudFont>().0.clone();
+    let hud = Hud {
+        points: text(world, &font, "points", Anchor::TopRight, -MARGIN, LINE_HEIGHT, FONT_SIZE),
+        lines: text(world, &font, "lines", Anchor::TopRight, -MARGIN - LINE_HEIGHT, LINE_HEIGHT, FONT_SIZE),
+        time: text(world, &font, "time", Anchor::TopRight, -MARGIN - 2. * LINE_HEIGHT, LINE_HEIGHT, FONT_SIZE),
+        mode: text(world, &font, "mode", Anchor::TopRight, -MARGIN - 3.5 * LINE_HEIGHT, 4. * LINE_HEIGHT, FONT_SIZE),
+        popup: text(world, &font, "popup", Anchor::Middle, 0., 10. * LINE_HEIGHT, POPUP_FONT_SIZE),
+    };
+    world.insert(hud);
+    world.insert(HudLines::default());
+    world.insert(Popup::default());
+}
+
+#[derive(Default, SystemDesc)]
+pub struct HudSystem;
+
+impl<'s> System<'s> for HudSystem {
+    type SystemData = (
+        Option<Read<'s, Hud>>,
+        WriteStorage<'s, UiText>,
+        Read<'s, Score>,
+        Read<'s, SessionStats>,
+        Read<'s, HudLines>,
+        Write<'s, Popup>,
+        Read<'s, Locale>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (hud, mut texts, score, stats, hud_lines, mut popup, locale, time): Self::SystemData) {
+        let hud = match hud {
+            Some(hud) => hud,
+            None => return,
+        };
+        if let Some(remaining) = popup.remaining {
+            // real time, the popup of a pause counts down while the game time stands still
+            let remaining = remaining - time.delta_real_seconds();
+            *popup = if remaining > 0. { Popup { text: popup.text.clone(), remaining: Some(remaining) } } else { Popup::default() };
+        }
+
+        let time = format!("{}:{:04.1}", (stats.elapsed / 60.) as u32, stats.elapsed % 60.);
+        let contents = [
+            (hud.points, locale.text("hud_points", &[&score.points])),
+            (hud.lines, locale.text("hud_lines", &[&score.lines])),
+            (hud.time, time),
+            (hud.mode, hud_lines.0.join("\n")),
+            (hud.popup, popup.text.clone()),
+        ];
+        for (entity, content) in contents.iter() {
+            if let Some(text) = texts.get_mut(*entity) {
+                if text.text != *content {
+                    text.text = content.clone();
+                }
+            }
+        }
+    }
+}
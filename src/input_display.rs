@@ -0,0 +1,170 @@
+use amethyst::{
+    core::math::Vector3,
+    core::transform::Transform,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Read, ReadExpect, System, SystemData, World, WriteStorage},
+    input::{InputHandler, StringBindings},
+    renderer::{palette::Srgba, resources::Tint, transparent::Transparent, SpriteRender},
+};
+
+use std::collections::VecDeque;
+
+use crate::effects::{BlockSprites, OUTLINE_SPRITE, WHITE_SPRITE};
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::DasCharge;
+use crate::stats::TRACKED_ACTIONS;
+
+const HISTORY_ROWS: usize = 8;
+const CELL_SCALE: f32 = 0.8; // relative to a block, leaves a gap between the cells
+const IDLE_ALPHA: f32 = 0.2;
+const OLDEST_ALPHA: f32 = 0.15; // history fades towards the top
+const DAS_BAR_ROW: f32 = 10.; // above the input history, below the preview
+const DAS_BAR_WIDTH: f32 = 5.; // in blocks when fully charged
+const BAR_HEIGHT: f32 = 0.4;
+const CHARGING: (f32, f32, f32) = (1., 0.7, 0.2);
+const CHARGED: (f32, f32, f32) = (0.3, 1., 0.4);
+
+// cell of an action in the bottom of the preview column, row 0 shows what is held, the rows above
+// are the latest presses with the newest at the bottom
+fn cell_transform(action: usize, row: usize, board_width: usize, block_size: f32) -> Transform {
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new(CELL_SCALE, CELL_SCALE, 1.));
+    transform.set_translation_xyz(
+        (board_width + 1 + action) as f32 * block_size,
+        (row as f32 + 0.5) * block_size,
+        0.,
+    );
+    transform
+}
+
+// shows the held actions (left, right, rotate, soft drop, hard drop) and a scrolling history of
+// presses next to the board while `input_display` is enabled
+#[derive(Default, SystemDesc)]
+pub struct InputDisplaySystem {
+    held: [bool; 5],
+    history: VecDeque<usize>, // pressed actions, newest first
+    entities: Vec<Entity>,
+    shown: bool,
+}
+
+impl<'s> System<'s> for InputDisplaySystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        ReadExpect<'s, BlockSprites>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+    );
+
+    fn run(&mut self, (entities, input, rules, settings, block_sprites, mut transforms, mut sprites, mut tints, mut transparents): Self::SystemData) {
+        let mut changed = settings.input_display != self.shown;
+        self.shown = settings.input_display;
+        for (i, &action) in TRACKED_ACTIONS.iter().enumerate() {
+            let down = settings.player_action_is_down(&input, 0, action);
+            if down && !self.held[i] {
+                self.history.push_front(i);
+                self.history.truncate(HISTORY_ROWS);
+            }
+            changed |= down != self.held[i];
+            self.held[i] = down;
+        }
+        if !changed {
+            return;
+        }
+
+        for e in self.entities.drain(..) {
+            entities.delete(e).ok();
+        }
+        if !self.shown {
+            return;
+        }
+
+        let held = self.held.iter().enumerate().map(|(action, &down)| {
+            let sprite = if down { WHITE_SPRITE } else { OUTLINE_SPRITE };
+            (action, 0, sprite, if down { 1. } else { IDLE_ALPHA })
+        });
+        let history = self.history.iter().enumerate().map(|(age, &action)| {
+            let alpha = 1. - (1. - OLDEST_ALPHA) * age as f32 / (HISTORY_ROWS - 1) as f32;
+            (action, age + 1, WHITE_SPRITE, alpha)
+        });
+        for (action, row, sprite, alpha) in held.chain(history) {
+            let entity = entities.build_entity()
+                .with(cell_transform(action, row, rules.board_width, settings.block_size), &mut transforms)
+                .with(block_sprites.sprites[sprite].clone(), &mut sprites)
+                .with(Tint(Srgba::new(1., 1., 1., alpha)), &mut tints)
+                .with(Transparent, &mut transparents)
+                .build();
+            self.entities.push(entity);
+        }
+    }
+}
+
+// a bar in the preview column filling up while a direction charges its das, green once it
+// repeats, with a thin bar below it for the time to the next repeated step, while `das_indicator` is on
+#[derive(Default, SystemDesc)]
+pub struct DasIndicatorSystem {
+    bars: Option<(Entity, Entity)>, // das and arr
+}
+
+impl<'s> System<'s> for DasIndicatorSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, DasCharge>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        ReadExpect<'s, BlockSprites>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+    );
+
+    fn run(&mut self, (entities, charge, rules, settings, block_sprites, mut transforms, mut sprites, mut tints, mut transparents): Self::SystemData) {
+        if !settings.das_indicator {
+            if let Some((das, arr)) = self.bars.take() {
+                entities.delete(das).ok();
+                entities.delete(arr).ok();
+            }
+            return;
+        }
+
+        let (das, arr) = match self.bars {
+            Some(bars) if entities.is_alive(bars.0) && entities.is_alive(bars.1) => bars,
+            _ => {
+                let mut bar = || entities.build_entity()
+                    .with(Transform::default(), &mut transforms)
+                    .with(block_sprites.sprites[WHITE_SPRITE].clone(), &mut sprites)
+                    .with(Tint(Srgba::new(1., 1., 1., 1.)), &mut tints)
+                    .with(Transparent, &mut transparents)
+                    .build();
+                let bars = (bar(), bar());
+                self.bars = Some(bars);
+                bars
+            },
+        };
+
+        let block_size = settings.block_size;
+        let left = (rules.board_width as f32 + 0.5) * block_size;
+        let color = if charge.das >= 1. { CHARGED } else { CHARGING };
+        let arr_fill = if charge.das >= 1. { charge.arr } else { 0. };
+        for &(bar, fill, row, height, alpha) in &[
+            (das, charge.das, DAS_BAR_ROW, BAR_HEIGHT, 1.),
+            (arr, arr_fill, DAS_BAR_ROW - BAR_HEIGHT, BAR_HEIGHT / 2., 0.6),
+        ] {
+            let width = DAS_BAR_WIDTH * fill;
+            if let Some(transform) = transforms.get_mut(bar) {
+                transform.set_scale(Vector3::new(width, height, 1.));
+                transform.set_translation_xyz(left + width * block_size / 2., (row + 0.5) * block_size, 0.);
+            }
+            if let Some(tint) = tints.get_mut(bar) {
+                *tint = Tint(Srgba::new(color.0, color.1, color.2, if fill > 0. { alpha } else { 0. }));
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::WorldExt,
+    input::{is_close_requested, InputEvent, VirtualKeyCode},
+    prelude::*,
+};
+
+use log::info;
+
+use crate::hud::{clear_popup, show_popup};
+use crate::locale::Locale;
+use crate::pause::Paused;
+use crate::rules::MAX_START_LEVEL;
+
+// the level the last game started at, the level select starts there when the mode is played again
+#[derive(Default)]
+pub struct StartLevel(pub u32);
+
+// pushed on top of a game whose rules have `level_select` before the first piece, left and right
+// or up and down pick the level, enter or space starts at it
+#[derive(Default)]
+pub struct LevelSelectState {
+    level: u32,
+}
+
+impl LevelSelectState {
+    fn prompt(&self, world: &World) {
+        let text = world.read_resource::<Locale>().text("level_prompt", &[&self.level, &MAX_START_LEVEL]);
+        info!("{}", text);
+        show_popup(world, text, None);
+    }
+
+    fn change(&mut self, world: &World, up: bool) -> SimpleTrans {
+        self.level = if up { (self.level + 1).min(MAX_START_LEVEL) } else { self.level.saturating_sub(1) };
+        self.prompt(world);
+        Trans::None
+    }
+}
+
+impl SimpleState for LevelSelectState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(0.);
+        data.world.insert(Paused(true));
+        self.level = data.world.try_fetch::<StartLevel>().map_or(0, |start| start.0).min(MAX_START_LEVEL);
+        self.prompt(data.world);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(1.);
+        data.world.insert(Paused(false));
+        data.world.insert(StartLevel(self.level));
+        info!("{}", data.world.read_resource::<Locale>().text("level_chosen", &[&self.level]));
+        clear_popup(data.world);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        match &event {
+            StateEvent::Window(event) if is_close_requested(&event) => Trans::Quit,
+            StateEvent::Input(InputEvent::KeyPressed { key_code, .. }) => match key_code {
+                VirtualKeyCode::Right | VirtualKeyCode::Up => self.change(data.world, true),
+                VirtualKeyCode::Left | VirtualKeyCode::Down => self.change(data.world, false),
+                VirtualKeyCode::Return | VirtualKeyCode::Space => Trans::Pop,
+                _ => Trans::None,
+            },
+            _ => Trans::None,
+        }
+    }
+}
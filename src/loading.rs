@@ -0,0 +1,207 @@
+use amethyst::{
+    assets::{AssetStorage, Completion, Handle, Loader, ProgressCounter},
+    config::Config,
+    prelude::*,
+    renderer::{ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat, Texture},
+    renderer::sprite::SpriteList,
+    utils::application_root_dir,
+    window::{DisplayConfig, Window},
+};
+
+use log::{error, info};
+
+use std::fs::File;
+
+use crate::audio::{init_music, init_sounds};
+use crate::background::load_backgrounds;
+use crate::effects::{BlockSprites, LAST_SPRITE};
+use crate::fallback::{asset_exists, generated_block_sheet, report_missing, MissingAssets};
+use crate::hud::load_font;
+use crate::locale::Locale;
+use crate::rhythm::BeatClock;
+use crate::settings::{GameSettings, DISPLAY_CONFIG_PATH};
+use crate::theme::Theme;
+
+const BLOCK_SHEET: &str = "blocks"; // sprites/blocks.png and sprites/blocks.ron
+const PROGRESS_BAR_WIDTH: usize = 20; // characters of the bar in the window title
+
+// loads every asset up front and hands over to the game or editor once all of them are ready
+pub struct LoadingState {
+    progress: ProgressCounter,
+    next: Option<Box<dyn State<GameData<'static, 'static>, StateEvent>>>,
+}
+
+impl LoadingState {
+    pub fn new<S: SimpleState + 'static>(next: S) -> Self {
+        Self {
+            progress: ProgressCounter::new(),
+            next: Some(Box::new(next)),
+        }
+    }
+
+    // the window title doubles as the progress bar, there is nothing else to draw with yet
+    fn show_progress(&self, world: &World) {
+        let total = self.progress.num_assets().max(1);
+        let filled = self.progress.num_finished() * PROGRESS_BAR_WIDTH / total;
+        let title = format!(
+            "Loading [{}{}] {}/{}",
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled),
+            self.progress.num_finished(),
+            self.progress.num_assets(),
+        );
+        world.read_resource::<Window>().set_title(&title);
+    }
+}
+
+impl SimpleState for LoadingState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        world.insert(MissingAssets::default());
+        let (theme, locale) = {
+            let settings = world.read_resource::<GameSettings>();
+            (Theme::load_named(&settings.theme), Locale::load_named(&settings.language))
+        };
+        world.insert(theme);
+        world.insert(locale);
+
+        let (sprites, frames) = load_sprites(world, &mut self.progress);
+        world.insert(BlockSprites { sprites, frames });
+        load_backgrounds(world, &mut self.progress);
+        load_font(world, &mut self.progress);
+        init_sounds(world, &mut self.progress);
+        init_music(world, &mut self.progress);
+        world.insert(BeatClock::load());
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        self.show_progress(data.world);
+
+        match self.progress.complete() {
+            Completion::Loading => Trans::None,
+            Completion::Failed => {
+                for failed in self.progress.errors() {
+                    error!("Failed to load {} {:?}: {}", failed.asset_type_name, failed.asset_name, failed.error);
+                }
+                Trans::Quit
+            },
+            Completion::Complete => {
+                info!("Loaded {} assets", self.progress.num_assets());
+                restore_title(data.world);
+                match self.next.take() {
+                    Some(next) => Trans::Switch(next),
+                    None => Trans::Quit,
+                }
+            },
+        }
+    }
+}
+
+// back to the configured title, with a warning if anything had to be replaced
+fn restore_title(world: &World) {
+    if let Ok(root) = application_root_dir() {
+        let config = DisplayConfig::load(root.join(DISPLAY_CONFIG_PATH));
+        let missing = world.read_resource::<MissingAssets>().files.len();
+        let title = if missing > 0 {
+            format!("{} - {} missing assets replaced, see the log", config.title, missing)
+        }
+        else {
+            config.title
+        };
+        world.read_resource::<Window>().set_title(&title);
+    }
+}
+
+fn sheet_paths(sheet: &str) -> (String, String) {
+    (format!("sprites/{}.png", sheet), format!("sprites/{}.ron", sheet))
+}
+
+// pixel width of the block sprites, the board is laid out in units of it so any art size fits
+fn read_block_size(sheet_path: &str) -> Option<f32> {
+    let path = application_root_dir().ok()?.join("resources").join(sheet_path);
+    let sheet = File::open(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| ron::de::from_reader::<_, SpriteList>(file).map_err(|e| e.to_string()));
+    match sheet {
+        Ok(sheet) => sheet.sprites.first().map(|sprite| sprite.width as f32),
+        Err(e) => {
+            error!("Failed to read block size from {:?}: {}", path, e);
+            None
+        },
+    }
+}
+
+// the theme's animated sheet if it has one and it is there, the one in blocks.png otherwise,
+// with the number of frames in it
+fn load_sprites(world: &mut World, progress: &mut ProgressCounter) -> (Vec<SpriteRender>, usize) {
+    let animation = world.read_resource::<Theme>().animation.clone().filter(|animation| animation.frames > 1);
+    let (sheet, frames) = match animation {
+        Some(animation) => {
+            let (texture_path, sheet_path) = sheet_paths(&animation.sheet);
+            match [texture_path, sheet_path].iter().find(|file| !asset_exists(file)) {
+                Some(file) => {
+                    report_missing(world, file);
+                    (BLOCK_SHEET.to_string(), 1)
+                },
+                None => (animation.sheet, animation.frames),
+            }
+        },
+        None => (BLOCK_SHEET.to_string(), 1),
+    };
+    let (texture_path, sheet_path) = sheet_paths(&sheet);
+    let missing = [&texture_path, &sheet_path]
+        .iter()
+        .filter(|file| !asset_exists(file))
+        .map(|file| file.to_string())
+        .collect::<Vec<_>>();
+    let (sheet_handle, frames) = if missing.is_empty() {
+        if let Some(block_size) = read_block_size(&sheet_path) {
+            world.write_resource::<GameSettings>().block_size = block_size;
+        }
+        (load_block_sheet(world, progress, texture_path, sheet_path), frames)
+    }
+    else {
+        for file in missing {
+            report_missing(world, &file);
+        }
+        (generated_block_sheet(world, progress), 1)
+    };
+
+    // Create our sprite renders. Each will have a handle to the texture
+    // that it renders from. The handle is safe to clone, since it just
+    // references the asset.
+    let sprites = (0..=LAST_SPRITE)
+        .map(|i| SpriteRender {
+            sprite_sheet: sheet_handle.clone(),
+            sprite_number: i,
+        })
+        .collect();
+    (sprites, frames)
+}
+
+fn load_block_sheet(world: &mut World, progress: &mut ProgressCounter, texture_path: String, sheet_path: String) -> Handle<SpriteSheet> {
+    // Load the texture for our sprites. We'll later need to
+    // add a handle to this texture to our `SpriteRender`s, so
+    // we need to keep a reference to it.
+    let texture_handle = {
+        let loader = world.read_resource::<Loader>();
+        let texture_storage = world.read_resource::<AssetStorage<Texture>>();
+        loader.load(
+            texture_path,
+            ImageFormat::default(),
+            &mut *progress,
+            &texture_storage,
+        )
+    };
+
+    // Load the spritesheet definition file, which contains metadata on our
+    // spritesheet texture.
+    let loader = world.read_resource::<Loader>();
+    let sheet_storage = world.read_resource::<AssetStorage<SpriteSheet>>();
+    loader.load(
+        sheet_path,
+        SpriteSheetFormat(texture_handle),
+        progress,
+        &sheet_storage,
+    )
+}
@@ -0,0 +1,48 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use log::warn;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+pub const LOCALE_DIR: &str = "resources/locales";
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+// the text shown to the player by key, `{0}`, `{1}`, .. stand for the values filled in
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Locale {
+    pub strings: BTreeMap<String, String>,
+}
+
+impl Locale {
+    // the language's strings over the english ones, so a partial translation still shows everything
+    pub fn load_named(language: &str) -> Self {
+        let root = match application_root_dir() {
+            Ok(root) => root.join(LOCALE_DIR),
+            Err(_) => return Self::default(),
+        };
+        let mut locale = Self::load(root.join(format!("{}.ron", DEFAULT_LANGUAGE)));
+        if language != DEFAULT_LANGUAGE {
+            match Self::load_no_fallback(root.join(format!("{}.ron", language))) {
+                Ok(translation) => locale.strings.extend(translation.strings),
+                Err(e) => warn!("No {} translation, showing english: {}", language, e),
+            }
+        }
+        locale
+    }
+
+    // the string for the key with the values in place, the key itself if no locale has it
+    pub fn text(&self, key: &str, values: &[&dyn Display]) -> String {
+        let mut text = self.strings.get(key).cloned().unwrap_or_else(|| key.to_string());
+        for (i, value) in values.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", i), &value.to_string());
+        }
+        text
+    }
+}
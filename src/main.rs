@@ -1,52 +1,264 @@
 use amethyst::{
+    audio::AudioBundle,
+    config::Config,
     core::transform::TransformBundle,
     prelude::*,
     renderer::{
-        plugins::{RenderFlat2D, RenderToWindow},
+        plugins::{RenderFlat2D, RenderShaded3D, RenderToWindow},
         types::DefaultBackend,
         RenderingBundle,
     },
     utils::application_root_dir,
     assets::{HotReloadBundle},
-    input::{InputBundle, StringBindings}
+    input::{InputBundle, StringBindings},
+    ui::{RenderUi, UiBundle},
 };
 
+mod audio;
+mod background;
+mod bests;
+mod bot;
+mod challenge;
+mod conflicts;
+mod crt;
+mod devices;
+mod display;
+mod editor;
+mod effects;
+mod events;
+mod export;
+mod fallback;
+mod finesse;
+mod gamelog;
+mod gamepad;
+mod gravity;
+mod hot_reload;
+mod hud;
+mod level_select;
+mod input_display;
+mod loading;
+mod locale;
+mod mode;
+mod opener;
+mod pause;
+mod piece_history;
+mod pieces;
+mod profile;
+mod render3d;
+mod results;
+mod rhythm;
+mod rules;
+mod save;
+mod screenshot;
+mod script;
+mod settings;
+mod solver;
 mod state;
+mod stats;
+mod sync;
+mod theme;
+mod tournament;
 
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
 
     let app_root = application_root_dir()?;
 
+    // `--profile <name>` anywhere on the command line keeps settings, bindings and records in
+    // profiles/<name>, the rest of the arguments are read without it
+    let mut args = std::env::args().skip(1).collect::<Vec<String>>();
+    let profile = match args.iter().position(|arg| arg == "--profile") {
+        Some(i) if i + 1 < args.len() => {
+            let name = args.remove(i + 1);
+            args.remove(i);
+            Some(name)
+        },
+        Some(_) => return Err(amethyst::Error::from_string("--profile needs a name")),
+        None => profile::current(),
+    };
+    match profile {
+        Some(name) => profile::select(&app_root, &name)?,
+        None => {
+            let available = profile::available(&app_root);
+            if !available.is_empty() {
+                log::info!("Playing without a profile, `--profile <name>` picks one of {}", available.join(", "));
+            }
+        },
+    }
+    let arg = |i: usize| args.get(i).cloned();
+
+    // `sync_dir` in the settings points at a folder shared between machines, newer files from it are
+    // taken before anything is read and the local ones go back when the game closes
+    let sync_dir = settings::GameSettings::load(profile::path(&app_root, settings::SETTINGS_PATH)).sync_dir;
+    let sync_dir = if sync_dir.is_empty() { None } else { Some(std::path::PathBuf::from(sync_dir)) };
+    if let Some(ref sync_dir) = sync_dir {
+        sync::pull(&app_root, sync_dir);
+    }
+
+    // replays are exported without opening a window, `cargo run -- export <gameplay log> [gif|png]`
+    if arg(0).as_ref().map(String::as_str) == Some("export") {
+        let log = arg(1).ok_or_else(|| amethyst::Error::from_string("export needs a gameplay log"))?;
+        let format = arg(2).map_or(Some(export::ExportFormat::Gif), |name| export::ExportFormat::from_name(&name))
+            .ok_or_else(|| amethyst::Error::from_string("replays export as gif or png"))?;
+        export::export_replay(std::path::Path::new(&log), format)?;
+        return Ok(());
+    }
+    // `cargo run -- verify <gameplay log>` checks a finished game before its score is trusted
+    if arg(0).as_ref().map(String::as_str) == Some("verify") {
+        let log = arg(1).ok_or_else(|| amethyst::Error::from_string("verify needs a gameplay log"))?;
+        match gamelog::verify_log(std::path::Path::new(&log)) {
+            Ok(result) if result.seed.is_some() => log::info!("{} game of {} points and {} lines verified", result.rules, result.points, result.lines),
+            // nothing ties an unseeded game's pieces to the game, a log could have picked them
+            Ok(result) => log::warn!(
+                "{} game of {} points and {} lines plays back, but it wasn't seeded so its pieces and score can't be verified",
+                result.rules, result.points, result.lines,
+            ),
+            Err(reason) => return Err(amethyst::Error::from_string(format!("{} rejected: {}", log, reason))),
+        }
+        return Ok(());
+    }
+
+    // there is no mode menu to show them next to yet, `cargo run -- modes` lists every mode with its
+    // personal bests
+    if arg(0).as_ref().map(String::as_str) == Some("modes") {
+        let (modes, bests) = (mode::ModeRegistry::builtin(), bests::PersonalBests::load());
+        let locale = locale::Locale::load_named(&settings::GameSettings::load(profile::path(&app_root, settings::SETTINGS_PATH)).language);
+        for name in modes.names() {
+            log::info!("{}", name);
+            for best in bests.of_mode(name) {
+                log::info!("  {}", results::best_text(&locale, best));
+            }
+        }
+        return Ok(());
+    }
+
+    // `cargo run -- handling save <name>` keeps the current handling as a preset to switch to with H
+    if arg(0).as_ref().map(String::as_str) == Some("handling") {
+        if arg(1).as_ref().map(String::as_str) != Some("save") {
+            return Err(amethyst::Error::from_string("handling presets are saved with `handling save <name>`"));
+        }
+        let name = arg(2).ok_or_else(|| amethyst::Error::from_string("the preset needs a name"))?;
+        let mut settings = settings::GameSettings::load(profile::path(&app_root, settings::SETTINGS_PATH));
+        settings.save_handling_preset(&name).map_err(amethyst::Error::from_string)?;
+        settings.save();
+        log::info!("Saved handling preset {}", name);
+        if let Some(ref sync_dir) = sync_dir {
+            sync::push(&app_root, sync_dir);
+        }
+        return Ok(());
+    }
+
     let resources = app_root.join("resources/");
-    let display_config = resources.join("display_config.ron");
+    let display_config = app_root.join(settings::DISPLAY_CONFIG_PATH);
+    let game_settings = settings::GameSettings::load(profile::path(&app_root, settings::SETTINGS_PATH));
 
-    let binding_path = resources.join("bindings.ron");
+    let binding_path = profile::path(&app_root, settings::BINDINGS_PATH);
     let input_bundle = InputBundle::<StringBindings>::new()
         .with_bindings_from_file(binding_path)?;
 
+    let mut rendering = RenderingBundle::<DefaultBackend>::new()
+        .with_plugin(
+            RenderToWindow::from_config_path(display_config)
+                .with_clear(game_settings.clear_color()),
+        )
+        .with_plugin(RenderFlat2D::default())
+        .with_plugin(RenderUi::default());
+    // the 3D pass is only there when `render_3d` is on at start
+    if game_settings.render_3d {
+        rendering = rendering.with_plugin(RenderShaded3D::default());
+    }
+    let render_3d = render3d::Render3d(game_settings.render_3d);
+
     let game_data = GameDataBuilder::default()
         .with_bundle(TransformBundle::new())?
-        // .with_bundle(HotReloadBundle::default())? // Doesn't work? :/
-        .with_bundle(
-            RenderingBundle::<DefaultBackend>::new()
-                .with_plugin(
-                    RenderToWindow::from_config_path(display_config)
-                        .with_clear([0., 0., 0., 1.]),
-                )
-                .with_plugin(RenderFlat2D::default()),
-        )?
+        .with_bundle(HotReloadBundle::default())?
+        .with_bundle(rendering)?
         .with_bundle(input_bundle)?
-        .with(state::PieceControllerSystem::new(), "piece_controller", &["input_system"])
-        .with(state::MovePieceSystem, "move_pieces", &["piece_controller"])
+        .with_bundle(UiBundle::<StringBindings>::new())?
+        .with_bundle(AudioBundle::default())?
+        .with(state::HandlingPresetSystem::default(), "handling_preset", &["input_system"])
+        .with(state::PieceControllerSystem::new(0), "piece_controller", &["input_system", "handling_preset"])
+        .with(state::PieceControllerSystem::new(1), "piece_controller_2", &["piece_controller"])
+        .with(bot::BotSystem::default(), "bot", &["piece_controller"])
+        .with(rhythm::BeatClockSystem::default(), "beat_clock", &[])
+        .with(state::MovePieceSystem, "move_pieces", &["piece_controller", "piece_controller_2", "bot", "beat_clock"])
+        .with_system_desc(state::DropScoreSystemDesc::default(), "drop_score", &["move_pieces"])
         .with(state::PieceSyncSystem, "piece_sync", &["move_pieces"])
+        .with(state::GhostSystem, "ghost", &["piece_sync"])
         .with(state::BoardSettlerSystem, "board_settler", &["piece_sync"])
         .with(state::BoardLineClearerSystem, "board_clearer", &["board_settler"])
         .with(state::BoardToRealTranslatorSystem, "board_to_real", &["board_clearer"])
+        .with(effects::SpawnSlideSystem, "spawn_slide", &["board_to_real"])
+        .with(effects::SkySystem, "sky", &["board_clearer"])
+        .with(render3d::CubeSystem::default(), "cubes", &["board_to_real"])
+        .with_system_desc(effects::HardDropTrailSystemDesc::default(), "hard_drop_trail", &["piece_controller"])
+        .with(effects::FadeSystem, "fade", &["hard_drop_trail"])
+        .with(effects::ParticleSystem, "particles", &[])
+        .with(effects::AmbientParticleSystem::default(), "ambient_particles", &[])
+        .with(effects::GarbageHoleSystem::default(), "garbage_holes", &["board_clearer"])
+        .with(effects::BlockAnimationSystem::default(), "block_animation", &["board_to_real", "garbage_holes"])
+        .with_system_desc(effects::GlowSystemDesc::default(), "glow", &["board_clearer", "piece_sync"])
+        .with_system_desc(stats::StatsSystemDesc::default(), "stats", &["board_clearer"])
+        .with_system_desc(gamelog::GameLogSystemDesc::default(), "gameplay_log", &["board_clearer"])
+        .with_system_desc(opener::OpenerSystemDesc::default(), "opener", &["board_settler"])
+        .with_system_desc(script::ScriptSystemDesc::default(), "script", &["board_clearer"])
+        .with_system_desc(rhythm::RhythmScoreSystemDesc::default(), "rhythm_score", &["piece_controller", "beat_clock"])
+        .with(solver::PcHintSystem::default(), "pc_hint", &[])
+        .with(finesse::FinesseHelpSystem::default(), "finesse_help", &["input_system"])
+        .with(input_display::InputDisplaySystem::default(), "input_display", &["input_system"])
+        .with(input_display::DasIndicatorSystem::default(), "das_indicator", &["piece_controller"])
+        .with_system_desc(piece_history::PieceHistorySystemDesc::default(), "piece_history", &["board_settler"])
+        .with(screenshot::ScreenshotSystem::default(), "screenshot", &["input_system", "board_to_real"])
+        .with(hot_reload::ConfigReloadSystem::new()?, "config_reload", &[])
+        .with(hud::HudSystem::default(), "hud", &["board_clearer", "stats"])
+        .with(crt::CrtSystem::default(), "crt", &[])
+        .with(display::CameraControlSystem::default(), "camera_control", &["input_system"])
+        .with(display::CameraFitSystem::default(), "camera_fit", &["camera_control"])
+        .with_system_desc(display::IdleSystemDesc::default(), "idle", &["config_reload"])
+        .with(background::BackgroundSystem::default(), "background", &["board_clearer", "camera_fit"])
+        .with(display::FullscreenSystem::default(), "fullscreen", &["input_system"])
+        .with(display::ResolutionSystem::default(), "resolution", &["input_system"])
+        .with(display::UiScaleSystem::default(), "ui_scale", &["config_reload"])
+        .with(audio::AudioControlSystem::default(), "audio_control", &["input_system"])
+        .with_system_desc(audio::SfxSystemDesc::default(), "sfx", &["board_clearer", "audio_control"])
+        .with_system_desc(audio::MusicSystemDesc::default(), "music", &["board_clearer", "audio_control"])
+        .with_thread_local_desc(gamepad::GamepadSystemDesc::default())
         ;
 
-    let mut game = Application::new(resources, state::TetrisGameState::default(), game_data)?;
+    // no mode menu yet, the mode is picked by name on the command line,
+    // the opener, board, script and marathon modes take the opener, board, script or gravity curve
+    // as a second argument,
+    // the tournament takes the names of its players
+    let mut modes = mode::ModeRegistry::builtin();
+    let mode = arg(0).and_then(|name| modes.variant(&name, &args[1..]));
+    // the variant picked here is the one the registry hands out by name
+    if let Some(mode) = &mode {
+        modes.register(mode.clone());
+    }
+    let mode = mode.unwrap_or_else(mode::ModeRegistry::default_mode);
+
+    // the board editor starts in place of a game, `cargo run -- editor <practice board>`
+    let initial_state = match (arg(0), arg(1)) {
+        (Some(ref first), board) if first == "editor" => {
+            loading::LoadingState::new(editor::EditorState::new(&board.unwrap_or_else(|| editor::DEFAULT_BOARD.to_string())))
+        },
+        _ => {
+            let saved_game = save::SavedGame::load();
+            loading::LoadingState::new(state::TetrisGameState::new(mode).with_saved_game(saved_game))
+        },
+    };
+
+    let (frame_strategy, max_fps) = game_settings.frame_rate_limit();
+    let mut game = Application::build(resources, initial_state)?
+        .with_frame_limit(frame_strategy, max_fps)
+        .with_resource(game_settings)
+        .with_resource(render_3d)
+        .with_resource(modes)
+        .build(game_data)?;
     game.run();
 
+    if let Some(ref sync_dir) = sync_dir {
+        sync::push(&app_root, sync_dir);
+    }
     Ok(())
 }
\ No newline at end of file
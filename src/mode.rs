@@ -0,0 +1,222 @@
+use amethyst::ecs::prelude::{World, WorldExt};
+
+use log::warn;
+
+use std::sync::Arc;
+
+const DEFAULT_PRACTICE_BOARD: &str = "downstack";
+const DEFAULT_GRAVITY: &str = "guideline";
+const MARATHON_VISIBLE_ROWS: usize = 20; // pieces spawn in the rows above
+
+use crate::challenge::Challenge;
+use crate::gravity::GravityCurve;
+use crate::opener::{OpenerMode, DEFAULT_OPENER};
+use crate::rhythm::RhythmMode;
+use crate::rules::GameRules;
+use crate::script::{ScriptMode, DEFAULT_SCRIPT};
+use crate::state::Score;
+use crate::tournament::TournamentMode;
+
+// a way to play, the game state asks it for the rules and lets it hook into the game,
+// a new mode is a type implementing this and a line in ModeRegistry::builtin or a register call,
+// ModeRegistry::variant too if it takes arguments
+pub trait GameMode: Send + Sync {
+    // name on the command line and in saved games
+    fn name(&self) -> &str;
+
+    // the arguments after the name that pick this variant of the mode, like the board or gravity
+    // curve, ModeRegistry::variant builds the mode again from them
+    fn args(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn rules(&self) -> GameRules;
+
+    // inserts the mode's own resources when the game starts
+    fn setup(&self, _world: &mut World) {}
+
+    // checked before every piece spawns
+    fn is_won(&self, world: &World) -> bool {
+        let lines = world.read_resource::<Score>().lines;
+        world.read_resource::<GameRules>().is_won(lines)
+    }
+
+    // extra lines logged with the score whenever lines are cleared
+    fn hud(&self, _world: &World) -> Vec<String> {
+        vec![]
+    }
+
+    // called once the game ended, won or topped out
+    fn results(&self, _world: &World) {}
+
+    // name the personal best is kept under, with the variant for modes that have one,
+    // None for modes whose games aren't one player's
+    fn best_name(&self, _rules: &GameRules) -> Option<String> {
+        Some(self.name().to_string())
+    }
+}
+
+// a mode that is nothing but a rule preset
+pub struct Preset {
+    name: &'static str,
+    make: fn() -> GameRules,
+}
+
+impl GameMode for Preset {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn rules(&self) -> GameRules {
+        (self.make)()
+    }
+}
+
+// a board and queue from resources/practice to drill, e.g. a downstacking situation
+pub struct PracticeBoardMode {
+    board: String,
+    setup: Option<Challenge>, // played as is instead of the file, from the board editor
+}
+
+impl PracticeBoardMode {
+    pub fn new(board: &str) -> Self {
+        Self { board: board.to_string(), setup: None }
+    }
+
+    pub fn from_setup(setup: Challenge) -> Self {
+        Self { board: setup.name.clone(), setup: Some(setup) }
+    }
+}
+
+impl GameMode for PracticeBoardMode {
+    fn name(&self) -> &str {
+        "board"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec![self.board.clone()]
+    }
+
+    fn rules(&self) -> GameRules {
+        let setup = self.setup.clone().or_else(|| Challenge::load_practice(&self.board)).unwrap_or_default();
+        GameRules {
+            name: "board",
+            sequence: setup.pieces(),
+            starting_board: setup.board(),
+            randomizer: setup.randomizer(),
+            practice: true,
+            ..GameRules::tetrus()
+        }
+    }
+
+    fn best_name(&self, _rules: &GameRules) -> Option<String> {
+        Some(format!("board {}", self.board))
+    }
+}
+
+// endless `tetrus` rules with pieces falling faster every level, by a curve from resources/gravity,
+// from a starting level the player picks and with line scores growing with the level
+pub struct MarathonMode {
+    gravity: String,
+}
+
+impl MarathonMode {
+    pub fn new(gravity: &str) -> Self {
+        Self { gravity: gravity.to_string() }
+    }
+}
+
+impl GameMode for MarathonMode {
+    fn name(&self) -> &str {
+        "marathon"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec![self.gravity.clone()]
+    }
+
+    fn rules(&self) -> GameRules {
+        GameRules {
+            name: "marathon",
+            gravity: GravityCurve::load(&self.gravity),
+            visible_rows: MARATHON_VISIBLE_ROWS,
+            level_select: true,
+            level_scoring: true,
+            ..GameRules::tetrus()
+        }
+    }
+
+    // one for every starting level
+    fn best_name(&self, rules: &GameRules) -> Option<String> {
+        Some(format!("marathon {} level {}", self.gravity, rules.start_level))
+    }
+}
+
+// every mode that can be picked by name, kept as a resource so saved games can find theirs
+pub struct ModeRegistry {
+    modes: Vec<Arc<dyn GameMode>>,
+}
+
+impl ModeRegistry {
+    pub fn builtin() -> Self {
+        let mut registry = Self { modes: vec![] };
+        for &(name, make) in GameRules::presets().iter() {
+            registry.register(Arc::new(Preset { name, make }));
+        }
+        registry.register(Arc::new(OpenerMode::new(DEFAULT_OPENER)));
+        registry.register(Arc::new(ScriptMode::new(DEFAULT_SCRIPT)));
+        registry.register(Arc::new(PracticeBoardMode::new(DEFAULT_PRACTICE_BOARD)));
+        registry.register(Arc::new(MarathonMode::new(DEFAULT_GRAVITY)));
+        registry.register(Arc::new(RhythmMode));
+        registry
+    }
+
+    // replaces a mode of the same name
+    pub fn register(&mut self, mode: Arc<dyn GameMode>) {
+        self.modes.retain(|registered| registered.name() != mode.name());
+        self.modes.push(mode);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.modes.iter().map(|mode| mode.name()).collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<Arc<dyn GameMode>> {
+        self.modes.iter().find(|mode| mode.name() == name).cloned()
+    }
+
+    // the mode by name, built from its arguments for the modes that take them
+    pub fn variant(&self, name: &str, args: &[String]) -> Option<Arc<dyn GameMode>> {
+        let arg = match args.first() {
+            Some(arg) => arg,
+            None => return self.find(name),
+        };
+        let mode: Arc<dyn GameMode> = match name {
+            "opener" => Arc::new(OpenerMode::new(arg)),
+            // a board tried out in the editor and never saved can't be played again
+            "board" if Challenge::load_practice(arg).is_none() => return None,
+            "board" => Arc::new(PracticeBoardMode::new(arg)),
+            "script" => Arc::new(ScriptMode::new(arg)),
+            "marathon" => Arc::new(MarathonMode::new(arg)),
+            "tournament" => match TournamentMode::new(args.to_vec()) {
+                Ok(tournament) => Arc::new(tournament),
+                Err(e) => {
+                    warn!("{}", e);
+                    return None;
+                },
+            },
+            _ => return self.find(name),
+        };
+        Some(mode)
+    }
+
+    pub fn default_mode() -> Arc<dyn GameMode> {
+        Arc::new(Preset { name: "tetrus", make: GameRules::tetrus })
+    }
+}
+
+impl Default for ModeRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
@@ -0,0 +1,102 @@
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, WorldExt, Write},
+    shrev::{EventChannel, ReaderId},
+};
+
+use log::info;
+
+use crate::events::GameEvent;
+use crate::locale::Locale;
+use crate::mode::GameMode;
+use crate::rules::GameRules;
+
+pub const DEFAULT_OPENER: &str = "pco";
+
+// how the pieces of an opener were placed so far
+#[derive(Clone, Default)]
+pub struct OpenerScore {
+    pub placed: usize,
+    pub correct: usize,
+}
+
+// practice of one opener, picked by the second command line argument
+pub struct OpenerMode {
+    opener: String,
+}
+
+impl OpenerMode {
+    pub fn new(opener: &str) -> Self {
+        Self { opener: opener.to_string() }
+    }
+}
+
+impl GameMode for OpenerMode {
+    fn name(&self) -> &str {
+        "opener"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec![self.opener.clone()]
+    }
+
+    fn rules(&self) -> GameRules {
+        GameRules::opener(&self.opener)
+    }
+
+    fn hud(&self, world: &World) -> Vec<String> {
+        let score = world.read_resource::<OpenerScore>();
+        vec![world.read_resource::<Locale>().text("opener_hud", &[&self.opener, &score.correct, &score.placed])]
+    }
+
+    fn results(&self, world: &World) {
+        let score = world.read_resource::<OpenerScore>();
+        info!("{}", world.read_resource::<Locale>().text("opener_results", &[&self.opener, &score.correct, &score.placed]));
+    }
+
+    fn best_name(&self, _rules: &GameRules) -> Option<String> {
+        Some(format!("opener {}", self.opener))
+    }
+}
+
+// checks every locked piece against its target while the rules have opener targets
+#[derive(SystemDesc)]
+#[system_desc(name(OpenerSystemDesc))]
+pub struct OpenerSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl OpenerSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for OpenerSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, GameRules>,
+        Write<'s, OpenerScore>,
+        Read<'s, Locale>,
+    );
+
+    fn run(&mut self, (events, rules, mut score, locale): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            if let GameEvent::PieceLocked { blocks, .. } = event {
+                if let Some(target) = rules.targets.get(score.placed) {
+                    let correct = blocks.len() == target.len() && blocks.iter().all(|block| target.contains(block));
+                    if correct {
+                        score.correct += 1;
+                    }
+                    info!("{}", locale.text(if correct { "opener_correct" } else { "opener_misplaced" }, &[&(score.placed + 1)]));
+                    if score.placed + 1 == rules.targets.len() {
+                        info!("{}", locale.text("opener_done", &[&score.correct, &rules.targets.len()]));
+                    }
+                }
+                score.placed += 1;
+            }
+        }
+    }
+}
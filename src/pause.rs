@@ -0,0 +1,198 @@
+use amethyst::{
+    core::math::Vector3,
+    core::timing::Time,
+    core::transform::Transform,
+    ecs::prelude::{Entity, WorldExt},
+    input::{is_close_requested, is_key_down, VirtualKeyCode},
+    prelude::*,
+    renderer::{palette::Srgba, resources::Tint},
+};
+
+use log::info;
+
+use crate::display::playfield_size;
+use crate::effects::{BlockSprites, WHITE_SPRITE};
+use crate::gamepad::GamepadLost;
+use crate::hud::{clear_popup, show_popup};
+use crate::locale::Locale;
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+
+const COVER_Z: f32 = 0.8; // over everything but still in front of the camera
+
+// stops the piece controls and the bot while the game is paused
+#[derive(Default)]
+pub struct Paused(pub bool);
+
+// pushed on top of the game by P or escape, time stands still and the playfield is covered so a
+// pause can't be used to plan ahead, P, enter or escape resumes
+pub struct PauseState {
+    limit: Option<f32>, // seconds until the game resumes by itself
+    elapsed: f32,
+    cover: Option<Entity>,
+}
+
+impl PauseState {
+    pub fn new(limit: Option<f32>) -> Self {
+        Self {
+            limit,
+            elapsed: 0.,
+            cover: None,
+        }
+    }
+}
+
+impl SimpleState for PauseState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        world.write_resource::<Time>().set_time_scale(0.);
+        world.insert(Paused(true));
+
+        let (block_size, clear_color) = {
+            let settings = world.read_resource::<GameSettings>();
+            (settings.block_size, settings.clear_color())
+        };
+        let board_width = world.read_resource::<GameRules>().board_width;
+        let (width, height) = playfield_size(block_size, board_width);
+        let mut transform = Transform::default();
+        transform.set_scale(Vector3::new(width / block_size, height / block_size, 1.));
+        transform.set_translation_xyz(width / 2., height / 2., COVER_Z);
+        let sprite = world.read_resource::<BlockSprites>().sprites[WHITE_SPRITE].clone();
+        self.cover = Some(world.create_entity()
+            .with(transform)
+            .with(sprite)
+            .with(Tint(Srgba::new(clear_color[0], clear_color[1], clear_color[2], 1.)))
+            .build());
+
+        let text = {
+            let locale = world.read_resource::<Locale>();
+            match self.limit {
+                Some(limit) => locale.text("paused_limited", &[&limit]),
+                None => locale.text("paused", &[]),
+            }
+        };
+        info!("{}", text);
+        show_popup(world, text, None);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(1.);
+        data.world.insert(Paused(false));
+        clear_popup(data.world);
+        if let Some(cover) = self.cover.take() {
+            data.world.delete_entity(cover).ok();
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            if is_close_requested(&event) {
+                return Trans::Quit;
+            }
+            if is_key_down(&event, VirtualKeyCode::P)
+                || is_key_down(&event, VirtualKeyCode::Return)
+                || is_key_down(&event, VirtualKeyCode::Escape) {
+                return Trans::Pop;
+            }
+        }
+        Trans::None
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        self.elapsed += data.world.read_resource::<Time>().delta_real_seconds();
+        match self.limit {
+            Some(limit) if self.elapsed >= limit => {
+                info!("{}", data.world.read_resource::<Locale>().text("pause_time_up", &[]));
+                Trans::Pop
+            },
+            _ => Trans::None,
+        }
+    }
+}
+
+// pushed on top of the game when the window is closed mid game, time stands still until enter or
+// closing again quits and escape goes back to the game
+#[derive(Default)]
+pub struct QuitConfirmState;
+
+impl SimpleState for QuitConfirmState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(0.);
+        data.world.insert(Paused(true));
+        let text = data.world.read_resource::<Locale>().text("quit_confirm", &[]);
+        info!("{}", text);
+        show_popup(data.world, text, None);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(1.);
+        data.world.insert(Paused(false));
+        clear_popup(data.world);
+    }
+
+    fn handle_event(
+        &mut self,
+        _data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            if is_close_requested(&event) || is_key_down(&event, VirtualKeyCode::Return) {
+                return Trans::Quit;
+            }
+            if is_key_down(&event, VirtualKeyCode::Escape) {
+                return Trans::Pop;
+            }
+        }
+        Trans::None
+    }
+}
+
+// pushed on top of the game when the gamepad in use is unplugged, time stands still until it or
+// another one is plugged in or enter goes on without it
+#[derive(Default)]
+pub struct GamepadLostState;
+
+impl SimpleState for GamepadLostState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(0.);
+        data.world.insert(Paused(true));
+        let name = data.world.read_resource::<GamepadLost>().0.clone().unwrap_or_default();
+        let text = data.world.read_resource::<Locale>().text("gamepad_lost", &[&name]);
+        info!("{}", text);
+        show_popup(data.world, text, None);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        data.world.write_resource::<Time>().set_time_scale(1.);
+        data.world.insert(Paused(false));
+        clear_popup(data.world);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            if is_close_requested(&event) {
+                return Trans::Quit;
+            }
+            if is_key_down(&event, VirtualKeyCode::Return) {
+                data.world.insert(GamepadLost(None));
+                return Trans::Pop;
+            }
+        }
+        Trans::None
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if data.world.read_resource::<GamepadLost>().0.is_none() {
+            return Trans::Pop;
+        }
+        Trans::None
+    }
+}
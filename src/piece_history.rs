@@ -0,0 +1,109 @@
+use amethyst::{
+    core::math::Vector3,
+    core::transform::Transform,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Read, ReadExpect, System, SystemData, World, WriteStorage},
+    renderer::{resources::Tint, SpriteRender},
+    shrev::{EventChannel, ReaderId},
+};
+
+use std::collections::VecDeque;
+
+use crate::effects::BlockSprites;
+use crate::events::GameEvent;
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::BOARD_HEIGHT;
+use crate::theme::Theme;
+
+const ICON_SCALE: f32 = 0.25; // of a block, a tetromino fits in one block
+const SLOT_HEIGHT: f32 = 1.5; // in blocks, newest at the top
+
+// cell of an icon in the last block of the preview column, clear of the preview itself
+fn icon_transform(slot: usize, (x, y): (usize, usize), board_width: usize, block_size: f32) -> Transform {
+    let cell = block_size * ICON_SCALE;
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new(ICON_SCALE, ICON_SCALE, 1.));
+    transform.set_translation_xyz(
+        (board_width + 5) as f32 * block_size + (x as f32 + 0.5) * cell,
+        BOARD_HEIGHT as f32 * block_size - (slot + 1) as f32 * SLOT_HEIGHT * block_size + (y as f32 + 0.5) * cell,
+        0.,
+    );
+    transform
+}
+
+// shows the last `piece_history` placed pieces as small icons in the shape they locked in, for
+// modes without a preview and for looking back at what was dealt
+#[derive(SystemDesc)]
+#[system_desc(name(PieceHistorySystemDesc))]
+pub struct PieceHistorySystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    history: VecDeque<(usize, Vec<(usize, usize)>)>, // block_idx and cells from the bottom left, newest first
+    #[system_desc(skip)]
+    entities: Vec<Entity>,
+    #[system_desc(skip)]
+    shown: usize,
+}
+
+impl PieceHistorySystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id, history: VecDeque::new(), entities: vec![], shown: 0 }
+    }
+}
+
+impl<'s> System<'s> for PieceHistorySystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        Option<Read<'s, Theme>>,
+        ReadExpect<'s, BlockSprites>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+    );
+
+    fn run(&mut self, (entities, events, rules, settings, theme, block_sprites, mut transforms, mut sprites, mut tints): Self::SystemData) {
+        // a game played again starts with an empty history and everything deleted
+        let mut changed = false;
+        if self.entities.iter().any(|&icon| !entities.is_alive(icon)) {
+            self.entities.clear();
+            self.history.clear();
+            changed = true;
+        }
+        let count = settings.piece_history();
+        changed |= count != self.shown;
+        self.shown = count;
+        for event in events.read(&mut self.reader_id) {
+            if let GameEvent::PieceLocked { blocks, block_idx, .. } = event {
+                let left = blocks.iter().map(|&(x, _)| x).min().unwrap_or(0);
+                let bottom = blocks.iter().map(|&(_, y)| y).min().unwrap_or(0);
+                self.history.push_front((*block_idx, blocks.iter().map(|&(x, y)| (x - left, y - bottom)).collect()));
+                changed = true;
+            }
+        }
+        self.history.truncate(count);
+        let theme = match theme {
+            Some(theme) if changed => theme,
+            _ => return,
+        };
+
+        for icon in self.entities.drain(..) {
+            entities.delete(icon).ok();
+        }
+        for (slot, (block_idx, cells)) in self.history.iter().enumerate() {
+            for &cell in cells {
+                let icon = entities.build_entity()
+                    .with(icon_transform(slot, cell, rules.board_width, settings.block_size), &mut transforms)
+                    .with(block_sprites.sprites[theme.block_sprite(*block_idx)].clone(), &mut sprites)
+                    .with(theme.block_tint(*block_idx, 1.), &mut tints)
+                    .build();
+                self.entities.push(icon);
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use amethyst::{
+    config::Config,
+    utils::application_root_dir,
+};
+
+use log::error;
+
+use serde::{Deserialize, Serialize};
+
+pub const PIECE_SET_DIR: &str = "resources/pieces";
+
+// a piece of a custom set, drawn like the challenge boards: top line first, '#' for its cells
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PieceShape {
+    pub name: String,
+    pub cells: Vec<String>, // the spawn rotation, at most 4 lines tall to fit above the board
+}
+
+impl PieceShape {
+    fn drawn_cells(&self) -> Vec<(usize, usize)> {
+        self.cells
+            .iter()
+            .rev()
+            .enumerate()
+            .flat_map(|(y, line)| line.chars().enumerate().filter(|&(_, c)| c == '#').map(move |(x, _)| (x, y)))
+            .collect()
+    }
+
+    // every distinct rotation starting with the drawn one, turned clockwise and moved to the
+    // bottom left corner like the built in tables
+    pub fn rotations(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut rotations: Vec<Vec<(usize, usize)>> = vec![];
+        let mut cells = normalized(self.drawn_cells().into_iter().map(|(x, y)| (x as i64, y as i64)).collect());
+        for _ in 0..4 {
+            let mut sorted = cells.clone();
+            sorted.sort();
+            if !rotations.iter().any(|rotation| {
+                let mut other = rotation.clone();
+                other.sort();
+                other == sorted
+            }) {
+                rotations.push(cells.clone());
+            }
+            cells = normalized(cells.iter().map(|&(x, y)| (y as i64, -(x as i64))).collect());
+        }
+        rotations
+    }
+}
+
+fn normalized(cells: Vec<(i64, i64)>) -> Vec<(usize, usize)> {
+    let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    cells.into_iter().map(|(x, y)| ((x - min_x) as usize, (y - min_y) as usize)).collect()
+}
+
+// pieces dealt in place of the seven tetrominoes, block_idx is the position in the set
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PieceSet {
+    pub name: String,
+    pub pieces: Vec<PieceShape>,
+}
+
+impl PieceSet {
+    // by file name in resources/pieces without the extension
+    pub fn load(name: &str) -> Option<Self> {
+        let path = application_root_dir().ok()?.join(PIECE_SET_DIR).join(format!("{}.ron", name));
+        match Self::load_no_fallback(&path) {
+            Ok(set) if !set.pieces.is_empty() => Some(set),
+            Ok(_) => {
+                error!("Piece set {:?} has no pieces", path);
+                None
+            },
+            Err(e) => {
+                error!("Failed to load piece set {:?}: {}", path, e);
+                None
+            },
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use log::info;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::settings::{BINDINGS_PATH, SETTINGS_PATH};
+
+pub const PROFILE_DIR: &str = "profiles";
+
+// the profile in use, set from `--profile <name>` on start or straight in the environment
+pub const PROFILE_VAR: &str = "TETRUS_PROFILE";
+
+pub fn current() -> Option<String> {
+    env::var(PROFILE_VAR).ok().filter(|name| !name.is_empty())
+}
+
+// where a file or directory of player data lives, in profiles/<name> under its file name while a
+// profile is in use and at its usual place in the game directory otherwise
+pub fn path(root: &Path, relative: &str) -> PathBuf {
+    match current() {
+        Some(name) => {
+            let file_name = Path::new(relative).file_name().map_or(relative.into(), |name| name.to_os_string());
+            root.join(PROFILE_DIR).join(name).join(file_name)
+        },
+        None => root.join(relative),
+    }
+}
+
+// makes the profile the one in use, a new profile starts out with copies of the settings and bindings
+pub fn select(root: &Path, name: &str) -> io::Result<()> {
+    if name.is_empty() || name.contains(|c: char| c == '/' || c == '\\' || c == '.') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("\"{}\" can't be used as a profile name", name)));
+    }
+    env::set_var(PROFILE_VAR, name);
+
+    let dir = root.join(PROFILE_DIR).join(name);
+    if !dir.exists() {
+        info!("New profile {}", name);
+    }
+    fs::create_dir_all(&dir)?;
+    for &file in &[SETTINGS_PATH, BINDINGS_PATH] {
+        let own = path(root, file);
+        if !own.exists() {
+            fs::copy(root.join(file), own)?;
+        }
+    }
+    info!("Playing as {}", name);
+    Ok(())
+}
+
+// names of the profiles in the profiles directory
+pub fn available(root: &Path) -> Vec<String> {
+    let mut names = fs::read_dir(root.join(PROFILE_DIR))
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
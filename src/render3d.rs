@@ -0,0 +1,137 @@
+use amethyst::{
+    assets::{AssetStorage, Handle, Loader},
+    core::math::Vector3,
+    core::transform::Transform,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Entity, Join, Read, ReadExpect, ReadStorage, System, SystemData, World, WorldExt, WriteStorage},
+    prelude::*,
+    renderer::{
+        camera::Projection,
+        light::{DirectionalLight, Light},
+        loaders::load_from_srgba,
+        mtl::{Material, MaterialDefaults},
+        palette::{Srgb, Srgba},
+        rendy::mesh::{Normal, Position, Tangent, TexCoord},
+        resources::AmbientColor,
+        shape::Shape,
+        Mesh, SpriteRender, Texture,
+    },
+};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::settings::GameSettings;
+use crate::state::Block;
+use crate::theme::Theme;
+
+const FIELD_OF_VIEW: f32 = PI / 4.;
+const TILT: f32 = PI / 9.; // the camera looks up at the board from below its center
+const CUBE_SIZE: f32 = 0.9; // of a cell, a gap is left between neighbouring cubes
+
+// `render_3d` as it was on start, the renderer only has the 3D pass if it was on then
+#[derive(Default)]
+pub struct Render3d(pub bool);
+
+pub fn projection(width: f32, height: f32) -> Projection {
+    Projection::perspective(width / height, FIELD_OF_VIEW, 0.1, 2000.)
+}
+
+// far enough back and tilted so the whole playfield is in view
+pub fn camera_transform(playfield_width: f32, playfield_height: f32) -> Transform {
+    let distance = playfield_height * 0.6 / (FIELD_OF_VIEW / 2.).tan();
+    let mut transform = Transform::default();
+    transform.set_translation_xyz(
+        playfield_width * 0.5,
+        playfield_height * 0.5 - distance * TILT.sin(),
+        distance * TILT.cos(),
+    );
+    transform.set_rotation_x_axis(TILT);
+    transform
+}
+
+// a light from the top left in front of the board and some ambient light for the sides facing away
+pub fn init_light(world: &mut World) {
+    world.insert(AmbientColor(Srgba::new(0.4, 0.4, 0.4, 1.)));
+    let light = DirectionalLight {
+        color: Srgb::new(1., 1., 1.),
+        direction: Vector3::new(0.4, -0.6, -1.).normalize(),
+        intensity: 1.,
+    };
+    world.create_entity().with(Light::Directional(light)).build();
+}
+
+// gives every block a cube in place of its sprite, the board and pieces work on blocks as before,
+// blocks given back to the pool lose their cube again
+#[derive(Default, SystemDesc)]
+pub struct CubeSystem {
+    mesh: Option<(f32, Handle<Mesh>)>, // for the block size it was made for
+    materials: HashMap<usize, Handle<Material>>, // by block_idx
+    cubes: Vec<Entity>,
+}
+
+impl<'s> System<'s> for CubeSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Block>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Handle<Mesh>>,
+        WriteStorage<'s, Handle<Material>>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Mesh>>,
+        Read<'s, AssetStorage<Texture>>,
+        Read<'s, AssetStorage<Material>>,
+        Option<Read<'s, MaterialDefaults>>,
+        Option<Read<'s, Theme>>,
+        Read<'s, GameSettings>,
+        Read<'s, Render3d>,
+    );
+
+    fn run(&mut self, (entities, blocks, mut sprites, mut meshes, mut materials, loader, mesh_storage, texture_storage, material_storage, material_defaults, theme, settings, render_3d): Self::SystemData) {
+        let (theme, material_defaults) = match (theme, material_defaults) {
+            (Some(theme), Some(material_defaults)) if render_3d.0 => (theme, material_defaults),
+            _ => return,
+        };
+
+        let block_size = settings.block_size;
+        if self.mesh.as_ref().map_or(true, |&(size, _)| size != block_size) {
+            let half = block_size * CUBE_SIZE / 2.;
+            let data = Shape::Cube.generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(Some((half, half, half)));
+            self.mesh = Some((block_size, loader.load_from_data(data.into(), (), &mesh_storage)));
+        }
+        let mesh = match &self.mesh {
+            Some((_, mesh)) => mesh.clone(),
+            None => return,
+        };
+
+        // cubes of blocks that went back to the pool
+        let (kept, released): (Vec<Entity>, Vec<Entity>) = self.cubes
+            .iter()
+            .cloned()
+            .partition(|&entity| entities.is_alive(entity) && blocks.contains(entity));
+        for entity in released {
+            meshes.remove(entity);
+            materials.remove(entity);
+        }
+        self.cubes = kept;
+
+        let sprited = (&entities, &blocks, &sprites).join().map(|(entity, block, _)| (entity, block.block_idx)).collect::<Vec<_>>();
+        for (entity, block_idx) in sprited {
+            let material = self.materials
+                .entry(block_idx)
+                .or_insert_with(|| {
+                    let color = theme.block_tint(block_idx, 1.).0;
+                    let albedo = loader.load_from_data(load_from_srgba(color).into(), (), &texture_storage);
+                    loader.load_from_data(Material { albedo, ..material_defaults.0.clone() }, (), &material_storage)
+                })
+                .clone();
+            sprites.remove(entity);
+            meshes.insert(entity, mesh.clone()).ok();
+            materials.insert(entity, material).ok();
+            if !self.cubes.contains(&entity) {
+                self.cubes.push(entity);
+            }
+        }
+    }
+}
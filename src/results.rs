@@ -0,0 +1,266 @@
+use amethyst::{
+    core::math::Vector3,
+    core::transform::Transform,
+    ecs::prelude::{World, WorldExt},
+    input::{is_close_requested, is_key_down, VirtualKeyCode},
+    prelude::*,
+    renderer::{palette::Srgba, resources::Tint, transparent::Transparent, SpriteRender},
+};
+
+use log::{error, info};
+
+use std::sync::Arc;
+
+use crate::bests::{PersonalBest, PersonalBests};
+use crate::effects::{BlockSprites, OUTLINE_SPRITE, WHITE_SPRITE};
+use crate::export::{export_replay, ExportFormat};
+use crate::finesse::FinesseScore;
+use crate::gamelog::GameLogFile;
+use crate::hud::show_popup;
+use crate::locale::Locale;
+use crate::mode::GameMode;
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::{coord_to_transform, Score, TetrisGameState, BOARD_HEIGHT};
+use crate::stats::SessionStats;
+
+const OVERLAY_Z: f32 = 0.5;
+const GRAPH_Z: f32 = 0.55; // lines over the graph background
+const GRAPH_WIDTH: f32 = 5.; // in blocks, inside the preview column
+const GRAPH_HEIGHT: f32 = 7.; // three of them stacked beside the board
+const LINE_WIDTH: f32 = 0.1;
+
+// shown after the game ended: R plays the mode again, S saves the game as a replay and enter
+// or escape leave, there is no menu to go back to yet
+pub struct ResultsState {
+    mode: Arc<dyn GameMode>,
+}
+
+impl ResultsState {
+    pub fn new(mode: Arc<dyn GameMode>) -> Self {
+        Self { mode }
+    }
+}
+
+impl SimpleState for ResultsState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let mut summary = summary(data.world);
+        summary.extend(personal_best(data.world, &*self.mode));
+        for line in &summary {
+            info!("{}", line);
+        }
+        show_heatmap(data.world);
+        show_handling_graph(data.world);
+        show_stack_graph(data.world);
+        let prompt = data.world.read_resource::<Locale>().text("results_prompt", &[]);
+        info!("{}", prompt);
+        summary.push(prompt);
+        show_popup(data.world, summary.join("\n"), None);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            if is_close_requested(&event)
+                || is_key_down(&event, VirtualKeyCode::Escape)
+                || is_key_down(&event, VirtualKeyCode::Return) {
+                return Trans::Quit;
+            }
+            if is_key_down(&event, VirtualKeyCode::R) {
+                data.world.delete_all();
+                return Trans::Switch(Box::new(TetrisGameState::new(self.mode.clone())));
+            }
+            if is_key_down(&event, VirtualKeyCode::S) {
+                save_replay(data.world);
+            }
+        }
+        Trans::None
+    }
+}
+
+fn save_replay(world: &World) {
+    let log = world.read_resource::<GameLogFile>().0.clone();
+    match log {
+        Some(log) => {
+            if let Err(e) = export_replay(&log, ExportFormat::Gif) {
+                error!("Failed to save replay: {}", e);
+            }
+        },
+        None => info!("{}", world.read_resource::<Locale>().text("replay_needs_log", &[])),
+    }
+}
+
+fn summary(world: &World) -> Vec<String> {
+    let score = world.read_resource::<Score>();
+    let stats = world.read_resource::<SessionStats>();
+    let finesse = world.read_resource::<FinesseScore>();
+    let locale = world.read_resource::<Locale>();
+    let drop_scores = world.read_resource::<GameRules>().drop_scores;
+    let clears = &stats.clears;
+
+    let time = format!("{}:{:04.1}", (stats.elapsed / 60.) as u32, stats.elapsed % 60.);
+    let mut lines = vec![
+        locale.text("results_score", &[&score.points, &score.lines, &time]),
+        locale.text("results_pieces", &[&stats.placements.len(), &format!("{:.2}", stats.pieces_per_second())]),
+        locale.text("results_clears", &[
+            &clears.singles, &clears.doubles, &clears.triples, &clears.tetrises, &clears.t_spins, &clears.perfect_clears,
+        ]),
+        locale.text("results_combo", &[&score.best_combo]),
+        locale.text("results_drops", &[
+            &score.soft_dropped,
+            &(score.soft_dropped * drop_scores[0]),
+            &score.hard_dropped,
+            &(score.hard_dropped * drop_scores[1]),
+        ]),
+    ];
+    if finesse.pieces > 0 {
+        let percent = 100. * (finesse.pieces - finesse.faults) as f32 / finesse.pieces as f32;
+        lines.push(locale.text("results_finesse", &[&format!("{:.1}", percent), &finesse.faults]));
+    }
+    lines
+}
+
+// the best game of the mode as it stands after this one
+fn personal_best(world: &World, mode: &dyn GameMode) -> Option<String> {
+    let bests = PersonalBests::load();
+    let best = bests.find(&mode.best_name(&world.read_resource::<GameRules>())?)?;
+    Some(best_text(&world.read_resource::<Locale>(), best))
+}
+
+pub fn best_text(locale: &Locale, best: &PersonalBest) -> String {
+    match best.time_text() {
+        Some(time) => locale.text("best_time", &[&best.mode, &time, &best.lines, &best.games]),
+        None => locale.text("best_points", &[&best.mode, &best.points, &best.lines, &best.games]),
+    }
+}
+
+fn overlay(world: &mut World, sprite: SpriteRender, mut transform: Transform, color: (f32, f32, f32, f32)) {
+    transform.set_translation_z(OVERLAY_Z);
+    world.create_entity()
+        .with(transform)
+        .with(sprite)
+        .with(Tint(Srgba::new(color.0, color.1, color.2, color.3)))
+        .with(Transparent)
+        .build();
+}
+
+// tints every cell by how often pieces were placed there, outlines cells where holes were created
+// and shades each column by how much it was used overall
+fn show_heatmap(world: &mut World) {
+    let (placement_heat, hole_heat) = {
+        let stats = world.read_resource::<SessionStats>();
+        (stats.placement_heat, stats.hole_heat)
+    };
+    let (white, outline) = {
+        let block_sprites = world.read_resource::<BlockSprites>();
+        (block_sprites.sprites[WHITE_SPRITE].clone(), block_sprites.sprites[OUTLINE_SPRITE].clone())
+    };
+
+    let block_size = world.read_resource::<GameSettings>().block_size;
+    let board_width = world.read_resource::<GameRules>().board_width;
+
+    let column_usage = (0..board_width)
+        .map(|x| placement_heat.iter().map(|line| line[x]).sum::<u32>())
+        .collect::<Vec<u32>>();
+    let max_column = column_usage.iter().cloned().max().unwrap_or(0).max(1) as f32;
+    for (x, &usage) in column_usage.iter().enumerate() {
+        let mut transform = coord_to_transform((x, 0), block_size);
+        transform.set_scale(Vector3::new(1., BOARD_HEIGHT as f32, 1.));
+        transform.set_translation_y(BOARD_HEIGHT as f32 * block_size / 2.);
+        overlay(world, white.clone(), transform, (1., 1., 1., 0.25 * usage as f32 / max_column));
+    }
+
+    let max_placement = placement_heat.iter().flat_map(|line| line.iter()).cloned().max().unwrap_or(0).max(1) as f32;
+    let max_holes = hole_heat.iter().flat_map(|line| line.iter()).cloned().max().unwrap_or(0).max(1) as f32;
+    for y in 0..BOARD_HEIGHT {
+        for x in 0..board_width {
+            if placement_heat[y][x] > 0 {
+                let alpha = 0.2 + 0.5 * placement_heat[y][x] as f32 / max_placement;
+                overlay(world, white.clone(), coord_to_transform((x, y), block_size), (1., 0.2, 0.1, alpha));
+            }
+            if hole_heat[y][x] > 0 {
+                let alpha = 0.4 + 0.6 * hole_heat[y][x] as f32 / max_holes;
+                overlay(world, outline.clone(), coord_to_transform((x, y), block_size), (0.2, 0.5, 1., alpha));
+            }
+        }
+    }
+}
+
+// a straight line between two points in world units
+fn segment(world: &mut World, sprite: SpriteRender, from: (f32, f32), to: (f32, f32), block_size: f32, color: (f32, f32, f32, f32)) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new((dx * dx + dy * dy).sqrt() / block_size, LINE_WIDTH, 1.));
+    transform.set_rotation_2d(dy.atan2(dx));
+    transform.set_translation_xyz((from.0 + to.0) / 2., (from.1 + to.1) / 2., GRAPH_Z);
+    world.create_entity()
+        .with(transform)
+        .with(sprite)
+        .with(Tint(Srgba::new(color.0, color.1, color.2, color.3)))
+        .with(Transparent)
+        .build();
+}
+
+// line chart of (time, value) points over the game, `bottom` in blocks
+fn line_chart(world: &mut World, sprite: &SpriteRender, points: &[(f32, f32)], bottom: f32, color: (f32, f32, f32, f32)) {
+    let block_size = world.read_resource::<GameSettings>().block_size;
+    let board_width = world.read_resource::<GameRules>().board_width as f32;
+    let elapsed = points.last().map_or(0., |&(time, _)| time).max(0.001);
+    let max_value = points.iter().map(|&(_, value)| value).fold(0., f32::max).max(0.001);
+
+    let (left, bottom) = ((board_width + 0.5) * block_size, bottom * block_size);
+    let (width, height) = (GRAPH_WIDTH * block_size, GRAPH_HEIGHT * block_size);
+    let mut background = Transform::default();
+    background.set_scale(Vector3::new(GRAPH_WIDTH, GRAPH_HEIGHT, 1.));
+    background.set_translation_xyz(left + width / 2., bottom + height / 2., 0.);
+    overlay(world, sprite.clone(), background, (0., 0., 0., 0.8));
+
+    let to_world = |&(time, value): &(f32, f32)| (left + width * time / elapsed, bottom + height * value / max_value);
+    segment(world, sprite.clone(), (left, bottom), (left + width, bottom), block_size, (1., 1., 1., 0.3));
+    for pair in points.windows(2) {
+        segment(world, sprite.clone(), to_world(&pair[0]), to_world(&pair[1]), block_size, color);
+    }
+}
+
+// the seconds every piece was in play below and the pieces per second so far above, both over
+// the time of the game, next to the board
+fn show_handling_graph(world: &mut World) {
+    let (durations, pps) = {
+        let stats = world.read_resource::<SessionStats>();
+        let durations = stats.placements.iter().map(|placement| (placement.time, placement.duration)).collect::<Vec<_>>();
+        (durations, stats.pps_over_time())
+    };
+    if durations.len() < 2 {
+        return;
+    }
+    let white = world.read_resource::<BlockSprites>().sprites[WHITE_SPRITE].clone();
+
+    line_chart(world, &white, &durations, 1., (1., 0.6, 0.2, 1.));
+    line_chart(world, &white, &pps, 2. + GRAPH_HEIGHT, (0.3, 0.8, 1., 1.));
+    let slowest = durations.iter().map(|&(_, duration)| duration).fold(0., f32::max);
+    let peak = pps.iter().map(|&(_, pps)| pps).fold(0., f32::max);
+    let text = world.read_resource::<Locale>().text("results_graph", &[&format!("{:.2}", slowest), &format!("{:.2}", peak)]);
+    info!("{}", text);
+}
+
+// the highest the stack got in every second of the game, above the handling graph
+fn show_stack_graph(world: &mut World) {
+    let heights = world.read_resource::<SessionStats>()
+        .stack_heights
+        .iter()
+        .enumerate()
+        .map(|(second, &height)| (second as f32, height as f32))
+        .collect::<Vec<_>>();
+    if heights.len() < 2 {
+        return;
+    }
+    let white = world.read_resource::<BlockSprites>().sprites[WHITE_SPRITE].clone();
+
+    line_chart(world, &white, &heights, 3. + 2. * GRAPH_HEIGHT, (1., 0.3, 0.3, 1.));
+    let peak = heights.iter().map(|&(_, height)| height).fold(0., f32::max);
+    let text = world.read_resource::<Locale>().text("results_stack_graph", &[&peak]);
+    info!("{}", text);
+}
@@ -0,0 +1,196 @@
+use amethyst::{
+    config::Config,
+    core::{timing::Time, SystemDesc},
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, WorldExt, Write},
+    shrev::{EventChannel, ReaderId},
+    utils::application_root_dir,
+};
+
+use log::info;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::audio::MUSIC_STEMS;
+use crate::events::GameEvent;
+use crate::locale::Locale;
+use crate::mode::GameMode;
+use crate::rules::GameRules;
+use crate::state::Score;
+
+pub const BEAT_MAP_PATH: &str = "resources/audio/music/beats.ron";
+const DEFAULT_BPM: f32 = 120.; // without music or an entry for it in the beat map
+const ON_BEAT_WINDOW: f32 = 0.08; // seconds either side of a beat that still count as on it
+const ON_BEAT_POINTS: u32 = 25; // times the streak of hard drops on the beat
+const MAX_STREAK: u32 = 8;
+
+// the tempo of every music track by file name, a track that changes tempo lists every change
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BeatMap {
+    pub tracks: BTreeMap<String, Vec<(f32, f32)>>, // beats per minute from the given second of the track, ascending
+}
+
+// where the music is in its loop, the music system starts it over whenever it queues the loop again
+pub struct BeatClock {
+    pub position: f32, // seconds into the loop
+    tempo: Vec<(f32, f32)>,
+    pub ticked: bool, // a beat fell in the last frame
+}
+
+impl BeatClock {
+    // the tempo of the first stem, the others play along with it
+    pub fn load() -> Self {
+        let track = Path::new(MUSIC_STEMS[0]).file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+        let tempo = application_root_dir()
+            .map(|root| BeatMap::load(root.join(BEAT_MAP_PATH)))
+            .ok()
+            .and_then(|map| map.tracks.get(&track).cloned())
+            .filter(|tempo| !tempo.is_empty())
+            .unwrap_or_else(|| vec![(0., DEFAULT_BPM)]);
+        Self { position: 0., tempo, ticked: false }
+    }
+
+    // beats since the start of the loop, a fraction between two beats
+    fn beats_at(&self, position: f32) -> f32 {
+        let mut beats = 0.;
+        for (i, &(start, bpm)) in self.tempo.iter().enumerate() {
+            if position <= start {
+                break;
+            }
+            let end = self.tempo.get(i + 1).map_or(position, |&(next, _)| next.min(position));
+            beats += (end - start) * bpm / 60.;
+        }
+        beats
+    }
+
+    fn bpm_at(&self, position: f32) -> f32 {
+        self.tempo.iter().take_while(|&&(start, _)| start <= position).last().map_or(DEFAULT_BPM, |&(_, bpm)| bpm)
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        let before = self.beats_at(self.position).floor();
+        self.position += seconds;
+        self.ticked = self.beats_at(self.position).floor() > before;
+    }
+
+    // the loop starts over on a beat
+    pub fn restart(&mut self) {
+        self.position = 0.;
+        self.ticked = true;
+    }
+
+    // seconds to the closest beat, before or after
+    pub fn off_beat(&self) -> f32 {
+        let fraction = self.beats_at(self.position).fract();
+        fraction.min(1. - fraction) * 60. / self.bpm_at(self.position)
+    }
+}
+
+impl Default for BeatClock {
+    fn default() -> Self {
+        Self { position: 0., tempo: vec![(0., DEFAULT_BPM)], ticked: false }
+    }
+}
+
+#[derive(Default, SystemDesc)]
+pub struct BeatClockSystem;
+
+impl<'s> System<'s> for BeatClockSystem {
+    type SystemData = (
+        Write<'s, BeatClock>,
+        Read<'s, Time>,
+    );
+
+    // game time, so the clock stands still through a pause like the music does
+    fn run(&mut self, (mut clock, time): Self::SystemData) {
+        clock.advance(time.delta_seconds());
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RhythmScore {
+    pub hard_drops: u32,
+    pub on_beat: u32,
+    pub streak: u32,
+}
+
+// pieces fall a row on every beat and lock on a beat, hard drops on the beat score a bonus that grows
+// with the streak of them
+pub struct RhythmMode;
+
+impl GameMode for RhythmMode {
+    fn name(&self) -> &str {
+        "rhythm"
+    }
+
+    fn rules(&self) -> GameRules {
+        GameRules {
+            name: "rhythm",
+            on_beat: true,
+            ..GameRules::tetrus()
+        }
+    }
+
+    fn setup(&self, world: &mut World) {
+        world.insert(RhythmScore::default());
+    }
+
+    fn hud(&self, world: &World) -> Vec<String> {
+        let score = world.read_resource::<RhythmScore>();
+        vec![world.read_resource::<Locale>().text("rhythm_drops", &[&score.on_beat, &score.hard_drops])]
+    }
+
+    fn results(&self, world: &World) {
+        for line in self.hud(world) {
+            info!("{}", line);
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(RhythmScoreSystemDesc))]
+pub struct RhythmScoreSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl RhythmScoreSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for RhythmScoreSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, GameRules>,
+        Read<'s, BeatClock>,
+        Write<'s, RhythmScore>,
+        Write<'s, Score>,
+        Read<'s, Locale>,
+    );
+
+    fn run(&mut self, (events, rules, clock, mut rhythm, mut score, locale): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            if !rules.on_beat {
+                continue;
+            }
+            if let GameEvent::HardDrop { .. } = event {
+                rhythm.hard_drops += 1;
+                if clock.off_beat() > ON_BEAT_WINDOW {
+                    rhythm.streak = 0;
+                    continue;
+                }
+                rhythm.on_beat += 1;
+                rhythm.streak += 1;
+                let bonus = ON_BEAT_POINTS * rhythm.streak.min(MAX_STREAK);
+                score.points += bonus;
+                info!("{}", locale.text("rhythm_on_beat", &[&rhythm.streak, &bonus]));
+            }
+        }
+    }
+}
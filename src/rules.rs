@@ -0,0 +1,376 @@
+use log::warn;
+
+use crate::challenge::{today, Challenge, Openers, WeeklyChallenges};
+use crate::gravity::GravityCurve;
+use crate::pieces::PieceSet;
+use crate::settings::MAX_PREVIEW_COUNT;
+use crate::state::{Piece, Rotations, BOARD_HEIGHT, LINES_PER_LEVEL, TETROMINO_ROTATIONS};
+use crate::stats::PIECE_NAMES;
+
+pub const DEFAULT_BOARD_WIDTH: usize = 10;
+pub const MAX_BOARD_WIDTH: usize = 16;
+pub const MAX_START_LEVEL: u32 = 15;
+
+// timed and challenge modes, the board is hidden while paused in every mode
+const COMPETITIVE_PAUSE: PausePolicy = PausePolicy::Limited { pauses: 1, seconds: 30. };
+// same block_idx order as TETROMINO_ROTATIONS: I, L, rev_L, square, T, S, Z
+const SEGA_ROTATIONS: [Rotations; 7] = [
+    // I - lies flat in the second row of its 4x4 box, stands in the third column
+    &[
+        &[(0, 2), (1, 2), (2, 2), (3, 2)],
+        &[(2, 0), (2, 1), (2, 2), (2, 3)],
+    ],
+    // L
+    &[
+        &[(0, 1), (1, 1), (2, 1), (0, 0)],
+        &[(0, 2), (1, 2), (1, 1), (1, 0)],
+        &[(2, 1), (0, 0), (1, 0), (2, 0)],
+        &[(1, 2), (1, 1), (1, 0), (2, 0)],
+    ],
+    // rev_L
+    &[
+        &[(0, 1), (1, 1), (2, 1), (2, 0)],
+        &[(1, 2), (1, 1), (1, 0), (0, 0)],
+        &[(0, 1), (0, 0), (1, 0), (2, 0)],
+        &[(1, 2), (2, 2), (1, 1), (1, 0)],
+    ],
+    // square
+    &[
+        &[(0, 0), (1, 0), (0, 1), (1, 1)],
+    ],
+    // T - spawns pointing down
+    &[
+        &[(0, 1), (1, 1), (2, 1), (1, 0)],
+        &[(1, 0), (1, 1), (1, 2), (0, 1)],
+        &[(0, 0), (1, 0), (2, 0), (1, 1)],
+        &[(1, 0), (1, 1), (1, 2), (2, 1)],
+    ],
+    // S
+    &[
+        &[(1, 1), (2, 1), (0, 0), (1, 0)],
+        &[(0, 2), (0, 1), (1, 1), (1, 0)],
+    ],
+    // Z
+    &[
+        &[(0, 1), (1, 1), (1, 0), (2, 0)],
+        &[(2, 2), (1, 1), (2, 1), (1, 0)],
+    ],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotationSystem {
+    Tetrus, // the tables the pieces are built with, kicks sideways and up
+    Sega, // flat spawns, bottom aligned rotations, no kicks
+}
+
+// offsets tried in order when a rotation doesn't fit in place, the last ones lift the piece
+const TETRUS_KICKS: [(i32, i32); 8] = [(0, 0), (-1, 0), (1, 0), (-2, 0), (2, 0), (0, 1), (-1, 1), (1, 1)];
+
+impl RotationSystem {
+    pub fn kicks(self) -> &'static [(i32, i32)] {
+        match self {
+            RotationSystem::Tetrus => &TETRUS_KICKS,
+            RotationSystem::Sega => &TETRUS_KICKS[..1],
+        }
+    }
+
+    pub fn apply(self, piece: &mut Piece) {
+        match self {
+            RotationSystem::Tetrus => {},
+            RotationSystem::Sega => {
+                piece.relative_coords = SEGA_ROTATIONS[piece.block_idx];
+                piece.idx = 0;
+            },
+        }
+    }
+
+    // the rotations a tetromino is dealt with, None past the seven of them
+    pub fn rotations(self, block_idx: usize) -> Option<Rotations> {
+        match self {
+            RotationSystem::Tetrus => TETROMINO_ROTATIONS.get(block_idx).copied(),
+            RotationSystem::Sega => SEGA_ROTATIONS.get(block_idx).copied(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Randomizer {
+    Bag, // every piece once per bag of seven
+    Classic, // every piece drawn independently
+    Bags(usize), // that many sets shuffled together into one bag
+    Weighted(Vec<u32>), // drawn independently with these odds by block_idx, missing ones at 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WinCondition {
+    Endless, // played until the stack tops out
+    Lines(u32), // finished once this many lines are cleared
+}
+
+// how often and how long the game may be paused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PausePolicy {
+    Free,
+    Limited { pauses: u32, seconds: f32 }, // the game resumes by itself once the time is up
+    Off,
+}
+
+// everything a mode decides about how the game is played, a preset is a new constructor
+#[derive(Clone, Debug)]
+pub struct GameRules {
+    pub name: &'static str,
+    pub rotation_system: RotationSystem,
+    pub randomizer: Randomizer,
+    pub gravity: GravityCurve, // rows per second by level, from resources/gravity
+    pub lock_delay: f32, // seconds a piece may rest on the stack before it settles
+    pub lock_resets: u32, // moves and rotations on the stack that restart the lock delay
+    pub line_scores: [u32; 5], // points awarded by number of lines cleared at once
+    pub drop_scores: [u32; 2], // points per cell soft dropped and hard dropped
+    pub preview_count: usize, // most upcoming pieces the mode reveals
+    pub win_condition: WinCondition,
+    pub seed: Option<u64>, // fixed piece sequence, random every game if None
+    pub sequence: Vec<usize>, // block_idx of pieces dealt before the randomizer takes over
+    pub starting_board: Vec<Vec<Option<usize>>>, // block_idx of every cell, bottom line first
+    pub challenge: Option<String>, // results are kept per challenge
+    pub combo_drill: bool, // the starting board is rebuilt whenever a placement breaks the combo
+    pub targets: Vec<Vec<(usize, usize)>>, // cells each piece of the sequence should lock in, for opener practice
+    pub practice: bool, // training aids like the perfect clear solver are on
+    pub finesse: bool, // pieces placed with more inputs than needed go back to spawn
+    pub piece_set: Option<PieceSet>, // dealt instead of the tetrominoes
+    pub board_width: usize, // columns, up to MAX_BOARD_WIDTH
+    pub visible_rows: usize, // rows shown from the bottom, up to BOARD_HEIGHT, pieces above them fade out
+    pub players: usize, // pieces falling at once, each steered by its own player
+    pub on_beat: bool, // gravity steps and locks wait for the beat of the music
+    pub pause: PausePolicy,
+    pub start_level: u32, // levels counted before the first line, up to MAX_START_LEVEL
+    pub level_select: bool, // the player picks start_level before the first piece
+    pub level_scoring: bool, // line scores times the level plus one
+}
+
+impl GameRules {
+    pub fn tetrus() -> Self {
+        Self {
+            name: "tetrus",
+            rotation_system: RotationSystem::Tetrus,
+            randomizer: Randomizer::Bag,
+            gravity: GravityCurve::load("tetrus"),
+            lock_delay: 0.5,
+            lock_resets: 15,
+            line_scores: [0, 100, 300, 500, 800],
+            drop_scores: [1, 2],
+            preview_count: MAX_PREVIEW_COUNT,
+            win_condition: WinCondition::Endless,
+            seed: None,
+            sequence: vec![],
+            starting_board: vec![],
+            challenge: None,
+            combo_drill: false,
+            targets: vec![],
+            practice: false,
+            finesse: false,
+            piece_set: None,
+            board_width: DEFAULT_BOARD_WIDTH,
+            visible_rows: BOARD_HEIGHT,
+            players: 1,
+            on_beat: false,
+            pause: PausePolicy::Free,
+            start_level: 0,
+            level_select: false,
+            level_scoring: false,
+        }
+    }
+
+    pub fn sega() -> Self {
+        Self {
+            name: "sega",
+            rotation_system: RotationSystem::Sega,
+            randomizer: Randomizer::Classic,
+            gravity: GravityCurve::load("sega"),
+            lock_delay: 0.5, // 30 frames
+            lock_resets: 0,
+            line_scores: [0, 100, 400, 900, 2000],
+            drop_scores: [0, 0], // only lines score
+            preview_count: 1,
+            win_condition: WinCondition::Endless,
+            seed: None,
+            sequence: vec![],
+            starting_board: vec![],
+            challenge: None,
+            combo_drill: false,
+            targets: vec![],
+            practice: false,
+            finesse: false,
+            piece_set: None,
+            board_width: DEFAULT_BOARD_WIDTH,
+            visible_rows: BOARD_HEIGHT,
+            players: 1,
+            on_beat: false,
+            pause: PausePolicy::Free,
+            start_level: 0,
+            level_select: false,
+            level_scoring: false,
+        }
+    }
+
+    pub fn sprint() -> Self {
+        Self {
+            name: "sprint",
+            win_condition: WinCondition::Lines(40),
+            pause: COMPETITIVE_PAUSE,
+            ..Self::tetrus()
+        }
+    }
+
+    // the same pieces for everyone playing on the same day
+    pub fn daily() -> Self {
+        let day = today();
+        Self {
+            name: "daily",
+            seed: Some(day),
+            challenge: Some(format!("daily {}", day)),
+            pause: COMPETITIVE_PAUSE,
+            ..Self::tetrus()
+        }
+    }
+
+    // this week's curated sequence and board from resources/challenges/weekly.ron
+    pub fn weekly() -> Self {
+        match WeeklyChallenges::current() {
+            Some((week, challenge)) => Self {
+                name: "weekly",
+                sequence: challenge.pieces(),
+                starting_board: challenge.board(),
+                randomizer: challenge.randomizer(),
+                challenge: Some(format!("weekly {} {}", week, challenge.name)),
+                pause: COMPETITIVE_PAUSE,
+                ..Self::tetrus()
+            },
+            None => {
+                warn!("No weekly challenge available, playing regular tetrus rules");
+                Self {
+                    name: "weekly",
+                    ..Self::tetrus()
+                }
+            },
+        }
+    }
+
+    // combo practice in a 4 wide well with three residue blocks, from resources/practice/4wide.ron
+    pub fn four_wide() -> Self {
+        let setup = Challenge::load_practice("4wide").unwrap_or_default();
+        Self {
+            name: "4wide",
+            sequence: setup.pieces(),
+            starting_board: setup.board(),
+            randomizer: setup.randomizer(),
+            combo_drill: true,
+            practice: true,
+            ..Self::tetrus()
+        }
+    }
+
+    // an opener from resources/practice/openers.ron dealt in its bag order, with its placements shown
+    pub fn opener(name: &str) -> Self {
+        match Openers::find(name) {
+            Some(opener) => Self {
+                name: "opener",
+                sequence: opener.pieces(),
+                targets: opener.targets(),
+                practice: true,
+                ..Self::tetrus()
+            },
+            None => Self {
+                name: "opener",
+                practice: true,
+                ..Self::tetrus()
+            },
+        }
+    }
+
+    pub fn finesse() -> Self {
+        Self {
+            name: "finesse",
+            finesse: true,
+            ..Self::tetrus()
+        }
+    }
+
+    // the 18 one sided pentominoes on a board two columns wider
+    pub fn pentomino() -> Self {
+        Self {
+            name: "pentomino",
+            piece_set: PieceSet::load("pentomino"),
+            board_width: 12,
+            ..Self::tetrus()
+        }
+    }
+
+    pub fn tromino() -> Self {
+        Self {
+            name: "tromino",
+            piece_set: PieceSet::load("tromino"),
+            ..Self::tetrus()
+        }
+    }
+
+    // two players with a piece each on a board wide enough for both
+    pub fn coop() -> Self {
+        Self {
+            name: "coop",
+            board_width: MAX_BOARD_WIDTH,
+            players: 2,
+            ..Self::tetrus()
+        }
+    }
+
+    // constructors by name, only the chosen preset is built since some of them read files,
+    // the opener is a mode of its own since it takes the opener's name
+    pub fn presets() -> [(&'static str, fn() -> Self); 10] {
+        [
+            ("tetrus", Self::tetrus),
+            ("sega", Self::sega),
+            ("sprint", Self::sprint),
+            ("daily", Self::daily),
+            ("weekly", Self::weekly),
+            ("4wide", Self::four_wide),
+            ("finesse", Self::finesse),
+            ("pentomino", Self::pentomino),
+            ("tromino", Self::tromino),
+            ("coop", Self::coop),
+        ]
+    }
+
+    pub fn piece_name(&self, block_idx: usize) -> String {
+        match &self.piece_set {
+            Some(set) => set.pieces.get(block_idx).map_or(String::new(), |piece| piece.name.clone()),
+            None => PIECE_NAMES.get(block_idx).map_or(String::new(), |name| name.to_string()),
+        }
+    }
+
+    pub fn level(&self, lines: u32) -> u32 {
+        self.start_level + lines / LINES_PER_LEVEL
+    }
+
+    pub fn drop_speed(&self, lines: u32) -> f32 {
+        self.gravity.speed(self.level(lines))
+    }
+
+    // points for clearing that many lines at once with the lines cleared before
+    pub fn line_score(&self, cleared: usize, lines: u32) -> u32 {
+        let multiplier = if self.level_scoring { self.level(lines) + 1 } else { 1 };
+        self.line_scores[cleared.min(4)] * multiplier
+    }
+
+    pub fn is_won(&self, lines: u32) -> bool {
+        match self.win_condition {
+            WinCondition::Endless => false,
+            WinCondition::Lines(target) => lines >= target,
+        }
+    }
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self::tetrus()
+    }
+}
@@ -0,0 +1,130 @@
+use amethyst::{
+    config::Config,
+    input::{is_close_requested, is_key_down, VirtualKeyCode},
+    prelude::*,
+    utils::application_root_dir,
+};
+
+use log::{error, info};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+
+use crate::hud::{clear_popup, show_popup};
+use crate::locale::Locale;
+use crate::profile;
+
+pub const AUTOSAVE_PATH: &str = "autosave.ron";
+pub const AUTOSAVE_INTERVAL: f32 = 10.; // seconds between checkpoints
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SavedGame {
+    pub rules: String,
+    #[serde(default)]
+    pub mode: String, // the mode the game was played in, its rules name for older saves
+    #[serde(default)]
+    pub args: Vec<String>, // and the arguments that picked its variant, see GameMode::args
+    pub board: Vec<Vec<Option<usize>>>, // block_idx of every cell, bottom line first
+    pub current_bag: Vec<usize>, // block_idx of the upcoming pieces, the one in play first
+    pub next_bag: Vec<usize>,
+    #[serde(default)]
+    pub seed: Option<u64>, // piece sequence seed of a daily game
+    #[serde(default)]
+    pub bags: u32, // bags the generator had drawn, replayed from the seed on resume
+    #[serde(default)]
+    pub challenge: Option<String>, // daily or weekly challenge the game counts towards
+    #[serde(default)]
+    pub start_level: u32,
+    pub points: u32,
+    pub lines: u32,
+    #[serde(default)]
+    pub went_back: bool, // rewound or quick loaded before it was saved, no personal best
+}
+
+impl SavedGame {
+    pub fn load() -> Option<Self> {
+        let path = profile::path(&application_root_dir().ok()?, AUTOSAVE_PATH);
+        if !path.exists() {
+            return None;
+        }
+
+        match Self::load_no_fallback(&path) {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                error!("Failed to load autosave: {}", e);
+                None
+            },
+        }
+    }
+
+    // written next to the autosave and renamed over it, a crash while writing keeps the last one
+    pub fn save(&self) {
+        let result = application_root_dir().map_err(Into::into).and_then(|root| {
+            let path = profile::path(&root, AUTOSAVE_PATH);
+            let written = path.with_extension("ron.tmp");
+            self.write(&written).and_then(|()| fs::rename(&written, &path).map_err(Into::into))
+        });
+        if let Err(e) = result {
+            error!("Failed to write autosave: {}", e);
+        }
+    }
+
+    pub fn mode_name(&self) -> &str {
+        if self.mode.is_empty() { &self.rules } else { &self.mode }
+    }
+
+    pub fn remove() {
+        if let Ok(root) = application_root_dir() {
+            fs::remove_file(profile::path(&root, AUTOSAVE_PATH)).ok();
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResumeChoice {
+    Resume,
+    Discard,
+}
+
+impl Default for ResumeChoice {
+    fn default() -> Self {
+        ResumeChoice::Resume
+    }
+}
+
+// pushed on top of the game while a restored board waits for the player to decide
+pub struct ResumePromptState;
+
+impl SimpleState for ResumePromptState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let prompt = data.world.read_resource::<Locale>().text("resume_prompt", &[]);
+        info!("{}", prompt);
+        show_popup(data.world, prompt, None);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        clear_popup(data.world);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            if is_close_requested(&event) {
+                return Trans::Quit;
+            }
+            if is_key_down(&event, VirtualKeyCode::Return) {
+                data.world.insert(ResumeChoice::Resume);
+                return Trans::Pop;
+            }
+            if is_key_down(&event, VirtualKeyCode::Escape) {
+                data.world.insert(ResumeChoice::Discard);
+                return Trans::Pop;
+            }
+        }
+        Trans::None
+    }
+}
@@ -0,0 +1,190 @@
+use amethyst::{
+    core::math::Vector3,
+    core::transform::Transform,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Entities, Join, Read, ReadExpect, ReadStorage, System, SystemData, World, WriteStorage},
+    input::{InputHandler, StringBindings},
+    renderer::{resources::Tint, transparent::Transparent, SpriteRender},
+    renderer::palette::Srgba,
+    utils::application_root_dir,
+};
+
+use image::{imageops, Rgba, RgbaImage};
+
+use log::{error, info};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::effects::{BlockSprites, Fade, WHITE_SPRITE};
+use crate::rules::GameRules;
+use crate::settings::GameSettings;
+use crate::state::{Block, BOARD_HEIGHT};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+const CELL_PIXELS: u32 = 16;
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const GRID: [u8; 4] = [24, 24, 24, 255];
+const TEXT: [u8; 4] = [255, 255, 255, 255];
+const TEXT_SCALE: u32 = 2; // pixels per dot of the font
+const TEXT_MARGIN: u32 = 8; // pixels around the score below the board
+const FLASH_LIFETIME: f32 = 0.2;
+const FLASH_ALPHA: f32 = 0.4;
+const FLASH_Z: f32 = 0.6; // over everything on the board
+
+fn channel(value: f32) -> u8 {
+    (value.max(0.).min(1.) * 255.).round() as u8
+}
+
+// the board drawn again cell by cell, there is no way to read the window back
+pub fn render_board(cells: &[((usize, usize), Srgba)], board_width: usize) -> RgbaImage {
+    let (width, height) = (board_width as u32 * CELL_PIXELS, BOARD_HEIGHT as u32 * CELL_PIXELS);
+    let mut image = RgbaImage::from_pixel(width, height, Rgba(BACKGROUND));
+    for (px, py, pixel) in image.enumerate_pixels_mut() {
+        if px % CELL_PIXELS == 0 || py % CELL_PIXELS == 0 {
+            *pixel = Rgba(GRID);
+        }
+    }
+
+    for &((x, y), color) in cells {
+        if x >= board_width || y >= BOARD_HEIGHT {
+            continue;
+        }
+        let fill = Rgba([channel(color.red), channel(color.green), channel(color.blue), 255]);
+        let top = (BOARD_HEIGHT - 1 - y) as u32 * CELL_PIXELS; // images count rows from the top
+        for py in top + 1..top + CELL_PIXELS {
+            for px in x as u32 * CELL_PIXELS + 1..(x as u32 + 1) * CELL_PIXELS {
+                image.put_pixel(px, py, fill);
+            }
+        }
+    }
+    image
+}
+
+// 3 by 5 dots for the characters in the score, a row in the lowest three bits from the left
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' | 'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' | 'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        _ => [0; 5],
+    }
+}
+
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * 4 * TEXT_SCALE
+}
+
+fn draw_text(image: &mut RgbaImage, text: &str, left: u32, top: u32) {
+    for (i, c) in text.chars().enumerate() {
+        let x = left + i as u32 * 4 * TEXT_SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..TEXT_SCALE {
+                    for dx in 0..TEXT_SCALE {
+                        image.put_pixel(x + column * TEXT_SCALE + dx, top + row as u32 * TEXT_SCALE + dy, Rgba(TEXT));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the final board with the points and lines below it, to share a game as one image
+pub fn render_snapshot(cells: &[((usize, usize), Srgba)], board_width: usize, points: u32, lines: u32) -> RgbaImage {
+    let board = render_board(cells, board_width);
+    let text = [format!("SCORE {}", points), format!("LINES {}", lines)];
+    let line_height = 6 * TEXT_SCALE;
+    let width = text.iter().map(|line| text_width(line) + 2 * TEXT_MARGIN).max().unwrap_or(0).max(board.width());
+    let height = board.height() + 2 * TEXT_MARGIN + text.len() as u32 * line_height;
+    let mut image = RgbaImage::from_pixel(width, height, Rgba(BACKGROUND));
+    imageops::overlay(&mut image, &board, (width - board.width()) / 2, 0);
+    for (i, line) in text.iter().enumerate() {
+        draw_text(&mut image, line, TEXT_MARGIN, board.height() + TEXT_MARGIN + i as u32 * line_height);
+    }
+    image
+}
+
+fn save(image: &RgbaImage, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let path = dir.join(format!("tetrus-{}.png", timestamp));
+    image.save(&path).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    Ok(path)
+}
+
+// saves the board as a png on the "screenshot" action (F12) and flashes it as confirmation outside of streamer mode
+#[derive(Default, SystemDesc)]
+pub struct ScreenshotSystem {
+    screenshot_held: bool,
+}
+
+impl<'s> System<'s> for ScreenshotSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadStorage<'s, Block>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        ReadExpect<'s, BlockSprites>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Fade>,
+    );
+
+    fn run(&mut self, (entities, input, blocks, rules, settings, block_sprites, mut transforms, mut sprites, mut tints, mut transparents, mut fades): Self::SystemData) {
+        let pressed = input.action_is_down("screenshot").unwrap_or(false);
+        let taken = pressed && !self.screenshot_held;
+        self.screenshot_held = pressed;
+        if !taken {
+            return;
+        }
+
+        let cells = (&blocks, &tints).join().map(|(block, tint)| (block.coord, tint.0)).collect::<Vec<_>>();
+        let image = render_board(&cells, rules.board_width);
+        match application_root_dir().and_then(|root| save(&image, &root.join(SCREENSHOT_DIR))) {
+            Ok(path) => info!("Saved screenshot to {:?}", path),
+            Err(e) => {
+                error!("Failed to save screenshot: {}", e);
+                return;
+            },
+        }
+
+        if !settings.flashes() {
+            return;
+        }
+        let board_size = (rules.board_width as f32 * settings.block_size, BOARD_HEIGHT as f32 * settings.block_size);
+        let mut transform = Transform::default();
+        transform.set_scale(Vector3::new(rules.board_width as f32, BOARD_HEIGHT as f32, 1.));
+        transform.set_translation_xyz(board_size.0 / 2., board_size.1 / 2., FLASH_Z);
+        entities.build_entity()
+            .with(transform, &mut transforms)
+            .with(block_sprites.sprites[WHITE_SPRITE].clone(), &mut sprites)
+            .with(Tint(Srgba::new(1., 1., 1., FLASH_ALPHA)), &mut tints)
+            .with(Transparent, &mut transparents)
+            .with(Fade::new(FLASH_LIFETIME, FLASH_ALPHA), &mut fades)
+            .build();
+    }
+}
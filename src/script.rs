@@ -0,0 +1,175 @@
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, WorldExt, Write},
+    shrev::{EventChannel, ReaderId},
+    utils::application_root_dir,
+};
+
+use log::{error, info};
+
+use rhai::{Engine, EvalAltResult, FuncArgs, ImmutableString, Scope, AST, INT};
+
+use crate::challenge::piece_idx;
+use crate::events::GameEvent;
+use crate::mode::GameMode;
+use crate::rules::GameRules;
+use crate::state::Score;
+use crate::stats::PIECE_NAMES;
+
+pub const SCRIPT_DIR: &str = "resources/scripts";
+pub const DEFAULT_SCRIPT: &str = "tenth_i";
+
+// a rhai script hooked into the game, every hook is optional:
+//   fn on_spawn(piece, count) - returns the letter of the piece to spawn instead
+//   fn on_lock(piece, count)
+//   fn on_clear(lines, combo, total_lines) - returns bonus points
+// functions in rhai can't see variables outside of them, so the counts are passed in
+pub struct RuleScript {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    spawned: INT,
+    locked: INT,
+}
+
+impl RuleScript {
+    // by file name in resources/scripts without the extension
+    pub fn load(name: &str) -> Option<Self> {
+        let path = application_root_dir().ok()?.join(SCRIPT_DIR).join(format!("{}.rhai", name));
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                error!("Failed to read script {:?}: {}", path, e);
+                return None;
+            },
+        };
+
+        let mut engine = Engine::new();
+        let prefix = name.to_string();
+        engine.on_print(move |text| info!("[{}] {}", prefix, text));
+        match engine.compile(&source) {
+            Ok(ast) => Some(Self { name: name.to_string(), engine, ast, spawned: 0, locked: 0 }),
+            Err(e) => {
+                error!("Failed to compile script {:?}: {}", path, e);
+                None
+            },
+        }
+    }
+
+    // None if the script doesn't define the hook or it failed
+    fn call<T: Clone + Send + Sync + 'static>(&self, hook: &str, args: impl FuncArgs) -> Option<T> {
+        match self.engine.call_fn::<_, T>(&mut Scope::new(), &self.ast, hook, args) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                match e.as_ref() {
+                    EvalAltResult::ErrorFunctionNotFound(..) => {},
+                    _ => error!("Script {} failed in {}: {}", self.name, hook, e),
+                }
+                None
+            },
+        }
+    }
+
+    // the block_idx to spawn in place of the dealt piece
+    pub fn on_spawn(&mut self, block_idx: usize) -> usize {
+        let dealt = PIECE_NAMES[block_idx].to_string();
+        let chosen = self.call::<ImmutableString>("on_spawn", (dealt, self.spawned));
+        self.spawned += 1;
+        chosen
+            .and_then(|letter| letter.chars().next().and_then(piece_idx))
+            .unwrap_or(block_idx)
+    }
+
+    pub fn on_lock(&mut self, block_idx: usize) {
+        self.call::<()>("on_lock", (PIECE_NAMES[block_idx].to_string(), self.locked));
+        self.locked += 1;
+    }
+
+    pub fn on_clear(&self, lines: usize, combo: u32, total_lines: u32) -> u32 {
+        self.call::<INT>("on_clear", (lines as INT, combo as INT, total_lines as INT))
+            .map_or(0, |bonus| bonus.max(0) as u32)
+    }
+}
+
+// the script of the game, if it has one
+#[derive(Default)]
+pub struct ActiveScript(pub Option<RuleScript>);
+
+// tetrus rules with a script on top, the preview shows the pieces before the script swaps them
+pub struct ScriptMode {
+    script: String,
+}
+
+impl ScriptMode {
+    pub fn new(script: &str) -> Self {
+        Self { script: script.to_string() }
+    }
+}
+
+impl GameMode for ScriptMode {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec![self.script.clone()]
+    }
+
+    fn rules(&self) -> GameRules {
+        GameRules {
+            name: "script",
+            ..GameRules::tetrus()
+        }
+    }
+
+    fn setup(&self, world: &mut World) {
+        world.insert(ActiveScript(RuleScript::load(&self.script)));
+    }
+
+    fn best_name(&self, _rules: &GameRules) -> Option<String> {
+        Some(format!("script {}", self.script))
+    }
+}
+
+// runs the lock and clear hooks, bonus points go straight to the score
+#[derive(SystemDesc)]
+#[system_desc(name(ScriptSystemDesc))]
+pub struct ScriptSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl ScriptSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for ScriptSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Write<'s, ActiveScript>,
+        Write<'s, Score>,
+    );
+
+    fn run(&mut self, (events, mut active, mut score): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            let script = match active.0.as_mut() {
+                Some(script) => script,
+                None => continue,
+            };
+            match event {
+                GameEvent::PieceLocked { block_idx, .. } => script.on_lock(*block_idx),
+                GameEvent::LinesCleared { lines, combo, .. } => {
+                    let bonus = script.on_clear(*lines, *combo, score.lines);
+                    if bonus > 0 {
+                        score.points += bonus;
+                        info!("Script bonus of {} points", bonus);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use amethyst::{
+    config::Config,
+    core::frame_limiter::FrameRateLimitStrategy,
+    input::{InputHandler, StringBindings},
+    utils::application_root_dir,
+};
+
+use log::error;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+use crate::gamepad;
+use crate::profile;
+
+pub const SETTINGS_PATH: &str = "resources/settings.ron";
+pub const BINDINGS_PATH: &str = "resources/bindings.ron";
+pub const DISPLAY_CONFIG_PATH: &str = "resources/display_config.ron";
+
+pub const MAX_PREVIEW_COUNT: usize = 6;
+pub const MAX_PIECE_HISTORY: usize = 8;
+
+// built in handling preset, the empty name stands for `handling` itself
+pub const ZERO_DELAY_PRESET: &str = "zero_delay";
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 3.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum GhostStyle {
+    Off,
+    Outline,
+    Translucent,
+}
+
+// where the playfield sits in the window
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Framing {
+    Centered,
+    Left, // against the left edge, the rest of the window is left to the score and stats on the right
+}
+
+// what drifts behind the playfield
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Ambience {
+    Off,
+    Stars, // drift up slowly and fade
+    Snow, // falls and sways
+}
+
+// what a player steers with
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Device {
+    Keyboard, // the player's bindings, with a p2_ prefix for the second player and so on
+    Gamepad(u32), // by the number it got when it was plugged in, with the layout in gamepad.rs
+}
+
+// what the perfect clear solver tells in practice modes
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum PcHint {
+    Off,
+    Possible, // only whether the queue can perfect clear
+    Solution, // the placements as well
+}
+
+// what the "up" binding does in the game
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum UpAction {
+    HardDrop,
+    Rotate,
+    Nothing,
+}
+
+impl UpAction {
+    fn action(self) -> Option<&'static str> {
+        match self {
+            UpAction::HardDrop => Some("hard_drop"),
+            UpAction::Rotate => Some("rotate"),
+            UpAction::Nothing => None,
+        }
+    }
+}
+
+// which way the piece goes while left and right are both held
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum BothDirections {
+    Neutral, // neither
+    LastPressed,
+    FirstPressed,
+}
+
+// timings of the piece controls, in seconds
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Handling {
+    pub das: f32, // left or right held this long starts repeating sideways steps
+    pub move_repeat: f32, // between the repeated sideways steps, 0 moves straight to the wall
+    pub preserve_das: bool, // a charged direction carries over to the next piece instead of starting over
+    pub both_directions: BothDirections,
+    pub rotate_repeat: f32, // between rotations while up is held
+    pub soft_drop_factor: f32, // multiplies the time per row while down is held, 0 drops instantly
+}
+
+impl Handling {
+    // as fast as the controls go, pieces already spawn and lines clear without a delay
+    pub fn zero_delay() -> Self {
+        Self {
+            das: 0.05,
+            move_repeat: 0.,
+            preserve_das: true,
+            both_directions: BothDirections::LastPressed,
+            rotate_repeat: 0.1,
+            soft_drop_factor: 0.,
+        }
+    }
+
+    // None for an instant soft drop
+    pub fn soft_drop_factor(&self) -> Option<f32> {
+        if self.soft_drop_factor > 0. { Some(self.soft_drop_factor.min(1.)) } else { None }
+    }
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das: 0.08,
+            move_repeat: 0.08,
+            preserve_das: true,
+            both_directions: BothDirections::Neutral,
+            rotate_repeat: 0.2,
+            soft_drop_factor: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GameSettings {
+    pub handling: Handling,
+    pub handling_preset: String, // empty for `handling`, zero_delay or a name in handling_presets
+    pub handling_presets: BTreeMap<String, Handling>, // saved with `cargo run -- handling save <name>`
+    pub player_handling: Vec<String>, // preset by player from the second on, players past the end use the first player's
+    pub up_action: UpAction,
+    pub preview_count: usize, // upcoming pieces shown next to the board, 0 to 6
+    pub theme: String, // file name in resources/themes without the extension
+    pub ghost_style: GhostStyle,
+    pub ghost_opacity: f32, // 0 to 1
+    pub pc_hint: PcHint,
+    pub bot: String, // file name in resources/bots without the extension, empty to play yourself
+    pub master_volume: f32, // 0 to 1
+    pub music_volume: f32, // 0 to 1
+    pub sfx_volume: f32, // 0 to 1
+    pub muted: bool,
+    pub export_stats: bool, // write a session report to the stats directory on game end
+    pub gameplay_log: bool, // write every game event as a JSON line to the logs directory
+    pub fps_cap: u32, // frames per second, 0 for no limit
+    pub idle_fps: u32, // frames per second while the window is in the background or the game paused, 0 for fps_cap
+    pub ui_scale: f32, // window size multiplier, 0 to follow the display's hidpi factor
+    pub ambience: Ambience,
+    pub glow: f32, // 0 to 1, a halo around the falling piece and a flash over cleared lines, 0 for none
+    pub render_3d: bool, // cubes under a tilted camera in place of sprites, read on start
+    pub zoom: f32, // 1 fits the playfield to the window, above zooms in and below out
+    pub framing: Framing,
+    pub streamer_mode: bool, // no flashes over the board and the playfield stays put when the window is resized
+    pub pause_on_focus_loss: bool, // alt-tabbing away pauses the game where the mode allows a pause
+    pub reduced_motion: bool, // no flashes, trails, particles or animation, the halo around the piece holds still
+    pub chroma_key: Option<(f32, f32, f32)>, // background color in place of black, read on start
+    pub input_display: bool, // held actions and recent presses below the preview
+    pub das_indicator: bool, // a bar for the charge of the held direction below the preview
+    pub piece_history: usize, // last placed pieces shown as icons beside the preview, 0 to 8
+    pub devices: Vec<Device>, // by player, players past the end play on the keyboard
+    pub rumble: bool, // pulse the gamepad on hard drops and tetrises
+    pub rumble_strength: f32, // 0 to 1
+    pub language: String, // file name in resources/locales without the extension, read on start
+    pub sync_dir: String, // folder shared between machines to keep settings, bindings and bests in step, empty for none
+    #[serde(skip)]
+    pub block_size: f32, // pixels per block, read from the block sprite sheet on start
+}
+
+fn unit(value: f32) -> f32 {
+    value.max(0.).min(1.)
+}
+
+impl GameSettings {
+    // the handling of a preset, `handling` when the preset is unknown
+    fn preset(&self, name: &str) -> Handling {
+        match name {
+            "" => self.handling,
+            ZERO_DELAY_PRESET => Handling::zero_delay(),
+            name => self.handling_presets.get(name).copied().unwrap_or(self.handling),
+        }
+    }
+
+    // the handling of the selected preset
+    pub fn handling(&self) -> Handling {
+        self.preset(&self.handling_preset)
+    }
+
+    // the handling a player's pieces move with, H only switches the first player's
+    pub fn player_handling(&self, player: usize) -> Handling {
+        match player.checked_sub(1).and_then(|i| self.player_handling.get(i)) {
+            Some(name) => self.preset(name),
+            None => self.handling(),
+        }
+    }
+
+    // switches to the preset after the selected one, through `handling`, zero_delay and the saved ones
+    pub fn next_handling_preset(&mut self) {
+        let names: Vec<&str> = ["", ZERO_DELAY_PRESET].iter().copied()
+            .chain(self.handling_presets.keys().map(String::as_str))
+            .collect();
+        let current = names.iter().position(|&name| name == self.handling_preset).unwrap_or(0);
+        self.handling_preset = names[(current + 1) % names.len()].to_string();
+    }
+
+    // stores the current handling under `name` and selects it
+    pub fn save_handling_preset(&mut self, name: &str) -> Result<(), String> {
+        if name.is_empty() || name == ZERO_DELAY_PRESET {
+            return Err(format!("\"{}\" can't be used as a preset name", name));
+        }
+        let handling = self.handling();
+        self.handling_presets.insert(name.to_string(), handling);
+        self.handling_preset = name.to_string();
+        Ok(())
+    }
+
+    pub fn preview_count(&self) -> usize {
+        self.preview_count.min(MAX_PREVIEW_COUNT)
+    }
+
+    pub fn piece_history(&self) -> usize {
+        self.piece_history.min(MAX_PIECE_HISTORY)
+    }
+
+    pub fn ghost_opacity(&self) -> f32 {
+        unit(self.ghost_opacity)
+    }
+
+    pub fn music_volume(&self) -> f32 {
+        if self.muted { 0. } else { unit(self.master_volume) * unit(self.music_volume) }
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        if self.muted { 0. } else { unit(self.master_volume) * unit(self.sfx_volume) }
+    }
+
+    // sleeping rather than yielding between frames keeps the cpu idle when capped,
+    // game logic runs off the frame delta so the cap doesn't change its speed
+    pub fn frame_rate_limit(&self) -> (FrameRateLimitStrategy, u32) {
+        (FrameRateLimitStrategy::Sleep, self.fps_cap)
+    }
+
+    // a lower cap when idle, never above fps_cap
+    pub fn idle_frame_rate_limit(&self) -> (FrameRateLimitStrategy, u32) {
+        match (self.idle_fps, self.fps_cap) {
+            (0, fps_cap) => (FrameRateLimitStrategy::Sleep, fps_cap),
+            (idle_fps, 0) => (FrameRateLimitStrategy::Sleep, idle_fps),
+            (idle_fps, fps_cap) => (FrameRateLimitStrategy::Sleep, idle_fps.min(fps_cap)),
+        }
+    }
+
+    // flashes over the board are left out when streaming and for reduced motion
+    pub fn flashes(&self) -> bool {
+        !self.streamer_mode && !self.reduced_motion
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom.max(MIN_ZOOM).min(MAX_ZOOM)
+    }
+
+    pub fn ui_scale(&self, hidpi_factor: f64) -> f64 {
+        if self.ui_scale > 0. { f64::from(self.ui_scale) } else { hidpi_factor }
+    }
+
+    // game actions are read through here rather than straight from the bindings, "up" stands in
+    // for the action `up_action` names
+    pub fn action_is_down(&self, input: &InputHandler<StringBindings>, action: &str) -> bool {
+        input.action_is_down(action).unwrap_or(false)
+            || (self.up_action.action() == Some(action) && input.action_is_down("up").unwrap_or(false))
+    }
+
+    // the second player's bindings are the first player's with a p2_ prefix, "up" is only the first player's,
+    // a player given a gamepad plays with the gamepad layout on it alone
+    pub fn player_action_is_down(&self, input: &InputHandler<StringBindings>, player: usize, action: &str) -> bool {
+        match self.device(player) {
+            Device::Gamepad(which) => gamepad::action_is_down(input, which, action),
+            Device::Keyboard if player == 0 => self.action_is_down(input, action),
+            Device::Keyboard => input.action_is_down(&format!("p{}_{}", player + 1, action)).unwrap_or(false),
+        }
+    }
+
+    pub fn device(&self, player: usize) -> Device {
+        self.devices.get(player).cloned().unwrap_or(Device::Keyboard)
+    }
+
+    pub fn rumble_strength(&self) -> f32 {
+        unit(self.rumble_strength)
+    }
+
+    pub fn clear_color(&self) -> [f32; 4] {
+        let (red, green, blue) = self.chroma_key.unwrap_or((0., 0., 0.));
+        [unit(red), unit(green), unit(blue), 1.]
+    }
+
+    pub fn save(&self) {
+        let result = application_root_dir()
+            .map_err(Into::into)
+            .and_then(|root| self.write(profile::path(&root, SETTINGS_PATH)));
+        if let Err(e) = result {
+            error!("Failed to save settings: {}", e);
+        }
+    }
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            handling: Handling::default(),
+            handling_preset: String::new(),
+            handling_presets: BTreeMap::new(),
+            player_handling: vec![],
+            up_action: UpAction::Rotate,
+            preview_count: 5,
+            theme: "default".to_string(),
+            ghost_style: GhostStyle::Translucent,
+            ghost_opacity: 0.3,
+            pc_hint: PcHint::Possible,
+            bot: String::new(),
+            master_volume: 1.,
+            music_volume: 0.7,
+            sfx_volume: 1.,
+            muted: false,
+            export_stats: false,
+            gameplay_log: false,
+            fps_cap: 144,
+            idle_fps: 15,
+            ui_scale: 0.,
+            ambience: Ambience::Off,
+            glow: 0.5,
+            render_3d: false,
+            zoom: 1.,
+            framing: Framing::Centered,
+            streamer_mode: false,
+            pause_on_focus_loss: true,
+            reduced_motion: false,
+            chroma_key: None,
+            input_display: false,
+            das_indicator: false,
+            piece_history: 0,
+            devices: vec![Device::Keyboard, Device::Keyboard],
+            rumble: true,
+            rumble_strength: 0.7,
+            language: "en".to_string(),
+            sync_dir: String::new(),
+            block_size: 16.,
+        }
+    }
+}
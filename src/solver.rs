@@ -0,0 +1,279 @@
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, Write},
+};
+
+use log::info;
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::settings::{GameSettings, PcHint};
+use crate::state::Gameboard;
+use crate::stats::PIECE_NAMES;
+
+const MAX_PC_HEIGHT: usize = 4; // lines a perfect clear is searched for, like the usual setups
+const FULL_LINE: u16 = (1 << 10) - 1;
+
+// a piece of the queue with its rotations as the rotation system gives them
+#[derive(Clone, Debug)]
+pub struct QueuedPiece {
+    pub block_idx: usize,
+    pub rotations: Vec<Vec<(usize, usize)>>, // moved to the bottom left corner
+}
+
+impl QueuedPiece {
+    pub fn new(block_idx: usize, rotations: &[&[(usize, usize)]]) -> Self {
+        let rotations = rotations
+            .iter()
+            .map(|cells| {
+                let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+                let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+                let mut moved = cells.to_vec();
+                for cell in moved.iter_mut() {
+                    *cell = (cell.0 - min_x, cell.1 - min_y);
+                }
+                moved
+            })
+            .collect();
+        Self { block_idx, rotations }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub block_idx: usize,
+    pub rotation: usize,
+    pub x: usize, // column of the rotation's left edge
+}
+
+type Solution = Option<Vec<Step>>;
+
+// searches perfect clears on a worker thread, a new request cancels the one before
+#[derive(Default)]
+pub struct PcSolver {
+    pending: Option<(Mutex<Receiver<Solution>>, Arc<AtomicBool>)>,
+}
+
+impl PcSolver {
+    // lines are bitmasks of the filled cells, bottom line first
+    pub fn solve(&mut self, lines: Vec<u16>, queue: Vec<QueuedPiece>) {
+        self.cancel();
+
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let solution = find_pc(lines, &queue, &worker_cancelled);
+            sender.send(solution).ok();
+        });
+        self.pending = Some((Mutex::new(receiver), cancelled));
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some((_, cancelled)) = self.pending.take() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// the board as bitmasks of its filled cells, columns past the standard width are left out
+pub fn board_lines(gameboard: &Gameboard) -> Vec<u16> {
+    let mut lines = gameboard.board
+        .iter()
+        .map(|line| line.iter().take(10).enumerate().filter(|(_, cell)| cell.is_some()).fold(0, |mask, (x, _)| mask | 1 << x))
+        .collect::<Vec<u16>>();
+    while lines.last() == Some(&0) {
+        lines.pop();
+    }
+    lines
+}
+
+fn find_pc(mut lines: Vec<u16>, queue: &[QueuedPiece], cancelled: &AtomicBool) -> Solution {
+    if lines.len() > MAX_PC_HEIGHT {
+        return None;
+    }
+
+    let filled = lines.iter().map(|line| line.count_ones() as usize).sum::<usize>();
+    // the lowest height the queue can fill up, four cells per piece
+    let height = (lines.len().max(1)..=MAX_PC_HEIGHT)
+        .find(|&height| (height * 10 - filled) % 4 == 0 && (height * 10 - filled) / 4 <= queue.len())?;
+    lines.resize(height, 0);
+
+    let mut steps = vec![];
+    let mut visited = HashSet::new();
+    if search(&lines, queue, &mut steps, &mut visited, cancelled) {
+        Some(steps)
+    }
+    else {
+        None
+    }
+}
+
+fn search(
+    lines: &[u16],
+    queue: &[QueuedPiece],
+    steps: &mut Vec<Step>,
+    visited: &mut HashSet<(Vec<u16>, usize)>,
+    cancelled: &AtomicBool,
+) -> bool {
+    if lines.is_empty() {
+        return true;
+    }
+    if queue.is_empty() || cancelled.load(Ordering::Relaxed) || !visited.insert((lines.to_vec(), queue.len())) {
+        return false;
+    }
+
+    let piece = &queue[0];
+    for (rotation, cells) in piece.rotations.iter().enumerate() {
+        let width = cells.iter().map(|&(x, _)| x).max().unwrap_or(0) + 1;
+        for x in 0..=(10 - width) {
+            if let Some(next) = drop_piece(lines, cells, x) {
+                steps.push(Step { block_idx: piece.block_idx, rotation, x });
+                if fillable(&next) && search(&next, &queue[1..], steps, visited, cancelled) {
+                    return true;
+                }
+                steps.pop();
+            }
+        }
+    }
+    false
+}
+
+// drops the piece straight down from above the lines, None if it would stick out of them,
+// full lines are removed from the result
+pub fn drop_piece(lines: &[u16], cells: &[(usize, usize)], x: usize) -> Option<Vec<u16>> {
+    let height = lines.len();
+    let fits = |y: usize| cells.iter().all(|&(dx, dy)| y + dy >= height || lines[y + dy] & 1 << (x + dx) == 0);
+
+    let mut y = height;
+    while y > 0 && fits(y - 1) {
+        y -= 1;
+    }
+    if cells.iter().any(|&(_, dy)| y + dy >= height) {
+        return None;
+    }
+
+    let mut next = lines.to_vec();
+    for &(dx, dy) in cells {
+        next[y + dy] |= 1 << (x + dx);
+    }
+    next.retain(|&line| line != FULL_LINE);
+    Some(next)
+}
+
+// every enclosed empty area has to take whole pieces
+fn fillable(lines: &[u16]) -> bool {
+    let height = lines.len();
+    let mut seen = vec![0u16; height];
+    for y in 0..height {
+        for x in 0..10usize {
+            if lines[y] & 1 << x != 0 || seen[y] & 1 << x != 0 {
+                continue;
+            }
+
+            let mut area = 0;
+            let mut open = vec![(x, y)];
+            seen[y] |= 1 << x;
+            while let Some((cx, cy)) = open.pop() {
+                area += 1;
+                let neighbours = [
+                    (cx.wrapping_sub(1), cy),
+                    (cx + 1, cy),
+                    (cx, cy.wrapping_sub(1)),
+                    (cx, cy + 1),
+                ];
+                for &(nx, ny) in neighbours.iter() {
+                    if nx < 10 && ny < height && lines[ny] & 1 << nx == 0 && seen[ny] & 1 << nx == 0 {
+                        seen[ny] |= 1 << nx;
+                        open.push((nx, ny));
+                    }
+                }
+            }
+            if area % 4 != 0 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// logs the answer of the solver once it is in
+#[derive(Default, SystemDesc)]
+pub struct PcHintSystem;
+
+impl<'s> System<'s> for PcHintSystem {
+    type SystemData = (
+        Write<'s, PcSolver>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (mut solver, settings): Self::SystemData) {
+        let answer = match &solver.pending {
+            Some((receiver, _)) => match receiver.lock().map(|receiver| receiver.try_recv()) {
+                Ok(Err(TryRecvError::Empty)) => return,
+                Ok(Ok(solution)) => solution,
+                _ => None,
+            },
+            None => return,
+        };
+        solver.pending = None;
+
+        match answer {
+            Some(steps) => {
+                info!("Perfect clear possible");
+                if settings.pc_hint == PcHint::Solution {
+                    for step in steps {
+                        info!("  {} rotation {} at column {}", PIECE_NAMES[step.block_idx], step.rotation, step.x + 1);
+                    }
+                }
+            },
+            None => info!("No perfect clear with this queue"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const I_FLAT: [(usize, usize); 4] = [(0, 0), (1, 0), (2, 0), (3, 0)];
+    const I_STANDING: [(usize, usize); 4] = [(0, 0), (0, 1), (0, 2), (0, 3)];
+
+    fn i_piece() -> QueuedPiece {
+        QueuedPiece::new(0, &[&I_FLAT, &I_STANDING])
+    }
+
+    // the bottom line with the four columns on the left open
+    const LEFT_OPEN: u16 = FULL_LINE & !0b1111;
+
+    #[test]
+    fn finds_pc_for_a_solvable_queue() {
+        let steps = find_pc(vec![LEFT_OPEN], &[i_piece()], &AtomicBool::new(false)).expect("an I clears the line");
+        assert_eq!(steps.len(), 1);
+        assert_eq!((steps[0].rotation, steps[0].x), (0, 0));
+    }
+
+    #[test]
+    fn gives_up_above_max_pc_height() {
+        let lines = vec![LEFT_OPEN; MAX_PC_HEIGHT + 1];
+        let queue = vec![i_piece(); 10];
+        assert!(find_pc(lines, &queue, &AtomicBool::new(false)).is_none());
+    }
+
+    #[test]
+    fn drop_piece_clears_full_lines() {
+        assert_eq!(drop_piece(&[LEFT_OPEN, 0], &I_FLAT, 0), Some(vec![0]));
+        assert_eq!(drop_piece(&[LEFT_OPEN, 0], &I_FLAT, 4), Some(vec![LEFT_OPEN, 0b1111 << 4]));
+    }
+
+    #[test]
+    fn fillable_needs_areas_of_whole_pieces() {
+        assert!(fillable(&[LEFT_OPEN]));
+        assert!(!fillable(&[LEFT_OPEN | 1]));
+    }
+}
@@ -1,162 +1,198 @@
 use amethyst::{
-    assets::{AssetStorage, Loader },
     ecs::{Component, DenseVecStorage},
+    core::math::Vector3,
     core::timing::Time,
     core::transform::Transform,
     core::SystemDesc,
     derive::SystemDesc,
-    input::{get_key, is_close_requested, is_key_down, VirtualKeyCode},
+    input::{is_close_requested, is_key_down, VirtualKeyCode},
+    winit::{Event, WindowEvent},
     input::{InputHandler, StringBindings},
     prelude::*,
     ecs::prelude::{Join, Read, Write, Entity, Entities, System, SystemData, World, ReadStorage, WriteStorage},
-    renderer::{Camera, ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat, Texture},
+    renderer::{Camera, SpriteRender},
+    renderer::{palette::Srgba, resources::Tint, transparent::Transparent},
+    shrev::{EventChannel, ReaderId},
+    utils::application_root_dir,
     window::ScreenDimensions,
 };
 
-use rand::{ Rng, seq::SliceRandom };
-
-use std::iter::FromIterator;
-
-use log::info;
+use rand::{ Rng, SeedableRng, distributions::{Distribution, WeightedIndex}, seq::SliceRandom };
+use rand_chacha::ChaCha8Rng;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use log::{error, info, warn};
+
+use crate::bot::UpcomingPieces;
+use crate::bests::{PersonalBest, PersonalBests};
+use crate::challenge::ChallengeResults;
+use crate::conflicts::check_bindings;
+use crate::devices::DeviceSelectState;
+use crate::display::{camera_projection, playfield_size};
+use crate::effects::{tape_lines, BlockSprites, SpawnSlide, OUTLINE_SPRITE};
+use crate::gamelog::GameLogFile;
+use crate::hud::{create_hud, show_popup, HudLines};
+use crate::level_select::{LevelSelectState, StartLevel};
+use crate::locale::Locale;
+use crate::results::ResultsState;
+use crate::render3d::{self, Render3d};
+use crate::rhythm::BeatClock;
+use crate::events::GameEvent;
+use crate::finesse::{optimal_inputs, FinesseScore};
+use crate::mode::{GameMode, ModeRegistry};
+use crate::opener::OpenerScore;
+use crate::gamepad::GamepadLost;
+use crate::pause::{GamepadLostState, PauseState, Paused, QuitConfirmState};
+use crate::pieces::PieceShape;
+use crate::profile;
+use crate::rules::{GameRules, PausePolicy, Randomizer, DEFAULT_BOARD_WIDTH, MAX_BOARD_WIDTH};
+use crate::screenshot::render_snapshot;
+use crate::script::ActiveScript;
+use crate::save::{ResumeChoice, ResumePromptState, SavedGame, AUTOSAVE_INTERVAL};
+use crate::settings::{BothDirections, GameSettings, GhostStyle, Handling, PcHint};
+use crate::solver::{board_lines, PcSolver, QueuedPiece};
+use crate::stats::{SessionStats, STATS_DIR};
+use crate::theme::Theme;
+
+type Board = Vec<Vec<Option<Entity>>>;
+
+pub const BOARD_HEIGHT: usize = 24;
+
+pub const SPAWN_COORD: (usize, usize) = (4, 20);
+pub const LINES_PER_LEVEL: u32 = 10;
+
+const GHOST_Z: f32 = -0.1; // behind the falling piece once they overlap
+const TARGET_Z: f32 = -0.15; // behind the ghost
+const TARGET_ALPHA: f32 = 0.35;
+const QUICK_SAVE_KEYS: [VirtualKeyCode; 4] = [VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4];
+const QUICK_SAVE_POPUP_SECONDS: f32 = 1.5;
+const REWIND_KEY: VirtualKeyCode = VirtualKeyCode::Back;
+const REWIND_SECONDS: f32 = 5.; // kept to go back to
+const REWIND_INTERVAL: f32 = 0.25; // seconds between the moments kept
+const REWIND_SPEED: f32 = 2.; // times faster than the game went
+
+// drawn for the cells of the falling piece
+pub struct PieceBlock {
+    pub piece: Entity,
+}
 
-type Board = [[Option<Entity>; 10]; 24];
+impl Component for PieceBlock {
+    type Storage = DenseVecStorage<Self>;
+}
 
-pub struct PieceBlock {}
+pub struct GhostBlock {
+    pub player: usize, // whose piece it shows the landing of
+}
 
-impl Component for PieceBlock {
+impl Component for GhostBlock {
     type Storage = DenseVecStorage<Self>;
 }
 
-#[derive(Clone, Debug)]
+// cells of every rotation of a piece, in the order the piece turns through them
+pub type Rotations = &'static [&'static [(usize, usize)]];
+
+// by block_idx: I, L, rev_L, square, T, S, Z
+pub const TETROMINO_ROTATIONS: [Rotations; 7] = [
+    &[
+        &[(0, 0), (0, 1), (0, 2), (0, 3)],
+        &[(0, 0), (1, 0), (2, 0), (3, 0)],
+    ],
+    &[
+        &[(0, 0), (1, 0), (1, 1), (1, 2)],
+        &[(0, 1), (1, 1), (2, 1), (2, 0)],
+        &[(0, 0), (0, 1), (0, 2), (1, 2)],
+        &[(0, 0), (1, 0), (2, 0), (0, 1)],
+    ],
+    &[
+        &[(0, 0), (0, 1), (0, 2), (1, 0)],
+        &[(0, 0), (1, 0), (2, 0), (2, 1)],
+        &[(1, 0), (1, 1), (1, 2), (0, 2)],
+        &[(0, 0), (0, 1), (1, 1), (2, 1)],
+    ],
+    &[
+        &[(0, 0), (0, 1), (1, 0), (1, 1)],
+    ],
+    &[
+        &[(0, 1), (1, 1), (2, 1), (1, 0)],
+        &[(0, 0), (0, 1), (0, 2), (1, 1)],
+        &[(0, 0), (1, 0), (2, 0), (1, 1)],
+        &[(0, 1), (1, 0), (1, 1), (1, 2)],
+    ],
+    &[
+        &[(0, 0), (0, 1), (1, 1), (1, 2)],
+        &[(0, 1), (1, 1), (1, 0), (2, 0)],
+    ],
+    &[
+        &[(1, 0), (1, 1), (0, 1), (0, 2)],
+        &[(0, 0), (1, 0), (1, 1), (2, 1)],
+    ],
+];
+
+// block_idx of the tetrominoes in the order the generator first shuffles them, seeded games depend on it
+const TETROMINO_DEAL_ORDER: [usize; 7] = [0, 5, 6, 1, 2, 3, 4];
+
+#[derive(Clone, Copy, Debug)]
 pub struct Piece {
-    pub relative_coords: Vec<[(usize, usize); 4]>,
+    pub relative_coords: Rotations,
     pub idx: usize,
     pub coord: (usize, usize),
     pub time_since_drop: f32, // time in seconds since last drop
     pub base_time_to_drop: f32, // in blocks per second
     pub curr_time_to_drop: f32, // in blocks per second
-    pub block_idx: usize, // 0 to 6
+    pub block_idx: usize, // 0 to 6, or the position in the rules' piece set
+    pub time_resting: f32, // time in seconds the piece has been resting on the stack
+    pub lock_resets: u32, // moves and rotations that restarted the lock delay
+    pub rotated_last: bool, // whether the last successful move was a rotation, for t-spins
+    pub inputs: u32, // left, right and rotate presses since the piece spawned, for the finesse trainer
+    pub player: usize, // who steers it, 0 unless the rules have more players
+    pub dropped: [u32; 2], // rows soft and hard dropped, scored when it locks
 }
 
 impl Component for Piece {
     type Storage = DenseVecStorage<Self>;
 }
 
-fn make_piece_I(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
+fn make_piece(block_idx: usize, relative_coords: Rotations) -> Piece {
     Piece {
-        relative_coords: vec![
-            [(0, 0), (0, 1), (0, 2), (0, 3)],
-            [(0, 0), (1, 0), (2, 0), (3, 0)],
-        ],
+        relative_coords,
         idx: 0,
-        coord,
+        coord: (0, 0),
         time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 0,
+        base_time_to_drop: 0.,
+        curr_time_to_drop: 0.,
+        block_idx,
+        time_resting: 0.,
+        lock_resets: 0,
+        rotated_last: false,
+        inputs: 0,
+        player: 0,
+        dropped: [0, 0],
     }
 }
 
-fn make_piece_L(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(0, 0), (1, 0), (1, 1), (1, 2)],
-            [(0, 1), (1, 1), (2, 1), (2, 0)],
-            [(0, 0), (0, 1), (0, 2), (1, 2)],
-            [(0, 0), (1, 0), (2, 0), (0, 1)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 1,
-    }
+// a piece of a set from resources/pieces, its rotations are leaked once per generator
+// as sets are only known at runtime, a few hundred bytes a game
+fn make_shape_piece(block_idx: usize, shape: &PieceShape) -> Piece {
+    let rotations = shape.rotations()
+        .into_iter()
+        .map(|cells| &*Box::leak(cells.into_boxed_slice()))
+        .collect::<Vec<&'static [(usize, usize)]>>();
+    make_piece(block_idx, Box::leak(rotations.into_boxed_slice()))
 }
 
-fn make_piece_rev_L(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(0, 0), (0, 1), (0, 2), (1, 0)],
-            [(0, 0), (1, 0), (2, 0), (2, 1)],
-            [(1, 0), (1, 1), (1, 2), (0, 2)],
-            [(0, 0), (0, 1), (1, 1), (2, 1)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 2,
-    }
-}
-
-fn make_piece_square(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(0, 0), (0, 1), (1, 0), (1, 1)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 3,
-    }
-}
-
-fn make_piece_T(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(0, 1), (1, 1), (2, 1), (1, 0)],
-            [(0, 0), (0, 1), (0, 2), (1, 1)],
-            [(0, 0), (1, 0), (2, 0), (1, 1)],
-            [(0, 1), (1, 0), (1, 1), (1, 2)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 4,
-    }
-}
-
-fn make_piece_S(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(0, 0), (0, 1), (1, 1), (1, 2)],
-            [(0, 1), (1, 1), (1, 0), (2, 0)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 5,
-    }
-}
-
-fn make_piece_Z(coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-    Piece {
-        relative_coords: vec![
-            [(1, 0), (1, 1), (0, 1), (0, 2)],
-            [(0, 0), (1, 0), (1, 1), (2, 1)],
-        ],
-        idx: 0,
-        coord,
-        time_since_drop: 0.,
-        base_time_to_drop: 1./blocks_per_second_drop_speed,
-        curr_time_to_drop: 1./blocks_per_second_drop_speed,
-        block_idx: 6,
-    }
+// columns in the middle of the player's share of the board, rounded to the left like on the standard board
+pub fn spawn_coord(board_width: usize, player: usize, players: usize) -> (usize, usize) {
+    let share = board_width / players.max(1);
+    (share * player + share / 2 - 1, SPAWN_COORD.1)
 }
 
 fn has_collision(piece: &Piece, board: &Board) -> bool {
     for &(x, y) in piece.relative_coords[piece.idx].iter() {
         let abs_x = piece.coord.0 + x;
-        let abs_y = piece.coord.1 as i64 - y as i64;
-        if abs_x >= 10 || abs_y < 0 || board[abs_y as usize][abs_x] != None {
+        let abs_y = piece.coord.1 + y;
+        if abs_y >= board.len() || abs_x >= board[abs_y].len() || board[abs_y][abs_x] != None {
             return true;
         }
     }
@@ -164,111 +200,282 @@ fn has_collision(piece: &Piece, board: &Board) -> bool {
 }
 
 impl Piece {
-    // todo next and prev with bound checks and possible reverse
-    fn next(&mut self, board: &Board)  {
+    // rotates at the first kick offset the piece fits at, or not at all
+    fn next(&mut self, board: &Board, kicks: &[(i32, i32)]) {
         // backwards feels better
-        let prev_idx = self.idx;
-        self.idx = (self.idx + 3) % self.relative_coords.len();
-        
-        if has_collision(&self, &board) {
-            // try again with left, right, up and down (all combinations?)
-            self.idx = prev_idx;
+        let (prev_idx, prev_coord) = (self.idx, self.coord);
+        self.idx = (self.idx + self.relative_coords.len() - 1) % self.relative_coords.len();
+
+        for &(dx, dy) in kicks {
+            let (x, y) = (prev_coord.0 as i32 + dx, prev_coord.1 as i32 + dy);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            self.coord = (x as usize, y as usize);
+            if !has_collision(&self, &board) {
+                self.rotated_last = true;
+                return;
+            }
         }
+        self.idx = prev_idx;
+        self.coord = prev_coord;
     }
 
-    fn get_abs(&self) -> Vec<(usize, usize)> {
+    // a successful move or rotation on the stack restarts the lock delay, as often as the rules allow
+    fn reset_lock(&mut self, lock_resets: u32) {
+        if self.time_resting > 0. && self.lock_resets < lock_resets {
+            self.time_resting = 0.;
+            self.lock_resets += 1;
+        }
+    }
+
+    pub fn get_abs(&self) -> Vec<(usize, usize)> {
         self.relative_coords[self.idx].iter().map(|&(lX, lY)| (lX + self.coord.0, lY + self.coord.1)).collect()
     }
 
+    fn drop_distance(&self, gameboard: &Gameboard) -> usize {
+        let blocks = self.get_abs();
+        let mut distance = 0;
+        while !gameboard.can_settle(&blocks.iter().map(|&(x, y)| (x, y - distance)).collect()) {
+            distance += 1;
+        }
+        distance
+    }
+
+    fn landing_coords(&self, gameboard: &Gameboard) -> Vec<(usize, usize)> {
+        let distance = self.drop_distance(gameboard);
+        self.get_abs().into_iter().map(|(x, y)| (x, y - distance)).collect()
+    }
+
     fn move_down(&mut self, board: &Board) {
-        if self.coord.1 != 0 {
-            self.coord.1 -= 1;
+        if self.coord.1 == 0 {
+            return;
         }
+        self.coord.1 -= 1;
         if has_collision(&self, &board) {
-            // self.coord.1 += 1;
+            self.coord.1 += 1;
         }
+        else {
+            self.rotated_last = false;
+        }
+    }
+
+    // the center of a T has a neighbour on three sides
+    fn is_t_spin(&self, board: &Board) -> bool {
+        if self.block_idx != 4 || !self.rotated_last {
+            return false;
+        }
+
+        let blocks = self.get_abs();
+        let is_neighbour = |&(ax, ay): &(usize, usize), &(bx, by): &(usize, usize)| {
+            (ax as i64 - bx as i64).abs() + (ay as i64 - by as i64).abs() == 1
+        };
+        let center = match blocks.iter().find(|&a| blocks.iter().filter(|&b| is_neighbour(a, b)).count() == 3) {
+            Some(&center) => center,
+            None => return false,
+        };
+
+        let occupied_corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|&&(dx, dy)| {
+                let x = center.0 as i64 + dx;
+                let y = center.1 as i64 + dy;
+                x < 0 || x >= board[0].len() as i64 || y < 0 || (y < board.len() as i64 && board[y as usize][x as usize] != None)
+            })
+            .count();
+        occupied_corners >= 3
     }
 }
 
+#[derive(Clone)]
 pub struct PieceGenerator {
-    current: Vec<Piece>,
-    next_pieces: Vec<Piece>,
-    options: [Piece; 7],
+    current: VecDeque<usize>, // block_idx of the pieces left in the bag being dealt
+    next_pieces: VecDeque<usize>,
+    options: Vec<Piece>, // every piece of the set by block_idx, only copied for spawns and previews
+    order: Vec<usize>, // block_idx of the set in the order the last shuffle left them
+    randomizer: Randomizer,
+    rng: ChaCha8Rng, // chacha gives the same sequence for a seed on every platform
+    script: Vec<usize>, // block_idx of the scripted pieces still to be dealt
+    bags: u32, // bags drawn so far, to fast forward a seeded generator when resuming
 }
 
 impl PieceGenerator {
-    fn new() -> Self {
-        let mut optionsInput =  [
-            make_piece_I((0, 0), 0.),
-            make_piece_S((0, 0), 0.),
-            make_piece_Z((0, 0), 0.),
-            make_piece_L((0, 0), 0.),
-            make_piece_rev_L((0, 0), 0.),
-            make_piece_square((0, 0), 0.),
-            make_piece_T((0, 0), 0.),            
-        ];
-        let options = optionsInput.clone();
-        optionsInput.shuffle(&mut rand::thread_rng());
-        let current = Vec::from_iter(optionsInput.iter().cloned());
-        
-        optionsInput.shuffle(&mut rand::thread_rng());
-        let next_pieces = Vec::from_iter(optionsInput.iter().cloned());
+    fn new(rules: &GameRules) -> Self {
+        let (optionsInput, order) = match &rules.piece_set {
+            Some(set) => (
+                set.pieces.iter().enumerate().map(|(block_idx, shape)| make_shape_piece(block_idx, shape)).collect(),
+                (0..set.pieces.len()).collect(),
+            ),
+            None => (
+                TETROMINO_ROTATIONS.iter().enumerate().map(|(block_idx, &rotations)| make_piece(block_idx, rotations)).collect(),
+                TETROMINO_DEAL_ORDER.to_vec(),
+            ),
+        };
+        let mut generator = Self {
+            options: optionsInput,
+            order,
+            current: VecDeque::new(),
+            next_pieces: VecDeque::new(),
+            randomizer: rules.randomizer.clone(),
+            rng: match rules.seed {
+                Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+                None => ChaCha8Rng::from_entropy(),
+            },
+            script: rules.sequence.clone(),
+            bags: 0,
+        };
+        generator.current = generator.fill_bag();
+        generator.next_pieces = generator.fill_bag();
+        generator
+    }
 
+    // the next seven scripted pieces, once the script is used up either shuffled sets
+    // or independent draws depending on the randomizer
+    fn fill_bag(&mut self) -> VecDeque<usize> {
+        self.bags += 1;
+        if !self.script.is_empty() {
+            let count = self.script.len().min(self.order.len());
+            return self.script.drain(..count).collect();
+        }
+        match &self.randomizer {
+            Randomizer::Bag => {
+                self.order.shuffle(&mut self.rng);
+                self.order.iter().copied().collect()
+            },
+            Randomizer::Classic => {
+                let (order, rng) = (&self.order, &mut self.rng);
+                (0..order.len())
+                    .map(|_| order[rng.gen_range(0, order.len())])
+                    .collect()
+            },
+            Randomizer::Bags(sets) => {
+                let mut bag = self.order.repeat((*sets).max(1));
+                bag.shuffle(&mut self.rng);
+                bag.into_iter().collect()
+            },
+            Randomizer::Weighted(weights) => {
+                let weights = (0..self.order.len()).map(|block_idx| weights.get(block_idx).copied().unwrap_or(1)).collect::<Vec<_>>();
+                match WeightedIndex::new(&weights) {
+                    Ok(odds) => (0..self.order.len()).map(|_| odds.sample(&mut self.rng)).collect(),
+                    // all at 0, every piece as likely as the others
+                    Err(_) => {
+                        let (order, rng) = (&self.order, &mut self.rng);
+                        (0..order.len())
+                            .map(|_| order[rng.gen_range(0, order.len())])
+                            .collect()
+                    },
+                }
+            },
+        }
+    }
 
-        Self {
-            options,
-            current,
-            next_pieces
+    // continues a saved queue, falls back to fresh bags if it can't be continued
+    // seeded and scripted generators are replayed up to where the saved game left off
+    fn from_queue(rules: &GameRules, bags: u32, current: &[usize], next_pieces: &[usize]) -> Self {
+        let mut generator = Self::new(rules);
+        while generator.bags < bags {
+            generator.fill_bag();
         }
+        if !current.is_empty() && !next_pieces.is_empty() {
+            generator.current = current.iter().copied().collect();
+            generator.next_pieces = next_pieces.iter().copied().collect();
+        }
+        generator
     }
 
-    fn peek(&self) -> Piece {
-        self.current[0].clone()
+    // a fresh piece of the set by block_idx
+    fn make(&self, block_idx: usize, coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
+        let mut piece = *self.options.get(block_idx).unwrap_or(&self.options[0]);
+        piece.coord = coord;
+        piece.base_time_to_drop = 1./blocks_per_second_drop_speed;
+        piece.curr_time_to_drop = 1./blocks_per_second_drop_speed;
+        piece
     }
 
-    fn next(&mut self, coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
-        let mut out = if self.current.len() == 1 {
-            let piece = self.current[0].clone();
-            self.options.shuffle(&mut rand::thread_rng());
+    fn bags(&self) -> u32 {
+        self.bags
+    }
 
-            std::mem::swap(&mut self.current, &mut self.next_pieces);
-            self.next_pieces = Vec::from_iter(self.options.iter().cloned());
+    fn queue(&self) -> (Vec<usize>, Vec<usize>) {
+        (
+            self.current.iter().copied().collect(),
+            self.next_pieces.iter().copied().collect(),
+        )
+    }
 
-            piece
-        } else {
-            self.current.remove(0)
-        };
+    // looks past the end of the current bag into the next one
+    fn peek_n(&self, count: usize) -> Vec<Piece> {
+        self.current
+            .iter()
+            .chain(self.next_pieces.iter())
+            .take(count)
+            .map(|&block_idx| self.make(block_idx, (0, 0), 0.))
+            .collect()
+    }
 
-        out.coord = coord;
-        out.base_time_to_drop = 1./blocks_per_second_drop_speed;
-        out.curr_time_to_drop = 1./blocks_per_second_drop_speed;
-        out
+    fn next(&mut self, coord: (usize, usize), blocks_per_second_drop_speed: f32) -> Piece {
+        let block_idx = self.current.pop_front().unwrap_or(0);
+        if self.current.is_empty() {
+            std::mem::swap(&mut self.current, &mut self.next_pieces);
+            self.next_pieces = self.fill_bag();
+        }
+        self.make(block_idx, coord, blocks_per_second_drop_speed)
     }
 }
 
+// block_idx of the first pieces a game with these rules deals, only repeatable with a seed
+pub fn dealt_pieces(rules: &GameRules, count: usize) -> Vec<usize> {
+    let mut generator = PieceGenerator::new(rules);
+    (0..count).map(|_| generator.next((0, 0), 1.).block_idx).collect()
+}
+
 pub struct Block {
     pub coord: (usize, usize),
+    pub block_idx: usize,
+    moved: bool, // the Transform is behind the coord
 }
 
 impl Block {
-    fn new(x: usize, y: usize) -> Self {
+    fn new(x: usize, y: usize, block_idx: usize) -> Self {
         Self {
             coord: (x, y),
+            block_idx,
+            moved: true,
+        }
+    }
+
+    fn move_to(&mut self, coord: (usize, usize)) {
+        if self.coord != coord {
+            self.coord = coord;
+            self.moved = true;
         }
     }
 }
 
-fn coord_to_transform((x, y): (usize, usize)) -> Transform {
-    let block_dimension = 16; // figure out how to read this based on state
+pub fn coord_to_transform((x, y): (usize, usize), block_size: f32) -> Transform {
     let mut transform = Transform::default();
     transform.set_translation_xyz(
-        (block_dimension / 2 + x * block_dimension) as f32,
-        (block_dimension / 2 + y * block_dimension) as f32,
+        (x as f32 + 0.5) * block_size,
+        (y as f32 + 0.5) * block_size,
         0. 
     );
     transform
 }
 
+pub fn preview_transform(slot: usize, (x, y): (usize, usize), board_width: usize, block_size: f32) -> Transform {
+    let block_dimension = block_size / 2.; // previews are drawn at half size
+    let board_width = board_width as f32 * block_size;
+    let board_height = BOARD_HEIGHT as f32 * block_size;
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new(0.5, 0.5, 1.));
+    transform.set_translation_xyz(
+        board_width + block_dimension + (x as f32 + 0.5) * block_dimension,
+        board_height - (slot + 1) as f32 * 5. * block_dimension + (y as f32 + 0.5) * block_dimension,
+        0.
+    );
+    transform
+}
+
 // impl Default for Block {
 //     fn default() -> Self {
 //         Self::new()
@@ -279,17 +486,119 @@ impl Component for Block {
     type Storage = DenseVecStorage<Self>;
 }
 
+// entities of cleared blocks wait here for the next pieces instead of being deleted and created
+// again, they keep their Transform and Tint but lose the Block and the sprite so nothing draws them
+#[derive(Default)]
+pub struct BlockPool(Vec<Entity>);
+
+// a block entity from the pool, or a new one when the pool is empty
+fn spawn_block(world: &mut World, block: Block, transform: Transform, sprite: SpriteRender, tint: Tint) -> Entity {
+    let pooled = {
+        let entities = world.entities();
+        let mut pool = world.write_resource::<BlockPool>();
+        // the pool outlives deleted entities when a state clears the world
+        let mut pooled = None;
+        while let Some(entity) = pool.0.pop() {
+            if entities.is_alive(entity) {
+                pooled = Some(entity);
+                break;
+            }
+        }
+        pooled
+    };
+
+    match pooled {
+        Some(entity) => {
+            world.write_storage::<Block>().insert(entity, block).ok();
+            world.write_storage::<Transform>().insert(entity, transform).ok();
+            world.write_storage::<SpriteRender>().insert(entity, sprite).ok();
+            world.write_storage::<Tint>().insert(entity, tint).ok();
+            entity
+        },
+        None => world.create_entity()
+            .with(block)
+            .with(transform)
+            .with(sprite)
+            .with(tint)
+            .build(),
+    }
+}
+
+fn release_blocks(world: &mut World, released: Vec<Entity>) {
+    {
+        let mut blocks = world.write_storage::<Block>();
+        let mut piece_blocks = world.write_storage::<PieceBlock>();
+        let mut sprites = world.write_storage::<SpriteRender>();
+        for &entity in &released {
+            blocks.remove(entity);
+            piece_blocks.remove(entity);
+            sprites.remove(entity);
+        }
+    }
+    world.write_resource::<BlockPool>().0.extend(released);
+}
+
 
 pub struct Gameboard {
-    pub board: [[Option<Entity>; 10]; 24],
-    pub curr_piece: Option<Entity>,
+    pub board: Board, // bottom line first
+    curr_pieces: Vec<Option<Entity>>, // by player
     pub done_entities: Vec<Entity>,
+    pub last_lock_t_spin: bool,
+    pub piece_locked: bool, // a piece locked this frame, the line clearer counts the combo from it
+    pub blocked_out: bool, // a piece sent back to spawn found it filled, the game is over
 }
 
 impl Gameboard {
+    pub fn new(width: usize) -> Self {
+        Self {
+            board: vec![vec![None; width]; BOARD_HEIGHT],
+            curr_pieces: vec![],
+            done_entities: vec![],
+            last_lock_t_spin: false,
+            piece_locked: false,
+            blocked_out: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.board[0].len()
+    }
+
+    // rows up to the highest filled cell
+    pub fn stack_height(&self) -> usize {
+        self.board.iter().rposition(|line| line.iter().any(Option::is_some)).map_or(0, |y| y + 1)
+    }
+
+    pub fn curr_piece(&self, player: usize) -> Option<Entity> {
+        self.curr_pieces.get(player).and_then(|&piece| piece)
+    }
+
+    pub fn set_curr_piece(&mut self, player: usize, piece: Option<Entity>) {
+        if self.curr_pieces.len() <= player {
+            self.curr_pieces.resize(player + 1, None);
+        }
+        self.curr_pieces[player] = piece;
+    }
+
+    // the board with the cells of the other players' pieces filled, for moving a piece around them
+    pub fn blocked_by(&self, others: &[(Entity, Vec<(usize, usize)>)]) -> Gameboard {
+        let mut board = self.board.clone();
+        for (entity, cells) in others {
+            for &(x, y) in cells {
+                if y < board.len() && x < board[y].len() {
+                    board[y][x] = Some(*entity);
+                }
+            }
+        }
+        Gameboard {
+            board,
+            ..Gameboard::new(self.width())
+        }
+    }
+
     pub fn can_place_blocks(&self, blocks: &Vec<(usize, usize)>) -> bool {
         for &(x, y) in blocks {
-            if x >= 10 || y >= 24 {
+            if x >= self.width() || y >= BOARD_HEIGHT {
                 return false;
             }
 
@@ -310,6 +619,15 @@ impl Gameboard {
         self.board[coord.1][coord.0] = Some(entity);
     }
 
+    // empty cells directly below the blocks that they would cover up
+    pub fn holes_below(&self, blocks: &Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        blocks
+            .iter()
+            .filter(|&&(x, y)| y > 0 && self.board[y - 1][x] == None && !blocks.contains(&(x, y - 1)))
+            .map(|&(x, y)| (x, y - 1))
+            .collect()
+    }
+
     pub fn can_settle(&self, blocks: &Vec<(usize, usize)>) -> bool {
         for &(x, y) in blocks {
             if y == 0 || self.board[y - 1][x] != None {
@@ -320,15 +638,16 @@ impl Gameboard {
         return false;
     }
 
-    pub fn clear_lines(&mut self) -> Vec<(Entity, (usize, usize))> {
+    // returns the cleared lines and the new coordinates of every remaining entity
+    pub fn clear_lines(&mut self) -> (Vec<usize>, Vec<(Entity, (usize, usize))>) {
         let destroyed_lines = self.board
             .iter()
             .enumerate()
-            .filter_map(|(i, &line)| if line.iter().all(|&elem| elem != None) { Some(i) } else { None })
+            .filter_map(|(i, line)| if line.iter().all(|&elem| elem != None) { Some(i) } else { None })
             .collect::<Vec<usize>>();
         
         if destroyed_lines.len() == 0 {
-            return vec![];
+            return (vec![], vec![]);
         }
 
         let new_to_old_mapping = (0..self.board.len())
@@ -336,7 +655,7 @@ impl Gameboard {
             .enumerate()
             .collect::<Vec<(usize, usize)>>();
 
-        let board = self.board;
+        let board = self.board.clone();
 
         self.done_entities.extend(
             destroyed_lines
@@ -345,14 +664,15 @@ impl Gameboard {
         );
 
         for &(new_line, old_line) in &new_to_old_mapping {
-            self.board[new_line] = self.board[old_line];
+            self.board[new_line] = board[old_line].clone();
         }
 
-        for idx in new_to_old_mapping.len()..24 {
-            self.board[idx] = [None; 10];
+        let width = self.width();
+        for idx in new_to_old_mapping.len()..BOARD_HEIGHT {
+            self.board[idx] = vec![None; width];
         }
 
-        self.board
+        let remaining = self.board
             .iter()
             .enumerate()
             .flat_map(|(j, line)| line
@@ -361,59 +681,231 @@ impl Gameboard {
                                     .filter_map(|(i, &e)| e.map(|x| (i, x)))
                                     .map(move |(i, e)| (e, (i, j)))
             )
-            .collect()
+            .collect();
+
+        (destroyed_lines, remaining)
     }
 }
 
 impl Default for Gameboard { 
     fn default() -> Self {
-        Self {
-            board: [[None; 10]; 24],
-            curr_piece: None,
-            done_entities: vec![],
-        }
+        Self::new(DEFAULT_BOARD_WIDTH)
     }
 }
 
+#[derive(Clone, Default)]
+pub struct Score {
+    pub points: u32,
+    pub lines: u32,
+    pub combo: u32, // consecutive placements that cleared lines
+    pub best_combo: u32,
+    pub soft_dropped: u32, // cells pieces travelled with down held
+    pub hard_dropped: u32, // cells pieces travelled in hard drops
+}
+
+
+// scores the rows a piece was soft and hard dropped once it locks, by the rules' drop_scores
+#[derive(SystemDesc)]
+#[system_desc(name(DropScoreSystemDesc))]
+pub struct DropScoreSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+}
+
+impl DropScoreSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for DropScoreSystem {
+    type SystemData = (
+        Read<'s, EventChannel<GameEvent>>,
+        Write<'s, Score>,
+        Read<'s, GameRules>,
+    );
+
+    fn run(&mut self, (events, mut score, rules): Self::SystemData) {
+        for event in events.read(&mut self.reader_id) {
+            match event {
+                GameEvent::PieceLocked { dropped: [soft, hard], .. } => {
+                    score.soft_dropped += soft;
+                    score.hard_dropped += hard;
+                    score.points += soft * rules.drop_scores[0] + hard * rules.drop_scores[1];
+                },
+                _ => {},
+            }
+        }
+    }
+}
 
 #[derive(SystemDesc)]
 pub struct MovePieceSystem;
 
 impl<'s> System<'s> for MovePieceSystem {
     type SystemData = (
+        Entities<'s>,
         WriteStorage<'s, Piece>,
         Read<'s, Gameboard>,
         Read<'s, Time>,
+        Read<'s, GameRules>,
+        Read<'s, BeatClock>,
+        Write<'s, EventChannel<GameEvent>>,
     );
 
-    fn run(&mut self, (mut pieces, gameboard, time): Self::SystemData) {
+    fn run(&mut self, (entities, mut pieces, gameboard, time, rules, clock, mut events): Self::SystemData) {
         let seconds = time.delta_seconds();
-        for piece in (&mut pieces).join() {
+        let falling = piece_cells(&entities, &pieces);
+        for (entity, piece) in (&entities, &mut pieces).join() {
             piece.time_since_drop += seconds;
-            if piece.time_since_drop >= piece.curr_time_to_drop {
-                piece.move_down(&gameboard.board);
+            // on the beat a piece falls a row with every beat, unless it is soft dropped
+            let step = if rules.on_beat && piece.curr_time_to_drop >= piece.base_time_to_drop {
+                clock.ticked
+            }
+            else {
+                piece.time_since_drop >= piece.curr_time_to_drop
+            };
+            if step {
+                let (soft_dropping, row) = (piece.curr_time_to_drop < piece.base_time_to_drop, piece.coord.1);
+                // a piece above another player's waits for it to get out of the way
+                let others = falling.iter().filter(|(other, _)| *other != entity).cloned().collect::<Vec<_>>();
+                if others.is_empty() {
+                    piece.move_down(&gameboard.board);
+                }
+                else {
+                    piece.move_down(&gameboard.blocked_by(&others).board);
+                }
+                if soft_dropping && piece.coord.1 < row {
+                    piece.dropped[0] += (row - piece.coord.1) as u32;
+                    events.single_write(GameEvent::SoftDrop { distance: row - piece.coord.1, block_idx: piece.block_idx });
+                }
                 piece.time_since_drop %= piece.curr_time_to_drop;
             }
         }
     }
 }
 
+// cells of every falling piece
+fn piece_cells(entities: &Entities, pieces: &WriteStorage<Piece>) -> Vec<(Entity, Vec<(usize, usize)>)> {
+    (entities, pieces).join().map(|(entity, piece)| (entity, piece.get_abs())).collect()
+}
+
+// how far the controller is in charging the held direction, for the das indicator
+#[derive(Default)]
+pub struct DasCharge {
+    pub direction: i32, // -1 left, 1 right, 0 neither
+    pub das: f32, // 0 to 1, repeating at 1
+    pub arr: f32, // 0 to 1 towards the next repeated step
+}
+
 #[derive(SystemDesc)]
 pub struct PieceControllerSystem {
-    curr_move_cd: f32,
-    move_cd: f32,
+    player: usize, // steers the pieces of this player with the player's bindings
+    direction: i32, // held sideways direction, -1 left, 1 right, 0 neither
+    das_charge: f32, // seconds the direction has been held
+    arr_timer: f32, // seconds since the last repeated step
     curr_rotate_cd: f32,
-    rotate_cd: f32,
+    hard_drop_held: bool,
+    left_held: bool,
+    right_held: bool,
+    rotate_held: bool,
+    last_direction: i32, // the direction pressed last, -1 left, 1 right
+    piece: Option<Entity>, // the piece the charge was built on
+    buffered_direction: i32, // pressed while there was no piece, taken by the next one
+    buffered_rotation: bool,
 }
 
 impl PieceControllerSystem {
-    pub fn new() -> Self {
+    pub fn new(player: usize) -> Self {
         Self {
-            curr_move_cd: 0.,
-            move_cd: 0.08,
+            player,
+            direction: 0,
+            das_charge: 0.,
+            arr_timer: 0.,
             curr_rotate_cd: 0.,
-            rotate_cd: 0.2,
+            hard_drop_held: false,
+            left_held: false,
+            right_held: false,
+            rotate_held: false,
+            last_direction: 0,
+            piece: None,
+            buffered_direction: 0,
+            buffered_rotation: false,
+        }
+    }
+
+    // sideways steps to take this frame: one on the press, then repeats every move_repeat once
+    // the direction was held for das, as many as it takes to reach the wall if move_repeat is 0
+    fn side_steps(&mut self, direction: i32, new_piece: bool, delta: f32, handling: &Handling) -> usize {
+        if direction != self.direction {
+            self.direction = direction;
+            self.das_charge = 0.;
+            self.arr_timer = 0.;
+            return if direction != 0 { 1 } else { 0 };
+        }
+        if direction == 0 {
+            return 0;
+        }
+        if new_piece && !handling.preserve_das {
+            self.das_charge = 0.;
+            self.arr_timer = 0.;
         }
+
+        let was_charged = self.das_charge >= handling.das;
+        self.das_charge += delta;
+        if self.das_charge < handling.das {
+            return 0;
+        }
+        if !was_charged {
+            self.arr_timer = 0.;
+            return if handling.move_repeat <= 0. { MAX_BOARD_WIDTH } else { 1 };
+        }
+        if handling.move_repeat <= 0. {
+            return MAX_BOARD_WIDTH;
+        }
+        self.arr_timer += delta;
+        let steps = (self.arr_timer / handling.move_repeat) as usize;
+        self.arr_timer -= steps as f32 * handling.move_repeat;
+        steps
+    }
+}
+
+// drops the piece onto the stack, it locks on the next settle check
+pub fn hard_drop(piece: &mut Piece, gameboard: &Gameboard, lock_delay: f32, events: &mut EventChannel<GameEvent>) {
+    let distance = piece.drop_distance(gameboard);
+    piece.coord.1 -= distance;
+    piece.dropped[1] += distance as u32;
+    if distance > 0 {
+        piece.rotated_last = false;
+    }
+    piece.time_resting = lock_delay;
+    events.single_write(GameEvent::HardDrop {
+        blocks: piece.get_abs(),
+        distance,
+        block_idx: piece.block_idx,
+    });
+}
+
+// moves the piece a column left (-1) or right (1) if it fits, returns whether it moved
+fn step_sideways(piece: &mut Piece, direction: i32, gameboard: &Gameboard, rules: &GameRules) -> bool {
+    let prev = piece.coord.0;
+    piece.coord.0 = clamp(0, piece.coord.0 as i32 + direction, gameboard.width() as i32 - 1) as usize;
+    if piece.coord.0 == prev || !gameboard.can_place_blocks(&piece.get_abs()) {
+        piece.coord.0 = prev;
+        return false;
+    }
+    piece.rotated_last = false;
+    piece.reset_lock(rules.lock_resets);
+    true
+}
+
+// rotates with the kicks of the rotation system, counts the input if the piece turned
+fn rotate(piece: &mut Piece, gameboard: &Gameboard, rules: &GameRules) {
+    let prev_idx = piece.idx;
+    piece.next(&gameboard.board, rules.rotation_system.kicks());
+    if piece.idx != prev_idx {
+        piece.inputs += 1;
+        piece.reset_lock(rules.lock_resets);
     }
 }
 
@@ -431,62 +923,176 @@ fn clamp<T: PartialOrd> (min: T, val: T, max: T) -> T {
 
 impl<'s> System<'s> for PieceControllerSystem {
     type SystemData = (
+        Entities<'s>,
         WriteStorage<'s, Piece>,
         Read<'s, InputHandler<StringBindings>>,
         Read<'s, Gameboard>,
-        Read<'s, Time>
+        Read<'s, Time>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        Write<'s, EventChannel<GameEvent>>,
+        Read<'s, Paused>,
+        Write<'s, DasCharge>,
     );
 
-    fn run(&mut self, (mut pieces, input, gameboard, time): Self::SystemData) {
-        // this only works with ever having one piece
+    fn run(&mut self, (entities, mut pieces, input, gameboard, time, rules, settings, mut events, paused, mut das_charge): Self::SystemData) {
+        if paused.0 || self.player >= rules.players {
+            return;
+        }
+        let player = self.player;
+        let handling = &settings.player_handling(player);
+        let action_is_down = |action| settings.player_action_is_down(&input, player, action);
+
+        let hard_drop_down = action_is_down("hard_drop");
+        let hard_drop_pressed = hard_drop_down && !self.hard_drop_held;
+        self.hard_drop_held = hard_drop_down;
+
+        // a held key counts once however far it moves the piece
+        let left_down = action_is_down("left");
+        let right_down = action_is_down("right");
+        let (left_pressed, right_pressed) = (left_down && !self.left_held, right_down && !self.right_held);
+        let side_presses = left_pressed as u32 + right_pressed as u32;
+        self.left_held = left_down;
+        self.right_held = right_down;
+        let rotate_down = action_is_down("rotate");
+        let rotate_pressed = rotate_down && !self.rotate_held;
+        self.rotate_held = rotate_down;
+
+        match (left_pressed, right_pressed) {
+            (true, false) => self.last_direction = -1,
+            (false, true) => self.last_direction = 1,
+            _ => {},
+        }
+        let direction = match (left_down, right_down) {
+            (true, false) => -1,
+            (false, true) => 1,
+            (false, false) => 0,
+            (true, true) => match handling.both_directions {
+                BothDirections::Neutral => 0,
+                BothDirections::LastPressed => self.last_direction,
+                BothDirections::FirstPressed => -self.last_direction,
+            },
+        };
+
+        // between a lock and the next spawn there is no piece, presses wait for the next one
+        if !(&pieces).join().any(|piece| piece.player == player) {
+            if left_pressed || right_pressed {
+                self.buffered_direction = if right_pressed && !left_pressed { 1 } else { -1 };
+            }
+            self.buffered_rotation |= rotate_pressed;
+            return;
+        }
+
+        // the other players' pieces are in the way like the stack
+        let others = piece_cells(&entities, &pieces)
+            .into_iter()
+            .filter(|&(entity, _)| pieces.get(entity).map_or(false, |piece| piece.player != player))
+            .collect::<Vec<_>>();
+        let blocked;
+        let gameboard: &Gameboard = if others.is_empty() {
+            &gameboard
+        }
+        else {
+            blocked = gameboard.blocked_by(&others);
+            &blocked
+        };
+
         // rotate_cd behaves weirdly
-        for mut piece in (&mut pieces).join() {
-            if input.action_is_down("down").unwrap_or(false) {
-                piece.curr_time_to_drop = 0.2 * piece.base_time_to_drop;
+        for (entity, mut piece) in (&entities, &mut pieces).join().filter(|(_, piece)| piece.player == player) {
+            let new_piece = self.piece != Some(entity);
+            self.piece = Some(entity);
+            piece.inputs += side_presses;
+            if new_piece {
+                if self.buffered_rotation {
+                    rotate(&mut piece, gameboard, &rules);
+                    self.curr_rotate_cd = handling.rotate_repeat;
+                }
+                if self.buffered_direction != 0 {
+                    // still held it gets the step of a fresh press, let go it gets the step it missed
+                    self.direction = 0;
+                    if direction != self.buffered_direction {
+                        step_sideways(&mut piece, self.buffered_direction, gameboard, &rules);
+                        piece.inputs += 1;
+                    }
+                }
+                self.buffered_rotation = false;
+                self.buffered_direction = 0;
             }
-            else {
-                piece.curr_time_to_drop = piece.base_time_to_drop;
+            if hard_drop_pressed {
+                hard_drop(&mut piece, gameboard, rules.lock_delay, &mut events);
+                continue;
+            }
+
+            piece.curr_time_to_drop = piece.base_time_to_drop;
+            if action_is_down("down") {
+                match handling.soft_drop_factor() {
+                    Some(factor) => piece.curr_time_to_drop = factor * piece.base_time_to_drop,
+                    // a sonic drop, straight down without locking
+                    None => {
+                        let distance = piece.drop_distance(gameboard);
+                        if distance > 0 {
+                            piece.coord.1 -= distance;
+                            piece.dropped[0] += distance as u32;
+                            piece.rotated_last = false;
+                            events.single_write(GameEvent::SoftDrop { distance, block_idx: piece.block_idx });
+                        }
+                    },
+                }
             }
             
             if self.curr_rotate_cd == 0. {
-                if input.action_is_down("up").unwrap_or(false) {
-                    piece.next(&gameboard.board);
-                    self.curr_rotate_cd = self.rotate_cd;
+                if action_is_down("rotate") {
+                    rotate(&mut piece, gameboard, &rules);
+                    self.curr_rotate_cd = handling.rotate_repeat;
                 }
             }
             else {
                 self.curr_rotate_cd = f32::max(0., self.curr_rotate_cd - time.delta_seconds());
-                if !input.action_is_down("up").unwrap_or(false) {
+                if !action_is_down("rotate") {
                     self.curr_rotate_cd = 0.;
                 }
             }
             
 
-            if self.curr_move_cd == 0. {
-                let delta : i32 = match (input.action_is_down("left"), input.action_is_down("right")) {
-                    (Some(true), Some(false)) => -1,
-                    (Some(false), Some(true)) => 1,
-                    _ => 0,
-                };
-
-                if delta != 0 {
-                    self.curr_move_cd = self.move_cd;
-                }
-    
-                let prev = piece.coord.0;
-    
-                piece.coord.0 = clamp(0, piece.coord.0 as i32 + delta, 9) as usize;
-                if !gameboard.can_place_blocks(&piece.get_abs()) {
-                    piece.coord.0 = prev;
-                }
-            }
-            else {
-                self.curr_move_cd = clamp(0., self.curr_move_cd - time.delta_seconds(), self.move_cd);
-                if !input.action_is_down("left").unwrap_or(false) && !input.action_is_down("right").unwrap_or(false) {
-                    self.curr_move_cd = 0.;
+            for _ in 0..self.side_steps(direction, new_piece, time.delta_seconds(), handling) {
+                if !step_sideways(&mut piece, direction, gameboard, &rules) {
+                    break;
                 }
             }
         }
+
+        if player != 0 {
+            return;
+        }
+        *das_charge = DasCharge {
+            direction: self.direction,
+            das: if self.direction == 0 { 0. } else if handling.das > 0. { (self.das_charge / handling.das).min(1.) } else { 1. },
+            arr: if handling.move_repeat > 0. { (self.arr_timer / handling.move_repeat).min(1.) } else { 0. },
+        };
+    }
+}
+
+// H switches the handling preset, the choice is saved with the settings
+#[derive(Default, SystemDesc)]
+pub struct HandlingPresetSystem {
+    preset_held: bool,
+}
+
+impl<'s> System<'s> for HandlingPresetSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Write<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (input, mut settings): Self::SystemData) {
+        let preset_down = input.action_is_down("handling_preset").unwrap_or(false);
+        if preset_down && !self.preset_held {
+            settings.next_handling_preset();
+            settings.save();
+            let name = if settings.handling_preset.is_empty() { "handling" } else { settings.handling_preset.as_str() };
+            info!("Handling preset {}: {:?}", name, settings.handling());
+        }
+        self.preset_held = preset_down;
     }
 }
 
@@ -500,22 +1106,77 @@ impl<'s> System<'s> for BoardSettlerSystem {
         WriteStorage<'s, PieceBlock>,
         ReadStorage<'s, Block>,
         Write<'s, Gameboard>,
+        Read<'s, GameRules>,
+        Read<'s, Time>,
+        Write<'s, EventChannel<GameEvent>>,
+        Write<'s, FinesseScore>,
+        Read<'s, BeatClock>,
     );
 
-    fn run(&mut self, (entities, mut pieces, mut piece_blocks, blocks, mut gameboard): Self::SystemData) {
+    fn run(&mut self, (entities, mut pieces, mut piece_blocks, blocks, mut gameboard, rules, time, mut events, mut finesse, clock): Self::SystemData) {
         let mut to_be_deleted = vec![];
-        for (entity, piece) in (&entities, &pieces).join() {
-            if gameboard.can_settle(&piece.get_abs()) {
+        for (entity, piece) in (&entities, &mut pieces).join() {
+            if !gameboard.can_settle(&piece.get_abs()) {
+                piece.time_resting = 0.;
+                continue;
+            }
+
+            piece.time_resting += time.delta_seconds();
+            // on the beat a piece whose lock delay ran out waits for the next beat
+            let locks = piece.time_resting >= rules.lock_delay && (!rules.on_beat || clock.ticked);
+            // finesse is counted in every mode for the results, only the finesse preset sends the piece back
+            let spawn = spawn_coord(rules.board_width, piece.player, rules.players);
+            let optimal = if locks && rules.board_width == DEFAULT_BOARD_WIDTH && rules.piece_set.is_none() {
+                optimal_inputs(piece, spawn.0, &gameboard)
+            }
+            else {
+                None
+            };
+            if let Some(optimal) = optimal {
+                finesse.pieces += 1;
+                if piece.inputs > optimal {
+                    finesse.faults += 1;
+                    if rules.finesse {
+                        info!("Finesse fault: {} inputs where {} do, {} faults in {} pieces", piece.inputs, optimal, finesse.faults, finesse.pieces);
+                        // back through the spawn a new piece would take, tops out like one if it's filled
+                        piece.coord = spawn;
+                        piece.idx = 0;
+                        rules.rotation_system.apply(piece);
+                        gameboard.blocked_out |= !gameboard.can_place_blocks(&piece.get_abs());
+                        piece.inputs = 0;
+                        piece.dropped = [0, 0];
+                        piece.time_resting = 0.;
+                        piece.lock_resets = 0;
+                        piece.time_since_drop = 0.;
+                        piece.rotated_last = false;
+                        continue;
+                    }
+                }
+            }
+            if locks {
+                gameboard.last_lock_t_spin = rules.piece_set.is_none() && piece.is_t_spin(&gameboard.board);
+                gameboard.piece_locked = true;
+                let holes = gameboard.holes_below(&piece.get_abs());
                 gameboard.place_blocks(&piece.get_abs().iter().map(|&abs| (entity, abs)).collect());
+                // a piece kicked up and dropped again doesn't score the rows twice, what a log can
+                // claim is checked against the same limit
+                let rows = spawn.1.saturating_sub(piece.coord.1) as u32;
+                let hard = piece.dropped[1].min(rows);
+                events.single_write(GameEvent::PieceLocked {
+                    blocks: piece.get_abs(),
+                    holes,
+                    block_idx: piece.block_idx,
+                    dropped: [piece.dropped[0].min(rows - hard), hard],
+                });
                 to_be_deleted.push(entity);
-                gameboard.curr_piece = None;
+                gameboard.set_curr_piece(piece.player, None);
             }
         }
 
         for &e in &to_be_deleted {
             pieces.remove(e);
 
-            let entities_tbr = (&entities, &mut piece_blocks).join().map(|(e, _)| e).collect::<Vec<Entity>>();
+            let entities_tbr = (&entities, &piece_blocks).join().filter(|(_, piece_block)| piece_block.piece == e).map(|(e, _)| e).collect::<Vec<Entity>>();
             for e in entities_tbr {
                 if let Some(block) = blocks.get(e) {
                     gameboard.override_entity(e, block.coord);
@@ -533,14 +1194,54 @@ impl<'s> System<'s> for BoardLineClearerSystem {
     type SystemData = (
         Entities<'s>,
         WriteStorage<'s, Block>,
-        Write<'s, Gameboard>
+        Write<'s, Gameboard>,
+        Write<'s, Score>,
+        Read<'s, GameRules>,
+        Write<'s, EventChannel<GameEvent>>,
+        Read<'s, Locale>,
     );
 
-    fn run(&mut self, (entities, mut blocks, mut gameboard): Self::SystemData) {
-        let entity_map : std::collections::HashMap<Entity, (usize, usize)> = gameboard.clear_lines().into_iter().collect();
+    fn run(&mut self, (entities, mut blocks, mut gameboard, mut score, rules, mut events, locale): Self::SystemData) {
+        let (rows, remaining) = gameboard.clear_lines();
+        let lines_cleared = rows.len();
+        let level = rules.level(score.lines);
+        score.points += rules.line_score(lines_cleared, score.lines);
+        score.lines += lines_cleared as u32;
+
+        if gameboard.piece_locked {
+            gameboard.piece_locked = false;
+            if lines_cleared > 0 {
+                score.combo += 1;
+                score.best_combo = score.best_combo.max(score.combo);
+            }
+            else {
+                if score.combo > 1 {
+                    info!("{}", locale.text("combo", &[&score.combo, &score.best_combo]));
+                }
+                score.combo = 0;
+            }
+        }
+
+        let t_spin = gameboard.last_lock_t_spin;
+        gameboard.last_lock_t_spin = false;
+        if lines_cleared > 0 || t_spin {
+            events.single_write(GameEvent::LinesCleared {
+                lines: lines_cleared,
+                rows,
+                combo: score.combo,
+                t_spin,
+                perfect_clear: lines_cleared > 0 && remaining.is_empty(),
+            });
+        }
+        if rules.level(score.lines) > level {
+            info!("{}", locale.text("level_up", &[&rules.level(score.lines)]));
+            events.single_write(GameEvent::LevelUp { level: rules.level(score.lines) });
+        }
+
+        let entity_map : std::collections::HashMap<Entity, (usize, usize)> = remaining.into_iter().collect();
         for (entity, mut block) in (&entities, &mut blocks).join() {
             if let Some(&coord) = entity_map.get(&entity) {
-                block.coord = coord;
+                block.move_to(coord);
             }
         }
     }
@@ -551,17 +1252,45 @@ pub struct PieceSyncSystem;
 
 impl<'s> System<'s> for PieceSyncSystem {
     type SystemData = (
+        Entities<'s>,
         ReadStorage<'s, Piece>,
         ReadStorage<'s, PieceBlock>,
         WriteStorage<'s, Block>
     );
 
-    fn run(&mut self, (pieces, piece_blocks, mut blocks): Self::SystemData) {
-        for piece in (pieces).join() {
+    fn run(&mut self, (entities, pieces, piece_blocks, mut blocks): Self::SystemData) {
+        for (entity, piece) in (&entities, &pieces).join() {
             let coords = piece.get_abs();
-            for (idx, (pB, mut block)) in (&piece_blocks, &mut blocks).join().enumerate() {
-                if idx < 4 {
-                    block.coord = coords[idx];
+            let own_blocks = (&piece_blocks, &mut blocks).join().filter(|(pB, _)| pB.piece == entity);
+            for (idx, (_, block)) in own_blocks.enumerate() {
+                if idx < coords.len() {
+                    block.move_to(coords[idx]);
+                }
+            }
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+pub struct GhostSystem;
+
+impl<'s> System<'s> for GhostSystem {
+    type SystemData = (
+        ReadStorage<'s, Piece>,
+        ReadStorage<'s, GhostBlock>,
+        WriteStorage<'s, Transform>,
+        Read<'s, Gameboard>,
+        Read<'s, GameSettings>,
+    );
+
+    fn run(&mut self, (pieces, ghost_blocks, mut transforms, gameboard, settings): Self::SystemData) {
+        for piece in (pieces).join() {
+            let coords = piece.landing_coords(&gameboard);
+            let own_ghost = (&ghost_blocks, &mut transforms).join().filter(|(ghost, _)| ghost.player == piece.player);
+            for (idx, (_, transform)) in own_ghost.enumerate() {
+                if idx < coords.len() {
+                    transform.set_translation(*coord_to_transform(coords[idx], settings.block_size).translation());
+                    transform.set_translation_z(GHOST_Z);
                 }
             }
         }
@@ -571,32 +1300,556 @@ impl<'s> System<'s> for PieceSyncSystem {
 #[derive(SystemDesc)]
 pub struct BoardToRealTranslatorSystem;
 
+// only blocks that moved since the last frame get their Transform written
 impl<'s> System<'s> for BoardToRealTranslatorSystem {
     type SystemData = (
-        ReadStorage<'s, Block>,
+        WriteStorage<'s, Block>,
         WriteStorage<'s, Transform>,
+        Read<'s, GameSettings>,
     );
 
-    fn run(&mut self, (block, mut transform): Self::SystemData) {
-        for (block, transform) in (&block, &mut transform).join() {
-            transform.set_translation(*coord_to_transform(block.coord).translation());
+    fn run(&mut self, (mut block, mut transform, settings): Self::SystemData) {
+        for (block, transform) in (&mut block, &mut transform).join().filter(|(block, _)| block.moved) {
+            transform.set_translation(*coord_to_transform(block.coord, settings.block_size).translation());
+            block.moved = false;
         }
     }
 }
 
+// everything a game needs to go back to a moment, for quick saves and rewinding
+#[derive(Clone)]
+pub struct QuickSave {
+    board: Vec<Vec<Option<usize>>>,
+    generator: PieceGenerator, // with the pieces in play back in front of the queue
+    pieces_since_reset: u32,
+    score: Score,
+    stats: SessionStats,
+    finesse: FinesseScore,
+    opener: OpenerScore,
+}
+
 pub struct TetrisGameState {
-    pub settings: (u32,), // todo make this a proper thing - right now only block dimension
     pub pieceGenerator: PieceGenerator,
     pub sprites: Vec<SpriteRender>,
+    pub mode: Arc<dyn GameMode>,
+    pub rules: GameRules,
+    pub preview_entities: Vec<Entity>,
+    pub ghost_entities: Vec<Vec<Entity>>, // by player
+    pub target_entities: Vec<Entity>,
+    pub saved_game: Option<SavedGame>,
+    pub awaiting_resume: bool,
+    resumed: bool, // the player already chose to resume the saved game, no prompt
+    resume_in: Option<Arc<dyn GameMode>>, // the saved game's mode when it isn't the one asked for
+    switch_to: Option<Box<TetrisGameState>>, // the saved game resumed in its own mode
+    pub awaiting_devices: bool, // the players pick their keyboard or gamepad before the first piece
+    pub awaiting_level: bool, // the player picks the starting level before the first piece
+    choosing_level: bool, // until the level select is done
+    pub autosave_timer: f32,
+    pub pieces_since_reset: u32, // pieces spawned since the board was last built
+    pub hud_lines: u32, // lines cleared when the mode's hud was last logged
+    pub pauses: u32, // taken this game, limited by the rules' pause policy
+    pub quick_saves: Vec<Option<QuickSave>>, // by slot, practice games only
+    pub rewind: VecDeque<QuickSave>, // the last REWIND_SECONDS, oldest first
+    pub rewind_timer: f32,
+    pub went_back: bool, // rewound or quick loaded, the game no longer counts for a personal best
 }
 
-impl Default for TetrisGameState {
-    fn default() -> Self {
+impl TetrisGameState {
+    pub fn new(mode: Arc<dyn GameMode>) -> Self {
+        let rules = mode.rules();
         Self {
-            settings: (60,),
-            pieceGenerator: PieceGenerator::new(),
+            pieceGenerator: PieceGenerator::new(&rules),
             sprites: vec![],
+            mode,
+            rules,
+            preview_entities: vec![],
+            ghost_entities: vec![],
+            target_entities: vec![],
+            saved_game: None,
+            awaiting_resume: false,
+            resumed: false,
+            resume_in: None,
+            switch_to: None,
+            awaiting_devices: false,
+            awaiting_level: false,
+            choosing_level: false,
+            autosave_timer: 0.,
+            pieces_since_reset: 0,
+            hud_lines: 0,
+            pauses: 0,
+            quick_saves: vec![None; QUICK_SAVE_KEYS.len()],
+            rewind: VecDeque::new(),
+            rewind_timer: 0.,
+            went_back: false,
+        }
+    }
+
+    pub fn with_saved_game(mut self, saved_game: Option<SavedGame>) -> Self {
+        self.saved_game = saved_game;
+        self
+    }
+
+    // picks up the saved game without asking again, its mode is this state's
+    fn resuming(mut self, saved_game: SavedGame) -> Self {
+        self.saved_game = Some(saved_game);
+        self.resumed = true;
+        self
+    }
+
+    // block_idx of every cell, bottom line first
+    fn place_board(&self, world: &mut World, board: &[Vec<Option<usize>>]) {
+        let block_size = world.read_resource::<GameSettings>().block_size;
+        for (y, line) in board.iter().enumerate().take(BOARD_HEIGHT) {
+            for (x, cell) in line.iter().enumerate().take(self.rules.board_width) {
+                if let Some(block_idx) = *cell {
+                    let (sprite_idx, tint) = {
+                        let theme = world.read_resource::<Theme>();
+                        (theme.block_sprite(block_idx), theme.block_tint(block_idx, 1.))
+                    };
+                    let entity = spawn_block(world, Block::new(x, y, block_idx), coord_to_transform((x, y), block_size), self.sprites[sprite_idx].clone(), tint);
+                    world.write_resource::<Gameboard>().override_entity(entity, (x, y));
+                }
+            }
+        }
+    }
+
+    // puts the saved board on screen, the player decides whether to keep it in ResumePromptState
+    fn restore(&mut self, world: &mut World, saved: &SavedGame) {
+        self.place_board(world, &saved.board);
+
+        let mut rules = self.rules.clone();
+        rules.seed = saved.seed;
+        self.pieceGenerator = PieceGenerator::from_queue(&rules, saved.bags, &saved.current_bag, &saved.next_bag);
+        let mut score = world.write_resource::<Score>();
+        score.points = saved.points;
+        score.lines = saved.lines;
+    }
+
+    // removes every block and the pieces in play and puts the board down instead
+    fn replace_board(&self, world: &mut World, board: &[Vec<Option<usize>>]) {
+        let on_board = {
+            let entities = world.entities();
+            let blocks = world.read_storage::<Block>();
+            (&*entities, &blocks).join().map(|(e, _)| e).collect::<Vec<Entity>>()
+        };
+        release_blocks(world, on_board);
+        let in_play = (0..self.rules.players)
+            .filter_map(|player| world.read_resource::<Gameboard>().curr_piece(player))
+            .collect::<Vec<_>>();
+        world.delete_entities(&in_play).ok();
+        world.insert(Gameboard::new(self.rules.board_width));
+        self.place_board(world, board);
+    }
+
+    // removes every block and puts the starting board back
+    fn reset_board(&mut self, world: &mut World) {
+        self.replace_board(world, &self.rules.starting_board);
+        self.pieces_since_reset = 0;
+    }
+
+    fn capture(&self, world: &World) -> QuickSave {
+        let saved = self.snapshot(world);
+        let mut generator = self.pieceGenerator.clone();
+        generator.current = saved.current_bag.into_iter().collect();
+        QuickSave {
+            board: saved.board,
+            generator,
+            pieces_since_reset: self.pieces_since_reset,
+            score: world.read_resource::<Score>().clone(),
+            stats: world.read_resource::<SessionStats>().clone(),
+            finesse: world.read_resource::<FinesseScore>().clone(),
+            opener: world.read_resource::<OpenerScore>().clone(),
+        }
+    }
+
+    // the pieces in play go back to the queue, the next update deals them again
+    fn go_back(&mut self, world: &mut World, saved: QuickSave) {
+        self.went_back = true;
+        self.replace_board(world, &saved.board);
+        self.pieceGenerator = saved.generator;
+        self.pieces_since_reset = saved.pieces_since_reset;
+        self.hud_lines = saved.score.lines;
+        world.insert(saved.score);
+        world.insert(saved.stats);
+        world.insert(saved.finesse);
+        world.insert(saved.opener);
+    }
+
+    fn quick_save(&mut self, world: &World, slot: usize) {
+        self.quick_saves[slot] = Some(self.capture(world));
+        let text = world.read_resource::<Locale>().text("quick_saved", &[&(slot + 1)]);
+        info!("{}", text);
+        show_popup(world, text, Some(QUICK_SAVE_POPUP_SECONDS));
+    }
+
+    fn quick_load(&mut self, world: &mut World, slot: usize) {
+        let saved = match self.quick_saves[slot].clone() {
+            Some(saved) => saved,
+            None => {
+                info!("{}", world.read_resource::<Locale>().text("quick_slot_empty", &[&(slot + 1)]));
+                return;
+            },
+        };
+        self.go_back(world, saved);
+        let text = world.read_resource::<Locale>().text("quick_loaded", &[&(slot + 1)]);
+        info!("{}", text);
+        show_popup(world, text, Some(QUICK_SAVE_POPUP_SECONDS));
+    }
+
+    // practice and casual games, challenges go on as played and a rewound game sets no personal best
+    fn can_rewind(&self) -> bool {
+        self.rules.practice || (self.rules.pause == PausePolicy::Free && self.rules.challenge.is_none())
+    }
+
+    // keeps a moment every REWIND_INTERVAL, with the rewind key held goes back through them
+    fn update_rewind(&mut self, world: &mut World) {
+        if !self.can_rewind() {
+            return;
+        }
+        let held = world.read_resource::<InputHandler<StringBindings>>().key_is_down(REWIND_KEY);
+        let delta = world.read_resource::<Time>().delta_seconds();
+        self.rewind_timer += if held { delta * REWIND_SPEED } else { delta };
+        if self.rewind_timer < REWIND_INTERVAL {
+            return;
+        }
+        self.rewind_timer = 0.;
+
+        if !held {
+            self.rewind.push_back(self.capture(world));
+            if self.rewind.len() as f32 > REWIND_SECONDS / REWIND_INTERVAL {
+                self.rewind.pop_front();
+            }
+            return;
+        }
+        if let Some(saved) = self.rewind.pop_back() {
+            self.go_back(world, saved);
+            if world.read_resource::<GameSettings>().flashes() {
+                tape_lines(world, self.rules.board_width);
+            }
+        }
+    }
+
+    // a challenge counts towards the day or week it was started in
+    fn keep_restored(&mut self, world: &mut World, saved: SavedGame) {
+        self.rules.seed = saved.seed;
+        self.rules.challenge = saved.challenge;
+        self.rules.start_level = saved.start_level;
+        self.went_back = saved.went_back;
+        world.insert(self.rules.clone());
+    }
+
+    fn discard_restored(&mut self, world: &mut World) {
+        self.reset_board(world);
+        world.insert(Score::default());
+        world.insert(OpenerScore::default());
+        self.pieceGenerator = PieceGenerator::new(&self.rules);
+        SavedGame::remove();
+    }
+
+    // pauses as far as the rules allow, counting limited pauses
+    fn pause(&mut self, world: &World) -> SimpleTrans {
+        let locale = world.read_resource::<Locale>();
+        match self.rules.pause {
+            PausePolicy::Free => return Trans::Push(Box::new(PauseState::new(None))),
+            PausePolicy::Limited { pauses, seconds } if self.pauses < pauses => {
+                self.pauses += 1;
+                return Trans::Push(Box::new(PauseState::new(Some(seconds))));
+            },
+            PausePolicy::Limited { .. } => info!("{}", locale.text("no_pauses_left", &[&self.rules.name])),
+            PausePolicy::Off => info!("{}", locale.text("no_pause", &[&self.rules.name])),
+        }
+        Trans::None
+    }
+
+    fn snapshot(&self, world: &World) -> SavedGame {
+        let gameboard = world.read_resource::<Gameboard>();
+        let blocks = world.read_storage::<Block>();
+        let pieces = world.read_storage::<Piece>();
+        let score = world.read_resource::<Score>();
+
+        let board = gameboard.board
+            .iter()
+            .map(|line| line.iter().map(|cell| cell.and_then(|e| blocks.get(e)).map(|block| block.block_idx)).collect())
+            .collect();
+
+        let (mut current_bag, next_bag) = self.pieceGenerator.queue();
+        for player in (0..self.rules.players).rev() {
+            if let Some(piece) = gameboard.curr_piece(player).and_then(|e| pieces.get(e)) {
+                current_bag.insert(0, piece.block_idx);
+            }
+        }
+
+        SavedGame {
+            rules: self.rules.name.to_string(),
+            mode: self.mode.name().to_string(),
+            args: self.mode.args(),
+            board,
+            current_bag,
+            next_bag,
+            seed: self.rules.seed,
+            bags: self.pieceGenerator.bags(),
+            challenge: self.rules.challenge.clone(),
+            start_level: self.rules.start_level,
+            points: score.points,
+            lines: score.lines,
+            went_back: self.went_back,
+        }
+    }
+
+    fn end_game(&self, world: &World) {
+        info!("{}", world.read_resource::<Locale>().text("game_over", &[]));
+        SavedGame::remove();
+        let (points, lines) = {
+            let score = world.read_resource::<Score>();
+            (score.points, score.lines)
+        };
+        if let Some(challenge) = &self.rules.challenge {
+            ChallengeResults::record(challenge, points, lines);
         }
+        // a challenge counts towards the day or week, not the mode
+        else if let Some(mode) = self.mode.best_name(&self.rules) {
+            let time = if self.rules.is_won(lines) { Some(world.read_resource::<SessionStats>().elapsed) } else { None };
+            if self.went_back {
+                info!("{}", world.read_resource::<Locale>().text("no_best_went_back", &[]));
+            }
+            else if PersonalBests::record(PersonalBest { mode, points, lines, time, games: 0 }) {
+                info!("{}", world.read_resource::<Locale>().text("new_best", &[]));
+            }
+        }
+        world.write_resource::<EventChannel<GameEvent>>().single_write(GameEvent::GameOver {
+            rules: self.rules.name.to_string(),
+            seed: self.rules.seed,
+            points,
+            lines,
+        });
+        self.mode.results(world);
+        if !world.read_resource::<GameSettings>().export_stats {
+            return;
+        }
+
+        let stats = world.read_resource::<SessionStats>();
+        let finesse = world.read_resource::<FinesseScore>();
+        let base = match application_root_dir().and_then(|root| stats.export(&profile::path(&root, STATS_DIR), &finesse)) {
+            Ok(base) => base,
+            Err(e) => {
+                error!("Failed to write session statistics: {}", e);
+                return;
+            },
+        };
+        info!("Wrote session statistics to {:?}", base);
+
+        // the final board next to the statistics, drawn again like a screenshot
+        let cells = (&world.read_storage::<Block>(), &world.read_storage::<Tint>())
+            .join()
+            .map(|(block, tint)| (block.coord, tint.0))
+            .collect::<Vec<_>>();
+        let snapshot = render_snapshot(&cells, self.rules.board_width, points, lines);
+        let path = base.with_extension("png");
+        match snapshot.save(&path) {
+            Ok(()) => info!("Saved the final board to {:?}", path),
+            Err(e) => error!("Failed to save the final board: {}", e),
+        }
+    }
+
+    // deals the player's next piece, a transition once the game is won or over
+    fn spawn_piece(&mut self, world: &mut World, player: usize) -> Option<SimpleTrans> {
+        let (lines, combo) = {
+            let score = world.read_resource::<Score>();
+            (score.lines, score.combo)
+        };
+        if self.rules.combo_drill && self.pieces_since_reset > 0 && combo == 0 {
+            info!("Combo broken, rebuilding the board");
+            self.reset_board(world);
+        }
+        if lines != self.hud_lines {
+            self.hud_lines = lines;
+            let lines = self.mode.hud(world);
+            for line in &lines {
+                info!("{}", line);
+            }
+            world.insert(HudLines(lines));
+        }
+        if self.mode.is_won(world) {
+            info!("{}", world.read_resource::<Locale>().text("cleared_lines", &[&lines]));
+            self.end_game(world);
+            return Some(Trans::Switch(Box::new(ResultsState::new(self.mode.clone()))));
+        }
+
+        let spawn = spawn_coord(self.rules.board_width, player, self.rules.players);
+        // the spawn waits while another player's piece is in the way
+        let falling = {
+            let pieces = world.read_storage::<Piece>();
+            (&pieces).join().flat_map(|piece| piece.get_abs()).collect::<Vec<(usize, usize)>>()
+        };
+        if !falling.is_empty() {
+            if let Some(mut next) = self.pieceGenerator.peek_n(1).pop() {
+                next.coord = spawn;
+                self.rules.rotation_system.apply(&mut next);
+                if next.get_abs().iter().any(|cell| falling.contains(cell)) {
+                    return None;
+                }
+            }
+        }
+
+        let mut piece = self.pieceGenerator.next(spawn, self.rules.drop_speed(lines));
+        let scripted = world.write_resource::<ActiveScript>().0.as_mut().map(|script| script.on_spawn(piece.block_idx));
+        if let Some(block_idx) = scripted.filter(|&block_idx| block_idx != piece.block_idx) {
+            piece = self.pieceGenerator.make(block_idx, spawn, self.rules.drop_speed(lines));
+        }
+        self.rules.rotation_system.apply(&mut piece);
+        piece.player = player;
+        if !world.read_resource::<Gameboard>().can_place_blocks(&piece.get_abs()) {
+            self.end_game(world);
+            return Some(Trans::Switch(Box::new(ResultsState::new(self.mode.clone()))));
+        }
+
+        self.pieces_since_reset += 1;
+        let block_idx = piece.block_idx;
+        let cells = piece.relative_coords[piece.idx].len();
+        self.request_pc_hint(world, &piece);
+        world.write_resource::<EventChannel<GameEvent>>().single_write(GameEvent::PieceSpawned { block_idx });
+        let piece_entity = world.create_entity().with(piece).build();
+        world.write_resource::<Gameboard>().set_curr_piece(player, Some(piece_entity));
+
+        let (block_size, slide) = {
+            let settings = world.read_resource::<GameSettings>();
+            // out of the preview if there is one to come out of
+            (settings.block_size, !settings.reduced_motion && settings.preview_count().min(self.rules.preview_count) > 0)
+        };
+        let (sprite_idx, tint) = {
+            let theme = world.read_resource::<Theme>();
+            (theme.block_sprite(block_idx), theme.block_tint(block_idx, 1.))
+        };
+        for _ in 0..cells {
+            let entity = spawn_block(world, Block::new(spawn.0, spawn.1, block_idx), coord_to_transform(spawn, block_size), self.sprites[sprite_idx].clone(), tint.clone());
+            world.write_storage::<PieceBlock>().insert(entity, PieceBlock { piece: piece_entity }).ok();
+            if slide {
+                world.write_storage::<SpawnSlide>().insert(entity, SpawnSlide::default()).ok();
+            }
+        }
+
+        self.refresh_ghost(world, player, block_idx, cells);
+        self.refresh_target(world, block_idx);
+        self.refresh_preview(world);
+        None
+    }
+
+    fn refresh_ghost(&mut self, world: &mut World, player: usize, block_idx: usize, cells: usize) {
+        if self.ghost_entities.len() <= player {
+            self.ghost_entities.resize(player + 1, vec![]);
+        }
+        for e in self.ghost_entities[player].drain(..) {
+            world.delete_entity(e).ok();
+        }
+
+        let (sprite_idx, tint, block_size) = {
+            let settings = world.read_resource::<GameSettings>();
+            match settings.ghost_style {
+                GhostStyle::Off => return,
+                GhostStyle::Outline => (OUTLINE_SPRITE, Tint(Srgba::new(1., 1., 1., 1.)), settings.block_size),
+                GhostStyle::Translucent => {
+                    let theme = world.read_resource::<Theme>();
+                    (theme.block_sprite(block_idx), theme.block_tint(block_idx, settings.ghost_opacity()), settings.block_size)
+                },
+            }
+        };
+
+        for _ in 0..cells {
+            let mut transform = coord_to_transform(SPAWN_COORD, block_size);
+            transform.set_translation_z(GHOST_Z);
+            let entity = world.create_entity()
+                .with(GhostBlock { player })
+                .with(transform)
+                .with(self.sprites[sprite_idx].clone())
+                .with(tint.clone())
+                .with(Transparent)
+                .build();
+            self.ghost_entities[player].push(entity);
+        }
+    }
+
+    // faint outline of where the piece belongs in the opener being practiced
+    fn refresh_target(&mut self, world: &mut World, block_idx: usize) {
+        for e in self.target_entities.drain(..) {
+            world.delete_entity(e).ok();
+        }
+
+        let placed = world.read_resource::<OpenerScore>().placed;
+        let cells = match self.rules.targets.get(placed) {
+            Some(cells) => cells.clone(),
+            None => return,
+        };
+        let block_size = world.read_resource::<GameSettings>().block_size;
+        let tint = world.read_resource::<Theme>().block_tint(block_idx, TARGET_ALPHA);
+        for coord in cells {
+            let mut transform = coord_to_transform(coord, block_size);
+            transform.set_translation_z(TARGET_Z);
+            let entity = world.create_entity()
+                .with(transform)
+                .with(self.sprites[OUTLINE_SPRITE].clone())
+                .with(tint.clone())
+                .with(Transparent)
+                .build();
+            self.target_entities.push(entity);
+        }
+    }
+
+    // asks the solver whether the board can be cleared with the piece in play and the visible queue
+    fn request_pc_hint(&self, world: &World, current: &Piece) {
+        let (pc_hint, preview_count) = {
+            let settings = world.read_resource::<GameSettings>();
+            (settings.pc_hint, settings.preview_count().min(self.rules.preview_count))
+        };
+        // the solver works on bitmasks of the standard board
+        if !self.rules.practice || pc_hint == PcHint::Off || self.rules.board_width != DEFAULT_BOARD_WIDTH {
+            return;
+        }
+
+        let lines = board_lines(&world.read_resource::<Gameboard>());
+        let queue = std::iter::once(*current)
+            .chain(self.pieceGenerator.peek_n(preview_count).into_iter().map(|mut piece| {
+                self.rules.rotation_system.apply(&mut piece);
+                piece
+            }))
+            .map(|piece| QueuedPiece::new(piece.block_idx, piece.relative_coords))
+            .collect();
+        world.write_resource::<PcSolver>().solve(lines, queue);
+    }
+
+    fn refresh_preview(&mut self, world: &mut World) {
+        for e in self.preview_entities.drain(..) {
+            world.delete_entity(e).ok();
+        }
+
+        let (preview_count, block_size) = {
+            let settings = world.read_resource::<GameSettings>();
+            (settings.preview_count().min(self.rules.preview_count), settings.block_size)
+        };
+        let upcoming = self.pieceGenerator.peek_n(preview_count);
+        let mut queue = vec![];
+        for (slot, mut piece) in upcoming.into_iter().enumerate() {
+            self.rules.rotation_system.apply(&mut piece);
+            queue.push(QueuedPiece::new(piece.block_idx, piece.relative_coords));
+            for &coord in piece.relative_coords[piece.idx].iter() {
+                let (sprite_idx, tint) = {
+                    let theme = world.read_resource::<Theme>();
+                    (theme.block_sprite(piece.block_idx), theme.block_tint(piece.block_idx, 1.))
+                };
+                let entity = world.create_entity()
+                    .with(preview_transform(slot, coord, self.rules.board_width, block_size))
+                    .with(self.sprites[sprite_idx].clone())
+                    .with(tint)
+                    .build();
+                self.preview_entities.push(entity);
+            }
+        }
+        world.insert(UpcomingPieces(queue));
+    }
+}
+
+impl Default for TetrisGameState {
+    fn default() -> Self {
+        Self::new(ModeRegistry::default_mode())
     }
 }
 
@@ -607,7 +1860,18 @@ impl SimpleState for TetrisGameState {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let world = data.world;
 
-        world.insert(Gameboard::default());
+        world.insert(Gameboard::new(self.rules.board_width));
+        world.insert(BlockPool::default());
+        world.insert(Score::default());
+        world.insert(SessionStats::default());
+        world.insert(OpenerScore::default());
+        world.insert(FinesseScore::default());
+        world.insert(PcSolver::default());
+        world.insert(self.rules.clone());
+        world.insert(ActiveScript::default());
+        world.insert(GameLogFile::default());
+        world.insert(Paused::default());
+        self.mode.setup(world);
 
         // Get the screen dimensions so we can initialize the camera and
         // place our sprites correctly later. We'll clone this since we'll
@@ -615,120 +1879,199 @@ impl SimpleState for TetrisGameState {
         let dimensions = (*world.read_resource::<ScreenDimensions>()).clone();
 
         // Place the camera
-        init_camera(world, &dimensions);
+        let perspective = world.read_resource::<Render3d>().0;
+        init_camera(world, &dimensions, perspective);
+        create_hud(world);
+        check_bindings(world, self.rules.players);
+
+        // Sprites and sounds were loaded by the LoadingState
+        self.sprites = world.read_resource::<BlockSprites>().sprites.clone();
+
+        self.awaiting_devices = self.rules.players > 1;
+        // a game saved in a mode that's gone or can't be built again is dropped, not played in another
+        if let Some(saved) = &self.saved_game {
+            let saved_mode = world.read_resource::<ModeRegistry>().variant(saved.mode_name(), &saved.args);
+            match saved_mode {
+                Some(mode) if mode.name() != self.mode.name() || mode.args() != self.mode.args() => {
+                    info!("{}", world.read_resource::<Locale>().text("resume_other_mode", &[mode.name()]));
+                    self.resume_in = Some(mode);
+                },
+                Some(_) => {},
+                None => {
+                    warn!("Dropping the autosave, its mode {} isn't there anymore", saved.mode_name());
+                    SavedGame::remove();
+                    self.saved_game = None;
+                },
+            }
+        }
 
-        // Load our sprites and display them
-        self.sprites = load_sprites(world);
+        // a resumed game keeps the level it started at
+        self.awaiting_level = self.rules.level_select && self.saved_game.is_none();
+        match self.saved_game.clone() {
+            // the board of another mode's game is only put down once that mode is played
+            Some(_) if self.resume_in.is_some() => {
+                self.place_board(world, &self.rules.starting_board);
+                self.awaiting_resume = true;
+            },
+            Some(saved) => {
+                self.restore(world, &saved);
+                if self.resumed {
+                    self.saved_game = None;
+                    self.keep_restored(world, saved);
+                }
+                else {
+                    self.awaiting_resume = true;
+                }
+            },
+            None => self.place_board(world, &self.rules.starting_board),
+        }
     }
 
-    // fn handle_event(
-    //     &mut self,
-    //     mut _data: StateData<'_, GameData<'_, '_>>,
-    //     event: StateEvent,
-    // ) -> SimpleTrans {
-    //     if let StateEvent::Window(event) = &event {
-    //         // Check if the window should be closed
-    //         if is_close_requested(&event) || is_key_down(&event, VirtualKeyCode::Escape) {
-    //             return Trans::Quit;
-    //         }
-
-    //         // Listen to any key events
-    //         if let Some(event) = get_key(&event) {
-    //             info!("handling key event: {:?}", event);
-    //         }
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if self.choosing_level {
+            self.choosing_level = false;
+            self.rules.start_level = data.world.read_resource::<StartLevel>().0;
+            data.world.insert(self.rules.clone());
+            return;
+        }
+        let saved = match self.saved_game.take() {
+            Some(saved) => saved,
+            None => return,
+        };
 
-    //         // If you're looking for a more sophisticated event handling solution,
-    //         // including key bindings and gamepad support, please have a look at
-    //         // https://book.amethyst.rs/stable/pong-tutorial/pong-tutorial-03.html#capturing-user-input
-    //     }
+        let choice = *data.world.read_resource::<ResumeChoice>();
+        match choice {
+            ResumeChoice::Resume => match self.resume_in.take() {
+                Some(mode) => self.switch_to = Some(Box::new(TetrisGameState::new(mode).resuming(saved))),
+                None => self.keep_restored(data.world, saved),
+            },
+            // the mode asked for is played either way
+            ResumeChoice::Discard => {
+                if self.resume_in.take().is_some() {
+                    SavedGame::remove();
+                }
+                else {
+                    self.discard_restored(data.world);
+                }
+                self.awaiting_level = self.rules.level_select;
+            },
+        }
+    }
 
-    //     // Keep going
-    //     Trans::None
-    // }
+    fn handle_event(
+        &mut self,
+        data: StateData<'_, GameData<'_, '_>>,
+        event: StateEvent,
+    ) -> SimpleTrans {
+        if let StateEvent::Window(event) = &event {
+            // closing the window mid game asks first, a stray click shouldn't throw the game away
+            if is_close_requested(&event) {
+                return Trans::Push(Box::new(QuitConfirmState::default()));
+            }
+            if is_key_down(&event, VirtualKeyCode::P) || is_key_down(&event, VirtualKeyCode::Escape) {
+                return self.pause(data.world);
+            }
+            // F1 to F4 load a practice game's quick save, with shift they save to it
+            if let Some(slot) = QUICK_SAVE_KEYS.iter().position(|&key| is_key_down(&event, key)) {
+                if self.rules.practice {
+                    let shift = {
+                        let input = data.world.read_resource::<InputHandler<StringBindings>>();
+                        input.key_is_down(VirtualKeyCode::LShift) || input.key_is_down(VirtualKeyCode::RShift)
+                    };
+                    if shift {
+                        self.quick_save(data.world, slot);
+                    }
+                    else {
+                        self.quick_load(data.world, slot);
+                    }
+                }
+            }
+            // alt-tabbing away pauses as P would, modes that can't be paused keep running
+            if let Event::WindowEvent { event: WindowEvent::Focused(false), .. } = event {
+                if data.world.read_resource::<GameSettings>().pause_on_focus_loss && self.rules.pause != PausePolicy::Off {
+                    return self.pause(data.world);
+                }
+            }
+        }
+        Trans::None
+    }
 
     fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
-        if data.world.read_resource::<Gameboard>().curr_piece == None {
-            // Load our sprites and display them
-
-            let piece = self.pieceGenerator.next((4, 20), 5.);
-            let block_idx = piece.block_idx;
-            // falling block - to be set by something else at some point
-            data.world.write_resource::<Gameboard>().curr_piece = Some(
-                data.world.create_entity()
-                    .with(piece)
-                    .build()
-                );
-
-            for i in 0..4 {
-                data.world.create_entity()
-                    .with(PieceBlock {})
-                    .with(Block::new(4, 20 + i))
-                    .with(coord_to_transform((4, 20 + i)))
-                    .with(self.sprites[block_idx].clone())
-                    .build();
+        if let Some(next) = self.switch_to.take() {
+            data.world.delete_all();
+            return Trans::Switch(next);
+        }
+        if self.awaiting_resume {
+            self.awaiting_resume = false;
+            return Trans::Push(Box::new(ResumePromptState));
+        }
+        if self.awaiting_devices {
+            self.awaiting_devices = false;
+            return Trans::Push(Box::new(DeviceSelectState::new(self.rules.players)));
+        }
+        if self.awaiting_level {
+            self.awaiting_level = false;
+            self.choosing_level = true;
+            return Trans::Push(Box::new(LevelSelectState::default()));
+        }
+        // waits for the pad whatever the pause rules, losing it isn't the player's doing
+        if data.world.read_resource::<GamepadLost>().0.is_some() {
+            return Trans::Push(Box::new(GamepadLostState::default()));
+        }
+
+        self.update_rewind(data.world);
+
+        self.autosave_timer += data.world.read_resource::<Time>().delta_seconds();
+        if self.autosave_timer >= AUTOSAVE_INTERVAL {
+            self.autosave_timer = 0.;
+            self.snapshot(data.world).save();
+        }
+
+        if data.world.read_resource::<Gameboard>().blocked_out {
+            self.end_game(data.world);
+            return Trans::Switch(Box::new(ResultsState::new(self.mode.clone())));
+        }
+        for player in 0..self.rules.players {
+            if data.world.read_resource::<Gameboard>().curr_piece(player).is_some() {
+                continue;
+            }
+            if let Some(trans) = self.spawn_piece(data.world, player) {
+                return trans;
             }
         }
 
-        let mut to_be_deleted = vec![];
-        std::mem::swap(&mut to_be_deleted, &mut data.world.write_resource::<Gameboard>().done_entities);
-        for e in to_be_deleted {
-            data.world.delete_entity(e).ok();
+        let cleared = std::mem::replace(&mut data.world.write_resource::<Gameboard>().done_entities, vec![]);
+        if !cleared.is_empty() {
+            release_blocks(data.world, cleared);
         }
 
         Trans::None
     }
 }
 
-fn init_camera(world: &mut World, dimensions: &ScreenDimensions) {
-    // Center the camera in the middle of the screen, and let it cover
-    // the entire screen
-    let mut transform = Transform::default();
-    transform.set_translation_xyz(dimensions.width() * 0.5, dimensions.height() * 0.5, 1.);
+// the board editor passes perspective: false, so clicks land on the cell under the mouse
+pub fn init_camera(world: &mut World, dimensions: &ScreenDimensions, perspective: bool) -> Entity {
+    let settings = world.read_resource::<GameSettings>().clone();
+    let board_width = world.read_resource::<GameRules>().board_width;
+    let (playfield_width, playfield_height) = playfield_size(settings.block_size, board_width);
+
+    // Center the camera on the playfield, CameraFitSystem refits the
+    // projection whenever the window is resized
+    let transform = if perspective {
+        render3d::init_light(world);
+        render3d::camera_transform(playfield_width, playfield_height)
+    }
+    else {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(playfield_width * 0.5, playfield_height * 0.5, 1.);
+        transform
+    };
 
     world
         .create_entity()
-        .with(Camera::standard_2d(dimensions.width(), dimensions.height()))
+        .with(Camera::from(camera_projection(dimensions, &settings, board_width, perspective)))
         .with(transform)
-        .build();
-}
-
-fn load_sprites(world: &mut World) -> Vec<SpriteRender> {
-    // Load the texture for our sprites. We'll later need to
-    // add a handle to this texture to our `SpriteRender`s, so
-    // we need to keep a reference to it.
-    let texture_handle = {
-        let loader = world.read_resource::<Loader>();
-        let texture_storage = world.read_resource::<AssetStorage<Texture>>();
-        loader.load(
-            "sprites/blocks.png",
-            ImageFormat::default(),
-            (),
-            &texture_storage,
-        )
-    };
-
-    // Load the spritesheet definition file, which contains metadata on our
-    // spritesheet texture.
-    let sheet_handle = {
-        let loader = world.read_resource::<Loader>();
-        let sheet_storage = world.read_resource::<AssetStorage<SpriteSheet>>();
-        loader.load(
-            "sprites/blocks.ron",
-            SpriteSheetFormat(texture_handle),
-            (),
-            &sheet_storage,
-        )
-    };
-
-    // Create our sprite renders. Each will have a handle to the texture
-    // that it renders from. The handle is safe to clone, since it just
-    // references the asset.
-    (0..7)
-        .map(|i| SpriteRender {
-            sprite_sheet: sheet_handle.clone(),
-            sprite_number: i,
-        })
-        .collect()
+        .build()
 }
 
 // fn init_sprites(world: &mut World, sprites: &[SpriteRender], dimensions: &ScreenDimensions) {
@@ -0,0 +1,200 @@
+use amethyst::{
+    core::timing::Time,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+};
+
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::events::GameEvent;
+use crate::finesse::FinesseScore;
+use crate::rules::{GameRules, MAX_BOARD_WIDTH};
+use crate::settings::GameSettings;
+use crate::state::{Gameboard, BOARD_HEIGHT};
+
+pub const STATS_DIR: &str = "stats";
+
+pub const TRACKED_ACTIONS: [&str; 5] = ["left", "right", "rotate", "down", "hard_drop"];
+
+// indexed by block_idx
+pub const PIECE_NAMES: [&str; 7] = ["I", "L", "J", "O", "T", "S", "Z"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Placement {
+    pub piece: String,
+    pub time: f32, // seconds into the game the piece was locked at
+    pub duration: f32, // seconds the piece was in play
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ClearCounts {
+    pub singles: u32,
+    pub doubles: u32,
+    pub triples: u32,
+    pub tetrises: u32,
+    pub t_spins: u32,
+    pub perfect_clears: u32,
+    pub best_combo: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct SessionStats {
+    pub elapsed: f32,
+    pub placements: Vec<Placement>,
+    pub clears: ClearCounts,
+    pub inputs: BTreeMap<&'static str, u32>,
+    pub placement_heat: [[u32; MAX_BOARD_WIDTH]; BOARD_HEIGHT], // blocks placed per cell
+    pub hole_heat: [[u32; MAX_BOARD_WIDTH]; BOARD_HEIGHT], // holes created per cell
+    pub stack_heights: Vec<usize>, // highest the stack was in every second of the game
+    last_lock: f32,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    duration: f32,
+    pieces: usize,
+    pieces_per_second: f32,
+    placements: &'a [Placement],
+    pps_over_time: Vec<(f32, f32)>,
+    clears: &'a ClearCounts,
+    inputs: &'a BTreeMap<&'static str, u32>,
+    stack_heights: &'a [usize],
+    finesse: &'a FinesseScore,
+}
+
+impl SessionStats {
+    pub fn pieces_per_second(&self) -> f32 {
+        if self.elapsed > 0. { self.placements.len() as f32 / self.elapsed } else { 0. }
+    }
+
+    // (time, pieces per second up to that point) for every placement
+    pub fn pps_over_time(&self) -> Vec<(f32, f32)> {
+        self.placements
+            .iter()
+            .enumerate()
+            .map(|(i, placement)| (placement.time, (i + 1) as f32 / placement.time.max(0.001)))
+            .collect()
+    }
+
+    // writes a JSON report and a CSV of the placements, both named after the current time
+    pub fn export(&self, dir: &Path, finesse: &FinesseScore) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let base = dir.join(format!("session-{}", timestamp));
+
+        let report = Report {
+            duration: self.elapsed,
+            pieces: self.placements.len(),
+            pieces_per_second: self.pieces_per_second(),
+            placements: &self.placements,
+            pps_over_time: self.pps_over_time(),
+            clears: &self.clears,
+            inputs: &self.inputs,
+            stack_heights: &self.stack_heights,
+            finesse,
+        };
+        let json = serde_json::to_string_pretty(&report).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        fs::write(base.with_extension("json"), json)?;
+
+        let mut csv = String::from("index,piece,time,duration,pps\n");
+        for (i, (placement, (_, pps))) in self.placements.iter().zip(report.pps_over_time.iter()).enumerate() {
+            csv.push_str(&format!("{},{},{:.3},{:.3},{:.3}\n", i, placement.piece, placement.time, placement.duration, pps));
+        }
+        fs::write(base.with_extension("csv"), csv)?;
+
+        Ok(base)
+    }
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(StatsSystemDesc))]
+pub struct StatsSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<GameEvent>,
+    #[system_desc(skip)]
+    held: [bool; 5],
+}
+
+impl StatsSystem {
+    pub fn new(reader_id: ReaderId<GameEvent>) -> Self {
+        Self {
+            reader_id,
+            held: [false; 5],
+        }
+    }
+}
+
+impl<'s> System<'s> for StatsSystem {
+    type SystemData = (
+        Write<'s, SessionStats>,
+        Read<'s, EventChannel<GameEvent>>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+        Read<'s, GameRules>,
+        Read<'s, GameSettings>,
+        Read<'s, Gameboard>,
+    );
+
+    fn run(&mut self, (mut stats, events, input, time, rules, settings, gameboard): Self::SystemData) {
+        stats.elapsed += time.delta_seconds();
+
+        let (second, height) = (stats.elapsed as usize, gameboard.stack_height());
+        if stats.stack_heights.len() <= second {
+            stats.stack_heights.resize(second + 1, 0);
+        }
+        stats.stack_heights[second] = stats.stack_heights[second].max(height);
+
+        for (held, &action) in self.held.iter_mut().zip(TRACKED_ACTIONS.iter()) {
+            let down = settings.player_action_is_down(&input, 0, action);
+            if down && !*held {
+                *stats.inputs.entry(action).or_insert(0) += 1;
+            }
+            *held = down;
+        }
+
+        for event in events.read(&mut self.reader_id) {
+            match event {
+                GameEvent::PieceLocked { blocks, holes, block_idx, .. } => {
+                    for &(x, y) in blocks {
+                        stats.placement_heat[y][x] += 1;
+                    }
+                    for &(x, y) in holes {
+                        stats.hole_heat[y][x] += 1;
+                    }
+
+                    let time = stats.elapsed;
+                    let duration = time - stats.last_lock;
+                    stats.placements.push(Placement { piece: rules.piece_name(*block_idx), time, duration });
+                    stats.last_lock = time;
+                },
+                GameEvent::LinesCleared { lines, combo, t_spin, perfect_clear, .. } => {
+                    let clears = &mut stats.clears;
+                    clears.best_combo = clears.best_combo.max(*combo);
+                    match lines {
+                        1 => clears.singles += 1,
+                        2 => clears.doubles += 1,
+                        3 => clears.triples += 1,
+                        4 => clears.tetrises += 1,
+                        _ => {},
+                    }
+                    if *t_spin {
+                        clears.t_spins += 1;
+                    }
+                    if *perfect_clear {
+                        clears.perfect_clears += 1;
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use amethyst::config::Config;
+
+use log::{error, info};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::bests::{PersonalBests, PERSONAL_BESTS_PATH};
+use crate::challenge::{ChallengeResults, CHALLENGE_RESULTS_PATH};
+use crate::profile;
+use crate::settings::{BINDINGS_PATH, SETTINGS_PATH};
+
+// files where the newer copy replaces the other one
+const NEWEST_WINS: [&str; 2] = [SETTINGS_PATH, BINDINGS_PATH];
+// files where both copies are merged
+const MERGED: [&str; 2] = [CHALLENGE_RESULTS_PATH, PERSONAL_BESTS_PATH];
+
+// a profile keeps its files in sync_dir/<profile>, playing without one in sync_dir/default,
+// a folder shared with Syncthing, Dropbox or a network drive carries them to the other machines
+fn remote_path(sync_dir: &Path, relative: &str) -> PathBuf {
+    let file_name = Path::new(relative).file_name().map_or(relative.into(), |name| name.to_os_string());
+    sync_dir.join(profile::current().unwrap_or_else(|| "default".to_string())).join(file_name)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// the replaced file is kept next to it as <file>.bak in case the other machine's copy was the wrong one
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        fs::copy(to, to.with_extension("ron.bak"))?;
+    }
+    else if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(from, to).map(|_| ())
+}
+
+// on start, before the settings are read: a newer settings or bindings file in the sync folder
+// replaces the local one and the challenge and personal bests of both are merged
+pub fn pull(root: &Path, sync_dir: &Path) {
+    for &file in &NEWEST_WINS {
+        let (local, remote) = (profile::path(root, file), remote_path(sync_dir, file));
+        if !remote.exists() || same_contents(&local, &remote) {
+            continue;
+        }
+        if modified(&remote) > modified(&local) {
+            match replace(&remote, &local) {
+                Ok(()) => info!("Took the newer {} from {:?}", file, remote),
+                Err(e) => error!("Failed to take {} from {:?}: {}", file, remote, e),
+            }
+        }
+    }
+    merge_challenge_results(root, sync_dir);
+    merge_personal_bests(root, sync_dir);
+}
+
+// both sides may have played challenges since they were last synced, neither replaces the other
+fn merge_challenge_results(root: &Path, sync_dir: &Path) {
+    let (local, remote) = (profile::path(root, CHALLENGE_RESULTS_PATH), remote_path(sync_dir, CHALLENGE_RESULTS_PATH));
+    if !remote.exists() || same_contents(&local, &remote) {
+        return;
+    }
+    let theirs = match ChallengeResults::load_no_fallback(&remote) {
+        Ok(theirs) => theirs,
+        Err(e) => {
+            error!("Failed to load challenge results from {:?}: {}", remote, e);
+            return;
+        },
+    };
+    let mut results = ChallengeResults::load();
+    results.merge(theirs);
+    match results.write(&local) {
+        Ok(()) => info!("Merged challenge results from {:?}", remote),
+        Err(e) => error!("Failed to write merged challenge results: {}", e),
+    }
+}
+
+fn merge_personal_bests(root: &Path, sync_dir: &Path) {
+    let (local, remote) = (profile::path(root, PERSONAL_BESTS_PATH), remote_path(sync_dir, PERSONAL_BESTS_PATH));
+    if !remote.exists() || same_contents(&local, &remote) {
+        return;
+    }
+    let theirs = match PersonalBests::load_no_fallback(&remote) {
+        Ok(theirs) => theirs,
+        Err(e) => {
+            error!("Failed to load personal bests from {:?}: {}", remote, e);
+            return;
+        },
+    };
+    let mut bests = PersonalBests::load();
+    bests.merge(theirs);
+    match bests.write(&local) {
+        Ok(()) => info!("Merged personal bests from {:?}", remote),
+        Err(e) => error!("Failed to write merged personal bests: {}", e),
+    }
+}
+
+// on exit: local files that changed since they were last synced are copied to the sync folder
+pub fn push(root: &Path, sync_dir: &Path) {
+    merge_challenge_results(root, sync_dir);
+    merge_personal_bests(root, sync_dir);
+    for &file in NEWEST_WINS.iter().chain(&MERGED) {
+        let (local, remote) = (profile::path(root, file), remote_path(sync_dir, file));
+        if !local.exists() || same_contents(&local, &remote) {
+            continue;
+        }
+        // a newer copy from another machine waits for the next start to be pulled
+        if remote.exists() && modified(&remote) > modified(&local) && !MERGED.contains(&file) {
+            info!("Kept the newer {} in {:?}, it is taken on the next start", file, remote);
+            continue;
+        }
+        match replace(&local, &remote) {
+            Ok(()) => info!("Synced {} to {:?}", file, remote),
+            Err(e) => error!("Failed to sync {} to {:?}: {}", file, remote, e),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use amethyst::{
+    config::Config,
+    renderer::{palette::Srgba, resources::Tint},
+    utils::application_root_dir,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::GARBAGE_BLOCK;
+use crate::effects::{BLOCK_SPRITE, GARBAGE_SPRITE, PATTERN_SPRITES};
+
+pub const THEME_DIR: &str = "resources/themes";
+
+// how blocks are colored, blocks are drawn with one white sprite tinted by the palette
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    pub palette: Vec<(u8, u8, u8)>, // block colors in block_idx order: I, L, rev_L, square, T, S, Z
+    pub patterned: bool, // tell pieces apart by a pattern per piece instead of only by color
+    pub garbage: (u8, u8, u8), // blocks of practice boards that belong to no piece
+    pub crt: bool, // scanlines and darker, rounded edges over the window like an old screen
+    pub animation: Option<BlockAnimation>,
+    pub backgrounds: Vec<Vec<BackgroundLayer>>, // one per level, starting over after the last
+}
+
+// an image in resources/backgrounds stretched over the window, later layers in front of earlier ones
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackgroundLayer {
+    pub image: String,
+    #[serde(default)]
+    pub drift: f32, // how far it sways, as a fraction of the window, 0 for a still layer
+}
+
+// a block sheet of its own with every sprite of sprites/blocks.ron once per frame, frame after frame
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockAnimation {
+    pub sheet: String, // <sheet>.png and <sheet>.ron in resources/sprites
+    pub frames: usize,
+    pub fps: f32,
+}
+
+impl Theme {
+    // falls back to the built in palette if the theme can't be read
+    pub fn load_named(name: &str) -> Self {
+        match application_root_dir() {
+            Ok(root) => Self::load(root.join(THEME_DIR).join(format!("{}.ron", name))),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn block_sprite(&self, block_idx: usize) -> usize {
+        // pieces of larger sets reuse the seven patterns
+        if block_idx == GARBAGE_BLOCK {
+            GARBAGE_SPRITE
+        }
+        else if self.patterned {
+            PATTERN_SPRITES + block_idx % (GARBAGE_SPRITE - PATTERN_SPRITES)
+        }
+        else {
+            BLOCK_SPRITE
+        }
+    }
+
+    pub fn block_tint(&self, block_idx: usize, alpha: f32) -> Tint {
+        let (r, g, b) = if block_idx == GARBAGE_BLOCK {
+            self.garbage
+        }
+        else {
+            self.palette.get(block_idx % self.palette.len().max(1)).cloned().unwrap_or((255, 255, 255))
+        };
+        Tint(Srgba::new(f32::from(r) / 255., f32::from(g) / 255., f32::from(b) / 255., alpha))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            palette: vec![
+                (181, 230, 29),
+                (255, 242, 0),
+                (153, 217, 234),
+                (195, 195, 195),
+                (255, 174, 201),
+                (112, 146, 190),
+                (200, 191, 231),
+            ],
+            patterned: false,
+            garbage: (128, 128, 128),
+            crt: false,
+            animation: None,
+            backgrounds: vec![],
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use amethyst::ecs::prelude::{World, WorldExt};
+
+use log::info;
+
+use std::sync::Mutex;
+
+use crate::locale::Locale;
+use crate::mode::GameMode;
+use crate::rules::GameRules;
+use crate::state::Score;
+use crate::stats::SessionStats;
+
+pub const MAX_PLAYERS: usize = 8;
+
+// how a player's sprint went
+#[derive(Clone, Copy, Debug)]
+enum Run {
+    Finished(f32), // seconds to clear the lines
+    ToppedOut(u32), // lines cleared before the stack topped out
+}
+
+impl Run {
+    // a finished sprint beats a topped out one, then the faster time or the more lines win
+    fn beats(self, other: Run) -> bool {
+        match (self, other) {
+            (Run::Finished(time), Run::Finished(other)) => time < other,
+            (Run::Finished(_), Run::ToppedOut(_)) => true,
+            (Run::ToppedOut(_), Run::Finished(_)) => false,
+            (Run::ToppedOut(lines), Run::ToppedOut(other)) => lines > other,
+        }
+    }
+
+    fn describe(self, locale: &Locale) -> String {
+        match self {
+            Run::Finished(time) => format!("{}:{:04.1}", (time / 60.) as u32, time % 60.),
+            Run::ToppedOut(lines) => locale.text("tournament_topped_out", &[&lines]),
+        }
+    }
+}
+
+struct MatchResult {
+    players: (usize, Option<usize>), // the second is None for a bye
+    runs: (Option<Run>, Option<Run>),
+    winner: usize,
+}
+
+// single elimination, the players of a match play the same sprint one after the other
+struct Bracket {
+    names: Vec<String>,
+    round: Vec<usize>, // players still in, paired up in order, the last one gets a bye if odd
+    results: Vec<Vec<MatchResult>>, // every round played so far, the last one may be unfinished
+    first_run: Option<Run>, // of the match being played, while the second player is up
+    seed: u64, // both players of a match get the same pieces
+}
+
+impl Bracket {
+    fn new(names: Vec<String>) -> Self {
+        let mut bracket = Self {
+            round: (0..names.len()).collect(),
+            names,
+            results: vec![vec![]],
+            first_run: None,
+            seed: rand::random(),
+        };
+        bracket.skip_byes();
+        bracket
+    }
+
+    fn current_match(&self) -> Option<(usize, Option<usize>)> {
+        let played = self.results.last().map_or(0, Vec::len);
+        self.round.get(played * 2).map(|&first| (first, self.round.get(played * 2 + 1).copied()))
+    }
+
+    fn champion(&self) -> Option<usize> {
+        if self.round.len() == 1 { self.round.first().copied() } else { None }
+    }
+
+    fn player_up(&self) -> Option<usize> {
+        match self.current_match() {
+            Some((first, _)) if self.first_run.is_none() => Some(first),
+            Some((_, second)) => second,
+            None => None,
+        }
+    }
+
+    fn record(&mut self, run: Run) {
+        let (first, second) = match self.current_match() {
+            Some((first, Some(second))) => (first, second),
+            _ => return,
+        };
+        let first_run = match self.first_run.take() {
+            Some(first_run) => first_run,
+            None => {
+                self.first_run = Some(run);
+                return;
+            },
+        };
+
+        let winner = if run.beats(first_run) { second } else { first };
+        if let Some(round) = self.results.last_mut() {
+            round.push(MatchResult { players: (first, Some(second)), runs: (Some(first_run), Some(run)), winner });
+        }
+        self.seed = rand::random();
+        self.skip_byes();
+    }
+
+    // moves past byes and on to the next round once every match of this one is played
+    fn skip_byes(&mut self) {
+        loop {
+            match self.current_match() {
+                Some((first, None)) => {
+                    if let Some(round) = self.results.last_mut() {
+                        round.push(MatchResult { players: (first, None), runs: (None, None), winner: first });
+                    }
+                },
+                Some(_) => return,
+                None => {
+                    self.round = self.results.last().map_or(vec![], |round| round.iter().map(|result| result.winner).collect());
+                    if self.champion().is_some() {
+                        return;
+                    }
+                    self.results.push(vec![]);
+                },
+            }
+        }
+    }
+
+    fn log(&self, locale: &Locale) {
+        for (number, round) in self.results.iter().enumerate().filter(|(_, round)| !round.is_empty()) {
+            info!("{}", locale.text("tournament_round", &[&(number + 1)]));
+            for result in round {
+                let name = |player: usize| self.names[player].as_str();
+                match (result.players, result.runs) {
+                    ((first, Some(second)), (Some(first_run), Some(second_run))) => {
+                        let loser = if result.winner == first { second } else { first };
+                        info!("{}", locale.text("tournament_result", &[
+                            &name(first), &first_run.describe(locale), &name(second), &second_run.describe(locale), &name(result.winner), &name(loser),
+                        ]));
+                    },
+                    _ => info!("{}", locale.text("tournament_bye", &[&name(result.winner)])),
+                }
+            }
+        }
+        if let Some(champion) = self.champion() {
+            info!("{}", locale.text("tournament_champion", &[&self.names[champion]]));
+        }
+        else if let Some((first, Some(second))) = self.current_match() {
+            info!("{}", locale.text("tournament_next", &[&self.names[first], &self.names[second]]));
+        }
+    }
+}
+
+// hot seat sprints for 2 to 8 players named on the command line, R on the results plays the next game
+pub struct TournamentMode {
+    bracket: Mutex<Bracket>,
+}
+
+impl TournamentMode {
+    pub fn new(names: Vec<String>) -> Result<Self, String> {
+        if names.len() < 2 || names.len() > MAX_PLAYERS {
+            return Err(format!("A tournament is played by 2 to {} players, not {}", MAX_PLAYERS, names.len()));
+        }
+        Ok(Self { bracket: Mutex::new(Bracket::new(names)) })
+    }
+}
+
+impl GameMode for TournamentMode {
+    fn name(&self) -> &str {
+        "tournament"
+    }
+
+    // the players, a resumed game starts their bracket again
+    fn args(&self) -> Vec<String> {
+        self.bracket.lock().map(|bracket| bracket.names.clone()).unwrap_or_default()
+    }
+
+    // the games belong to the bracket's players, not whoever plays on this machine
+    fn best_name(&self, _rules: &GameRules) -> Option<String> {
+        None
+    }
+
+    fn rules(&self) -> GameRules {
+        let seed = self.bracket.lock().map(|bracket| bracket.seed).ok();
+        GameRules {
+            seed,
+            ..GameRules::sprint()
+        }
+    }
+
+    fn setup(&self, world: &mut World) {
+        let locale = world.read_resource::<Locale>();
+        let bracket = match self.bracket.lock() {
+            Ok(bracket) => bracket,
+            Err(_) => return,
+        };
+        if let (Some((first, Some(second))), Some(player)) = (bracket.current_match(), bracket.player_up()) {
+            info!("{}", locale.text("tournament_match", &[&bracket.names[first], &bracket.names[second], &bracket.names[player]]));
+        }
+    }
+
+    fn hud(&self, world: &World) -> Vec<String> {
+        match self.bracket.lock().ok().and_then(|bracket| bracket.player_up().map(|player| bracket.names[player].clone())) {
+            Some(name) => vec![world.read_resource::<Locale>().text("tournament_playing", &[&name])],
+            None => vec![],
+        }
+    }
+
+    fn results(&self, world: &World) {
+        let lines = world.read_resource::<Score>().lines;
+        let run = if world.read_resource::<GameRules>().is_won(lines) {
+            Run::Finished(world.read_resource::<SessionStats>().elapsed)
+        }
+        else {
+            Run::ToppedOut(lines)
+        };
+
+        let locale = world.read_resource::<Locale>();
+        let mut bracket = match self.bracket.lock() {
+            Ok(bracket) => bracket,
+            Err(_) => return,
+        };
+        if let Some(player) = bracket.player_up() {
+            info!("{}", locale.text("tournament_run", &[&bracket.names[player], &run.describe(&locale)]));
+        }
+        bracket.record(run);
+        bracket.log(&locale);
+        if bracket.champion().is_none() {
+            info!("{}", locale.text("tournament_next_game", &[]));
+        }
+        // started here rather than in setup, the game state asks for the rules and their seed
+        // before the mode is set up
+        else {
+            info!("{}", locale.text("tournament_restart", &[]));
+            let names = bracket.names.clone();
+            *bracket = Bracket::new(names);
+        }
+    }
+}